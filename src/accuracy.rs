@@ -0,0 +1,15 @@
+/// Trades emulation accuracy for speed.
+///
+/// - `Accurate`: every bus access the real NES performs is emulated, including the dummy reads
+///   the CPU makes when fetching operands. These matter when reading registers with side-effects,
+///   such as PPUSTATUS. Reading an address nothing responds to returns the last value on the bus,
+///   and the PPU's I/O latch decays to 0 if it isn't refreshed.
+/// - `Fast`: dummy reads still take a cycle, but don't touch the bus. Open bus reads return 0, and
+///   the PPU's I/O latch never decays.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccuracyProfile {
+    Fast,
+    #[default]
+    Accurate,
+}
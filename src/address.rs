@@ -4,6 +4,7 @@ use std::ops::AddAssign;
 use std::ops::Sub;
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Address(u16);
 
 impl Address {
@@ -0,0 +1,219 @@
+use bitflags::bitflags;
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// The delta modulation channel plays 1-bit delta-encoded samples read from CPU memory.
+pub struct DmcGenerator {
+    irq_enabled: bool,
+    loop_flag: bool,
+    // `timer` starts at `timer_initial` and counts down to 0.
+    // When it reaches 0, it is reloaded with `timer_initial` and the output unit is clocked.
+    timer_initial: u16,
+    timer: u16,
+    // 7-bit level, moved up or down by 2 for each bit of the sample.
+    output_level: u8,
+    // Registers for the start address and length of the sample, which are decoded on restart.
+    sample_address: u8,
+    sample_length: u8,
+    current_address: u16,
+    bytes_remaining: u16,
+    // The next sample byte, fetched by DMA when empty.
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    // Whether the shift register holds a sample byte. If not, the output level is held.
+    playing: bool,
+    interrupt: bool,
+}
+
+impl DmcGenerator {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.interrupt = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn write_flags(&mut self, value: u8) {
+        let flags = DmcFlags::from_bits_truncate(value);
+        self.irq_enabled = flags.contains(DmcFlags::IRQ_ENABLED);
+        self.loop_flag = flags.contains(DmcFlags::LOOP);
+        self.timer_initial = RATE[(flags & DmcFlags::RATE).bits() as usize] - 1;
+        if !self.irq_enabled {
+            self.interrupt = false;
+        }
+    }
+
+    pub fn write_direct_load(&mut self, value: u8) {
+        self.output_level = value & 0b0111_1111;
+    }
+
+    pub fn write_sample_address(&mut self, value: u8) {
+        self.sample_address = value;
+    }
+
+    pub fn write_sample_length(&mut self, value: u8) {
+        self.sample_length = value;
+    }
+
+    // Whether there are still bytes of the sample left to play
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+
+    // The address of the next sample byte, if the sample buffer needs refilling
+    pub fn sample_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.active() {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    // Fill the sample buffer with the byte read from `sample_request`
+    pub fn load_sample(&mut self, byte: u8) {
+        self.sample_buffer = Some(byte);
+        // Addresses wrap around to 0x8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.interrupt = true;
+            }
+        }
+    }
+
+    // High-frequency tick to control waveform generation
+    pub fn tick(&mut self) -> u8 {
+        let value = self.output_level;
+
+        if self.timer == 0 {
+            self.timer = self.timer_initial;
+            self.clock_output();
+        } else {
+            self.timer -= 1;
+        }
+
+        value
+    }
+
+    fn restart(&mut self) {
+        self.current_address = 0xc000 | (self.sample_address as u16) << 6;
+        self.bytes_remaining = ((self.sample_length as u16) << 4) + 1;
+    }
+
+    fn clock_output(&mut self) {
+        if self.playing {
+            if self.shift_register & 1 == 1 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining = self.bits_remaining.saturating_sub(1);
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.playing = true;
+                    self.shift_register = byte;
+                }
+                None => self.playing = false,
+            }
+        }
+    }
+}
+
+// Period in CPU cycles for each rate index
+const RATE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+bitflags! {
+    #[derive(Copy, Clone)]
+    struct DmcFlags: u8 {
+        const IRQ_ENABLED = 0b1000_0000;
+        const LOOP        = 0b0100_0000;
+        const RATE        = 0b0000_1111;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direct_load_sets_output_level() {
+        let mut dmc = DmcGenerator::default();
+
+        dmc.write_direct_load(0x45);
+        assert_eq!(dmc.tick(), 0x45);
+
+        // Only 7 bits are used
+        dmc.write_direct_load(0xff);
+        assert_eq!(dmc.tick(), 0x7f);
+    }
+
+    #[test]
+    fn sample_bits_move_output_level_up_and_down() {
+        let mut dmc = DmcGenerator::default();
+        dmc.write_flags(0x0f);
+        dmc.write_direct_load(0x40);
+        dmc.write_sample_length(0);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.sample_request(), Some(0xc000));
+        dmc.load_sample(0b0000_0011);
+        assert_eq!(dmc.sample_request(), None);
+
+        let levels: Vec<u8> = (0..54 * 10).map(|_| dmc.tick()).step_by(54).collect();
+
+        // The first output cycle is silent, then the sample is played
+        assert_eq!(
+            levels,
+            [0x40, 0x40, 0x42, 0x44, 0x42, 0x40, 0x3e, 0x3c, 0x3a, 0x38]
+        );
+    }
+
+    #[test]
+    fn finishing_sample_raises_interrupt_if_enabled() {
+        let mut dmc = DmcGenerator::default();
+        dmc.write_flags(0b1000_0000);
+        dmc.write_sample_length(0);
+        dmc.set_enabled(true);
+
+        dmc.load_sample(0);
+
+        assert!(!dmc.active());
+        assert!(dmc.interrupt());
+    }
+
+    #[test]
+    fn looping_sample_restarts_from_sample_address() {
+        let mut dmc = DmcGenerator::default();
+        dmc.write_flags(0b1100_0000);
+        dmc.write_sample_address(0x01);
+        dmc.write_sample_length(0);
+        dmc.set_enabled(true);
+
+        dmc.load_sample(0);
+        dmc.tick();
+
+        assert!(dmc.active());
+        assert!(!dmc.interrupt());
+        assert_eq!(dmc.sample_request(), Some(0xc040));
+    }
+}
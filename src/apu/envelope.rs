@@ -1,4 +1,5 @@
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // An envelope changes a sound's volume over time.
 // In the NES APU, it can set a constant volume or a decay.
 pub struct Envelope {
@@ -0,0 +1,102 @@
+use std::f32::consts::PI;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// The filters the NES applies to its audio output, see https://www.nesdev.org/wiki/APU_Mixer
+pub struct FilterChain {
+    filters: [Filter; 3],
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: f32) -> Self {
+        FilterChain {
+            filters: [
+                Filter::high_pass(90.0, sample_rate),
+                Filter::high_pass(440.0, sample_rate),
+                Filter::low_pass(14_000.0, sample_rate),
+            ],
+        }
+    }
+
+    pub fn apply(&mut self, value: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(value, |value, filter| filter.apply(value))
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// A first-order RC filter
+struct Filter {
+    kind: FilterKind,
+    alpha: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+impl Filter {
+    fn high_pass(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let (rc, dt) = Self::rc_dt(cutoff_hz, sample_rate);
+        Self::new(FilterKind::HighPass, rc / (rc + dt))
+    }
+
+    fn low_pass(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let (rc, dt) = Self::rc_dt(cutoff_hz, sample_rate);
+        Self::new(FilterKind::LowPass, dt / (rc + dt))
+    }
+
+    fn rc_dt(cutoff_hz: f32, sample_rate: f32) -> (f32, f32) {
+        (1.0 / (2.0 * PI * cutoff_hz), 1.0 / sample_rate)
+    }
+
+    fn new(kind: FilterKind, alpha: f32) -> Self {
+        Filter {
+            kind,
+            alpha,
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    fn apply(&mut self, value: f32) -> f32 {
+        let output = match self.kind {
+            FilterKind::HighPass => self.alpha * (self.last_output + value - self.last_input),
+            FilterKind::LowPass => self.last_output + self.alpha * (value - self.last_output),
+        };
+        self.last_input = value;
+        self.last_output = output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_pass_removes_dc_offset_over_time() {
+        let mut filters = FilterChain::new(44_100.0);
+
+        let output: Vec<f32> = (0..44_100).map(|_| filters.apply(0.5)).collect();
+
+        // The sudden step passes through at first, then decays away
+        assert!(output[0] > 0.1);
+        assert!(output[44_099].abs() < 0.001);
+    }
+
+    #[test]
+    fn low_pass_smooths_sudden_changes() {
+        let mut filter = Filter::low_pass(14_000.0, 44_100.0);
+
+        let first = filter.apply(1.0);
+        let second = filter.apply(1.0);
+
+        assert!(0.0 < first && first < second && second < 1.0);
+    }
+}
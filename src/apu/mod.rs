@@ -1,23 +1,84 @@
 //! Emulates the APU (audio processing unit)
 use bitflags::bitflags;
+use dmc::DmcGenerator;
+use filter::FilterChain;
 use noise::NoiseGenerator;
 use pulse::PulseGenerator;
+use resampler::Resampler;
+use sweep::NegateMode;
 use triangle::TriangleGenerator;
 
+use crate::Region;
+
+mod dmc;
 mod envelope;
+mod filter;
 mod noise;
 mod pulse;
+mod resampler;
+mod sweep;
 mod triangle;
 
-#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct APU {
     pulse_1: PulseGenerator,
     pulse_2: PulseGenerator,
     triangle: TriangleGenerator,
     noise: NoiseGenerator,
+    dmc: DmcGenerator,
     // APU can run in two "modes", which affect timing and interrupts
     mode_toggle: bool,
     cycles: u16,
+    mix_mode: MixMode,
+    region: Region,
+    // Output of each channel on the last tick, before mixing
+    channel_outputs: [u8; 5],
+    sample_rate: Option<u32>,
+    // Reduces output to the requested sample rate, if set
+    resampler: Option<Resampler>,
+    // Emulates the filtering applied to the NES audio output, at the output sample rate
+    filters: FilterChain,
+    filter_enabled: bool,
+    // Position of each channel between left (-1.0) and right (1.0), if output is stereo
+    panning: Option<[f32; 5]>,
+    // The resampler and filters above are used for the left side, these for the right side
+    right_resampler: Option<Resampler>,
+    right_filters: FilterChain,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU {
+            pulse_1: PulseGenerator::new(NegateMode::OnesComplement),
+            pulse_2: PulseGenerator::new(NegateMode::TwosComplement),
+            triangle: TriangleGenerator::default(),
+            noise: NoiseGenerator::default(),
+            dmc: DmcGenerator::default(),
+            mode_toggle: false,
+            cycles: 0,
+            mix_mode: MixMode::default(),
+            region: Region::default(),
+            channel_outputs: [0; 5],
+            sample_rate: None,
+            resampler: None,
+            filters: FilterChain::new(Region::NTSC.cpu_frequency_hz() as f32),
+            filter_enabled: true,
+            panning: None,
+            right_resampler: None,
+            right_filters: FilterChain::new(Region::NTSC.cpu_frequency_hz() as f32),
+        }
+    }
+}
+
+/// How the output of each channel is combined into a single wave.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MixMode {
+    /// Accurate mixing, emulating the non-linear response of the NES DAC.
+    #[default]
+    Nonlinear,
+    /// Cheaper linear approximation of the non-linear mixer.
+    Linear,
 }
 
 impl APU {
@@ -26,40 +87,177 @@ impl APU {
         let pulse_2 = self.pulse_2.tick();
         let triangle = self.triangle.tick();
         let noise = self.noise.tick();
+        let dmc = self.dmc.tick();
+        self.channel_outputs = [pulse_1, pulse_2, triangle, noise, dmc];
 
         let cycles = self.cycles;
         self.cycles += 1;
 
-        match (self.mode_toggle, cycles) {
-            (_, 7457) | (_, 22371) => {
-                self.pulse_1.clock_envelope();
-                self.pulse_2.clock_envelope();
-                self.noise.clock_envelope();
-                self.triangle.clock_linear_counter();
+        // The 4-step mode ends on the 4th step, the 5-step mode on the 5th
+        let steps = frame_counter_steps(self.region);
+        let last_step = if self.mode_toggle { steps[4] } else { steps[3] };
+
+        if cycles == steps[0] || cycles == steps[2] {
+            self.clock_quarter_frame();
+        } else if cycles == steps[1] || cycles == last_step {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+
+        if cycles == last_step {
+            self.cycles = 0;
+        }
+
+        self.mix(self.channel_outputs.map(f32::from))
+    }
+
+    fn mix(&self, [pulse_1, pulse_2, triangle, noise, dmc]: [f32; 5]) -> f32 {
+        match self.mix_mode {
+            MixMode::Nonlinear => mix(pulse_1, pulse_2, triangle, noise, dmc),
+            MixMode::Linear => mix_linear(pulse_1, pulse_2, triangle, noise, dmc),
+        }
+    }
+
+    /// Tick, returning a sample only when one is due at the rate set by [APU::set_sample_rate].
+    /// Without a sample rate, every tick produces a sample. Unlike [APU::tick], samples are
+    /// filtered like the NES audio output, unless disabled with [APU::set_filter_enabled].
+    pub fn tick_sample(&mut self) -> Option<f32> {
+        let value = self.tick();
+        output_sample(
+            &mut self.resampler,
+            &mut self.filters,
+            self.filter_enabled,
+            value,
+        )
+    }
+
+    /// Like [APU::tick_sample], but returns a left and right sample, with each channel placed
+    /// according to [APU::set_panning]. Without panning, both sides get the same mono mix.
+    pub fn tick_stereo_sample(&mut self) -> Option<(f32, f32)> {
+        let mono = self.tick();
+        let (left, right) = match self.panning {
+            Some(panning) => {
+                let outputs = self.channel_outputs.map(f32::from);
+                let gains = panning.map(pan_gains);
+                let left = self.mix(std::array::from_fn(|i| outputs[i] * gains[i].0));
+                let right = self.mix(std::array::from_fn(|i| outputs[i] * gains[i].1));
+                (left, right)
             }
-            (_, 14913) | (false, 29829) | (true, 37281) => {
-                self.pulse_1.clock_envelope();
-                self.pulse_2.clock_envelope();
-                self.noise.clock_envelope();
-                self.triangle.clock_linear_counter();
-                self.pulse_1.clock_length_counter();
-                self.pulse_2.clock_length_counter();
-                self.triangle.clock_length_counter();
-                self.noise.clock_length_counter();
+            None => (mono, mono),
+        };
+
+        let filter_enabled = self.filter_enabled;
+        let left = output_sample(&mut self.resampler, &mut self.filters, filter_enabled, left);
+        let right = output_sample(
+            &mut self.right_resampler,
+            &mut self.right_filters,
+            filter_enabled,
+            right,
+        );
+        left.zip(right)
+    }
+
+    /// Place each channel between left (-1.0) and right (1.0), in the order pulse 1, pulse 2,
+    /// triangle, noise and DMC. A channel in the centre plays at full volume on both sides.
+    /// `None`, the default, gives mono output.
+    pub fn set_panning(&mut self, panning: Option<[f32; 5]>) {
+        self.panning = panning.map(|panning| panning.map(|pan| pan.clamp(-1.0, 1.0)));
+    }
+
+    /// Whether panning has been set with [APU::set_panning].
+    pub fn is_stereo(&self) -> bool {
+        self.panning.is_some()
+    }
+
+    /// Produce samples from [APU::tick_sample] at `hz` samples per second.
+    pub fn set_sample_rate(&mut self, hz: u32) {
+        self.sample_rate = Some(hz);
+        self.configure_output();
+    }
+
+    /// Set the region, which changes the frame counter timing and the CPU clock speed that the
+    /// output is resampled from.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.configure_output();
+    }
+
+    fn configure_output(&mut self) {
+        let cpu_frequency = self.region.cpu_frequency_hz();
+        match self.sample_rate {
+            Some(hz) => {
+                self.resampler = Some(Resampler::new(cpu_frequency, f64::from(hz)));
+                self.right_resampler = Some(Resampler::new(cpu_frequency, f64::from(hz)));
+                self.filters = FilterChain::new(hz as f32);
+                self.right_filters = FilterChain::new(hz as f32);
             }
-            (false, 14915) | (true, 37282) => {
-                self.cycles = 0;
+            None => {
+                self.resampler = None;
+                self.right_resampler = None;
+                self.filters = FilterChain::new(cpu_frequency as f32);
+                self.right_filters = FilterChain::new(cpu_frequency as f32);
             }
-            _ => {}
         }
+    }
+
+    /// Turn the output filters on or off. They're on by default.
+    pub fn set_filter_enabled(&mut self, enabled: bool) {
+        self.filter_enabled = enabled;
+    }
+
+    /// The raw output of each channel on the last tick, in the order pulse 1, pulse 2, triangle,
+    /// noise and DMC. Useful for visualizing the channels separately.
+    pub fn channel_outputs(&self) -> [u8; 5] {
+        self.channel_outputs
+    }
+
+    pub fn set_mix_mode(&mut self, mix_mode: MixMode) {
+        self.mix_mode = mix_mode;
+    }
 
-        mix(pulse_1, pulse_2, triangle, noise)
+    /// Silence all channels, as when the reset button is pressed.
+    pub fn reset(&mut self) {
+        self.write_status(0);
+    }
+
+    /// Reset all state to how it is when the NES is powered on. Settings such as the mix mode are
+    /// kept.
+    pub fn power_on(&mut self) {
+        *self = APU {
+            mix_mode: self.mix_mode,
+            panning: self.panning,
+            region: self.region,
+            sample_rate: self.sample_rate,
+            filter_enabled: self.filter_enabled,
+            ..APU::default()
+        };
+        self.configure_output();
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.clock_envelope();
+        self.pulse_2.clock_envelope();
+        self.noise.clock_envelope();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.clock_length_counter();
+        self.pulse_2.clock_length_counter();
+        self.pulse_1.clock_sweep();
+        self.pulse_2.clock_sweep();
+        self.triangle.clock_length_counter();
+        self.noise.clock_length_counter();
     }
 
     pub fn write_pulse_1_flags(&mut self, value: u8) {
         self.pulse_1.write_flags(value);
     }
 
+    pub fn write_pulse_1_sweep(&mut self, value: u8) {
+        self.pulse_1.write_sweep(value);
+    }
+
     pub fn write_pulse_1_timer(&mut self, value: u8) {
         self.pulse_1.write_timer(value);
     }
@@ -72,6 +270,10 @@ impl APU {
         self.pulse_2.write_flags(value);
     }
 
+    pub fn write_pulse_2_sweep(&mut self, value: u8) {
+        self.pulse_2.write_sweep(value);
+    }
+
     pub fn write_pulse_2_timer(&mut self, value: u8) {
         self.pulse_2.write_timer(value);
     }
@@ -104,6 +306,37 @@ impl APU {
         self.noise.write_length(value);
     }
 
+    pub fn write_dmc_flags(&mut self, value: u8) {
+        self.dmc.write_flags(value);
+    }
+
+    pub fn write_dmc_direct_load(&mut self, value: u8) {
+        self.dmc.write_direct_load(value);
+    }
+
+    pub fn write_dmc_sample_address(&mut self, value: u8) {
+        self.dmc.write_sample_address(value);
+    }
+
+    pub fn write_dmc_sample_length(&mut self, value: u8) {
+        self.dmc.write_sample_length(value);
+    }
+
+    /// The address of the next DMC sample byte, if the DMC needs one to be read by DMA.
+    pub fn dmc_sample_request(&self) -> Option<u16> {
+        self.dmc.sample_request()
+    }
+
+    /// Give the DMC the byte read from the address given by `dmc_sample_request`.
+    pub fn load_dmc_sample(&mut self, byte: u8) {
+        self.dmc.load_sample(byte);
+    }
+
+    /// Whether the APU is asserting the CPU's IRQ line.
+    pub fn irq(&self) -> bool {
+        self.dmc.interrupt()
+    }
+
     pub fn write_frame_counter(&mut self, value: u8) {
         let value = FrameCounter::from_bits_truncate(value);
         self.mode_toggle = value.contains(FrameCounter::MODE);
@@ -113,6 +346,10 @@ impl APU {
         let mut status = Status::empty();
         status.set(Status::PULSE_1, !self.pulse_1.halted());
         status.set(Status::PULSE_2, !self.pulse_2.halted());
+        status.set(Status::TRIANGLE, !self.triangle.halted());
+        status.set(Status::NOISE, !self.noise.halted());
+        status.set(Status::DMC, self.dmc.active());
+        status.set(Status::DMC_INTERRUPT, self.dmc.interrupt());
         status.bits()
     }
 
@@ -122,19 +359,52 @@ impl APU {
         self.pulse_2.set_enabled(status.contains(Status::PULSE_2));
         self.triangle.set_enabled(status.contains(Status::TRIANGLE));
         self.noise.set_enabled(status.contains(Status::NOISE));
+        self.dmc.set_enabled(status.contains(Status::DMC));
+    }
+}
+
+// CPU cycles at which each step of the frame counter happens
+fn frame_counter_steps(region: Region) -> [u16; 5] {
+    match region {
+        Region::NTSC => [7457, 14913, 22371, 29829, 37281],
+        Region::PAL => [8313, 16627, 24939, 33253, 41565],
     }
 }
 
+// Resample and filter a mixed value, as configured by `APU::configure_output`
+fn output_sample(
+    resampler: &mut Option<Resampler>,
+    filters: &mut FilterChain,
+    filter_enabled: bool,
+    value: f32,
+) -> Option<f32> {
+    let sample = match resampler {
+        Some(resampler) => resampler.sample(value)?,
+        None => value,
+    };
+
+    if filter_enabled {
+        Some(filters.apply(sample))
+    } else {
+        Some(sample)
+    }
+}
+
+// Gain of the left and right side for a channel panned to `pan`
+fn pan_gains(pan: f32) -> (f32, f32) {
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
+
 // Mix output channels, produce a value between 0.0 and 1.0
-fn mix(pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8) -> f32 {
-    let pulse_in = (pulse_1 + pulse_2) as f32;
+fn mix(pulse_1: f32, pulse_2: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+    let pulse_in = pulse_1 + pulse_2;
     let pulse_out = if pulse_in == 0.0 {
         0.0
     } else {
         95.88 / ((8128.0 / pulse_in) + 100.0)
     };
 
-    let tnd_in = (triangle as f32) / 8227.0 + (noise as f32) / 12241.0;
+    let tnd_in = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
     let tnd_out = if tnd_in == 0.0 {
         0.0
     } else {
@@ -143,14 +413,22 @@ fn mix(pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8) -> f32 {
     pulse_out + tnd_out
 }
 
+// Linear approximation of `mix`, see https://www.nesdev.org/wiki/APU_Mixer
+fn mix_linear(pulse_1: f32, pulse_2: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+    let pulse_out = 0.00752 * (pulse_1 + pulse_2);
+    let tnd_out = 0.00851 * triangle + 0.00494 * noise + 0.00335 * dmc;
+    pulse_out + tnd_out
+}
+
 bitflags! {
+    #[derive(Debug, PartialEq)]
     struct Status: u8 {
         const PULSE_1         = 0b0000_0001;
         const PULSE_2         = 0b0000_0010;
         const TRIANGLE        = 0b0000_0100;
         const NOISE           = 0b0000_1000;
         const DMC             = 0b0001_0000;
-        const FRAME_INTERRUPT = 0b1000_0000;
+        const FRAME_INTERRUPT = 0b0100_0000;
         const DMC_INTERRUPT   = 0b1000_0000;
     }
 
@@ -189,3 +467,162 @@ const LENGTH_COUNTER_TABLE: [u8; 32] = [
      16, /* trip. quaver */    28, 
      32, /* trip. crotchet */  30,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_reports_channels_until_length_counters_expire() {
+        let mut apu = APU::default();
+        apu.write_status(0b0000_1111);
+
+        // Length index 0 is 10 half-frames
+        apu.write_triangle_length(0);
+        apu.write_noise_length(0);
+
+        let status = Status::from_bits_truncate(apu.read_status());
+        assert_eq!(status, Status::TRIANGLE | Status::NOISE);
+
+        // Length counters are clocked every half-frame, about every 14915 CPU cycles
+        for _ in 0..14916 * 9 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status(), status.bits());
+
+        for _ in 0..14916 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn pal_length_counters_are_clocked_more_slowly() {
+        let mut apu = APU::default();
+        apu.set_region(Region::PAL);
+        apu.write_status(0b0000_0100);
+        // Length index 0x03 is 2 half-frames
+        apu.write_triangle_length(0b0001_1000);
+
+        // Half-frames happen at 16627 and 33253 CPU cycles
+        for _ in 0..33253 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status(), Status::TRIANGLE.bits());
+
+        apu.tick();
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn channel_outputs_report_each_channel_before_mixing() {
+        let mut apu = APU::default();
+        assert_eq!(apu.channel_outputs(), [0; 5]);
+
+        apu.write_status(0b0000_0001);
+        // 50% duty, constant volume 15
+        apu.write_pulse_1_flags(0b1011_1111);
+        apu.write_pulse_1_timer(0x00);
+        apu.write_pulse_1_length(0b0000_1001);
+
+        let mut pulse_1 = vec![];
+        for _ in 0..0x200 * 16 {
+            apu.tick();
+            let [pulse, rest @ ..] = apu.channel_outputs();
+            pulse_1.push(pulse);
+            assert_eq!(rest, [0; 4]);
+        }
+
+        assert!(pulse_1.contains(&0));
+        assert!(pulse_1.contains(&15));
+    }
+
+    #[test]
+    fn sample_rate_sets_number_of_samples_per_second() {
+        let mut apu = APU::default();
+        apu.set_sample_rate(44_100);
+
+        let cycles_per_second = Region::NTSC.cpu_frequency_hz() as usize;
+        let samples = (0..cycles_per_second)
+            .filter_map(|_| apu.tick_sample())
+            .count();
+
+        // Allow for rounding at the end of the second
+        assert!((44_099..=44_101).contains(&samples), "{} samples", samples);
+    }
+
+    #[test]
+    fn without_sample_rate_every_tick_produces_a_sample() {
+        let mut apu = APU::default();
+
+        let samples = (0..1000).filter_map(|_| apu.tick_sample()).count();
+
+        assert_eq!(samples, 1000);
+    }
+
+    #[test]
+    fn nonlinear_mix_uses_nonlinear_formula() {
+        let expected = 95.88 / ((8128.0 / 15.0) + 100.0)
+            + 159.79 / (1.0 / (6.0 / 8227.0 + 3.0 / 12241.0 + 40.0 / 22638.0) + 100.0);
+        assert_eq!(mix(10.0, 5.0, 6.0, 3.0, 40.0), expected);
+        assert_eq!(mix(0.0, 0.0, 0.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn linear_mix_uses_weighted_sum() {
+        let expected = 0.00752 * 15.0 + (0.00851 * 6.0 + 0.00494 * 3.0 + 0.00335 * 40.0);
+        assert_eq!(mix_linear(10.0, 5.0, 6.0, 3.0, 40.0), expected);
+        assert_eq!(mix_linear(0.0, 0.0, 0.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn panning_routes_channel_to_one_side() {
+        let mut apu = APU::default();
+        apu.set_filter_enabled(false);
+        apu.set_panning(Some([-1.0, 1.0, 0.0, 0.0, 0.0]));
+
+        apu.write_status(0b0000_0001);
+        // 50% duty, constant volume 15
+        apu.write_pulse_1_flags(0b1011_1111);
+        apu.write_pulse_1_timer(0x00);
+        apu.write_pulse_1_length(0b0000_1001);
+
+        let mut left_max: f32 = 0.0;
+        for _ in 0..0x200 * 16 {
+            let (left, right) = apu.tick_stereo_sample().unwrap();
+            left_max = left_max.max(left);
+            assert_eq!(right, 0.0);
+        }
+        assert_eq!(left_max, mix(15.0, 0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn centred_channel_plays_fully_on_both_sides() {
+        let mut apu = APU::default();
+        apu.set_filter_enabled(false);
+        apu.set_panning(Some([0.0; 5]));
+
+        apu.write_status(0b0000_0001);
+        apu.write_pulse_1_flags(0b1011_1111);
+        apu.write_pulse_1_timer(0x00);
+        apu.write_pulse_1_length(0b0000_1001);
+
+        for _ in 0..0x200 * 16 {
+            let (left, right) = apu.tick_stereo_sample().unwrap();
+            assert_eq!(left, right);
+            assert_eq!(
+                left,
+                mix(f32::from(apu.channel_outputs()[0]), 0.0, 0.0, 0.0, 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn pan_gains_fade_out_the_opposite_side() {
+        assert_eq!(pan_gains(-1.0), (1.0, 0.0));
+        assert_eq!(pan_gains(-0.5), (1.0, 0.5));
+        assert_eq!(pan_gains(0.0), (1.0, 1.0));
+        assert_eq!(pan_gains(0.5), (0.5, 1.0));
+        assert_eq!(pan_gains(1.0), (0.0, 1.0));
+    }
+}
@@ -3,6 +3,7 @@ use bitflags::bitflags;
 
 use super::envelope::Envelope;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // A pseudo-random noise generator
 pub struct NoiseGenerator {
     enabled: bool,
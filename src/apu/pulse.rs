@@ -4,8 +4,10 @@ use bitflags::bitflags;
 use super::Length;
 
 use super::envelope::Envelope;
+use super::sweep::{NegateMode, Sweep};
 
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // A 'pulse wave' is a rectangular wave (alternating from high to low).
 pub struct PulseGenerator {
     enabled: bool,
@@ -22,9 +24,17 @@ pub struct PulseGenerator {
     length_counter: u8,
     length_counter_halt: bool,
     envelope: Envelope,
+    sweep: Sweep,
 }
 
 impl PulseGenerator {
+    pub fn new(negate_mode: NegateMode) -> Self {
+        PulseGenerator {
+            sweep: Sweep::new(negate_mode),
+            ..PulseGenerator::default()
+        }
+    }
+
     pub fn set_enabled(&mut self, enabled: bool) {
         if !enabled {
             self.length_counter = 0;
@@ -42,6 +52,10 @@ impl PulseGenerator {
             .set_volume((flags & PulseFlags::VOLUME).bits());
     }
 
+    pub fn write_sweep(&mut self, value: u8) {
+        self.sweep.write(value);
+    }
+
     pub fn write_timer(&mut self, value: u8) {
         // Set the low bits of the timer
         self.timer_initial = (self.timer_initial & 0xFF00) | value as u16;
@@ -71,6 +85,11 @@ impl PulseGenerator {
         self.envelope.clock();
     }
 
+    // Low-frequency clock to bend the pitch
+    pub fn clock_sweep(&mut self) {
+        self.sweep.clock(&mut self.timer_initial);
+    }
+
     // Low-frequency clock to stop sound after a certain time
     pub fn clock_length_counter(&mut self) {
         if self.length_counter > 0 && !self.length_counter_halt {
@@ -80,7 +99,7 @@ impl PulseGenerator {
 
     // High-frequency tick to control waveform generation
     pub fn tick(&mut self) -> u8 {
-        let playing = !self.halted();
+        let playing = !self.halted() && !self.sweep.mutes(self.timer_initial);
         let volume = self.envelope.volume();
         let waveform = PULSE_DUTY_WAVEFORM[self.duty_cycle as usize];
         let value = (waveform.rotate_right(self.sequencer as u32) & 0b1) * volume * playing as u8;
@@ -133,6 +152,7 @@ mod tests {
             // Set duty to 25%
             duty_cycle: 1,
             envelope: Envelope::default(),
+            sweep: Sweep::default(),
         };
 
         // Volume goes up to 11
@@ -157,4 +177,87 @@ mod tests {
             .concat()
         );
     }
+
+    #[test]
+    fn test_pulse_generator_writing_length_restarts_sequencer_and_envelope() {
+        let mut pulse = PulseGenerator {
+            enabled: true,
+            odd_cycle: false,
+            timer_initial: 8,
+            timer: 8,
+            sequencer: 1,
+            length_counter: 5,
+            length_counter_halt: false,
+            // Set duty to 25% negated, so the first step of the waveform is high
+            duty_cycle: 3,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+        };
+
+        pulse.envelope.set_volume(3);
+        assert_eq!(pulse.tick(), 0);
+
+        pulse.write_length(0b0000_1000);
+        assert_eq!(pulse.sequencer, 0);
+
+        // Envelope restarts at full volume on the next clock, and the waveform starts high
+        pulse.clock_envelope();
+        assert_eq!(pulse.tick(), 15);
+    }
+
+    #[test]
+    fn rising_sweep_increases_timer_period() {
+        let mut pulse = PulseGenerator::new(NegateMode::TwosComplement);
+        pulse.write_timer(0x00);
+        pulse.write_length(0x01);
+
+        // Enabled, period 1, shift 2
+        pulse.write_sweep(0b1001_0010);
+
+        let periods: Vec<u16> = (0..5)
+            .map(|_| {
+                pulse.clock_sweep();
+                pulse.timer_initial
+            })
+            .collect();
+
+        // Period changes on every other clock, adding a quarter each time
+        assert_eq!(periods, [0x140, 0x140, 0x190, 0x190, 0x1f4]);
+    }
+
+    #[test]
+    fn falling_sweep_decreases_timer_period() {
+        let mut pulse_1 = PulseGenerator::new(NegateMode::OnesComplement);
+        let mut pulse_2 = PulseGenerator::new(NegateMode::TwosComplement);
+
+        for pulse in [&mut pulse_1, &mut pulse_2] {
+            pulse.write_timer(0x00);
+            pulse.write_length(0x01);
+            // Enabled, period 0, negate, shift 1
+            pulse.write_sweep(0b1000_1001);
+            pulse.clock_sweep();
+        }
+
+        // Pulse 1 subtracts an extra 1
+        assert_eq!(pulse_1.timer_initial, 0x7f);
+        assert_eq!(pulse_2.timer_initial, 0x80);
+    }
+
+    #[test]
+    fn sweep_mutes_channel_when_target_period_overflows() {
+        let mut pulse = PulseGenerator::new(NegateMode::TwosComplement);
+        pulse.set_enabled(true);
+        // Set duty to 25% negated, so the first step of the waveform is high
+        pulse.write_flags(0b1101_1111);
+        pulse.write_timer(0x00);
+        pulse.write_length(0b0000_1100);
+
+        // Sweep disabled, but shift 0 would double the period out of range
+        pulse.write_sweep(0b0000_0000);
+        assert!((0..100).all(|_| pulse.tick() == 0));
+
+        pulse.write_sweep(0b0000_0001);
+        pulse.write_length(0b0000_1100);
+        assert_eq!(pulse.tick(), 15);
+    }
 }
@@ -0,0 +1,71 @@
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// Converts the APU output, which produces a sample every CPU cycle, to a lower output sample rate.
+// Each output sample is the average of the input samples it covers, which filters out the
+// aliasing that comes from just picking every nth sample.
+pub struct Resampler {
+    // Number of input samples per output sample, which isn't a whole number
+    step: f64,
+    // Input samples until the next output sample
+    next_sample: f64,
+    sum: f32,
+    count: u32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        Resampler {
+            step: input_rate / output_rate,
+            next_sample: input_rate / output_rate,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    // Returns the sample to output, if any, after this input sample
+    pub fn sample(&mut self, value: f32) -> Option<f32> {
+        self.sum += value;
+        self.count += 1;
+        self.next_sample -= 1.0;
+
+        if self.next_sample > 0.0 {
+            return None;
+        }
+
+        self.next_sample += self.step;
+        let average = self.sum / self.count as f32;
+        self.sum = 0.0;
+        self.count = 0;
+        Some(average)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_samples_at_fractional_rate() {
+        let mut resampler = Resampler::new(2.5, 1.0);
+
+        let output: Vec<bool> = (0..10)
+            .map(|i| resampler.sample(i as f32).is_some())
+            .collect();
+
+        assert_eq!(
+            output,
+            [false, false, true, false, true, false, false, true, false, true]
+        );
+    }
+
+    #[test]
+    fn output_is_average_of_input_samples() {
+        let mut resampler = Resampler::new(4.0, 1.0);
+
+        let output: Vec<f32> = [1.0, 2.0, 3.0, 6.0, 0.0, 0.0, 1.0, 1.0]
+            .into_iter()
+            .filter_map(|value| resampler.sample(value))
+            .collect();
+
+        assert_eq!(output, [3.0, 0.5]);
+    }
+}
@@ -0,0 +1,85 @@
+use bitflags::bitflags;
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// A sweep periodically adjusts a pulse channel's period, bending the pitch up or down.
+pub struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+    negate_mode: NegateMode,
+}
+
+// The two pulse channels negate the change in period differently
+#[derive(Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NegateMode {
+    // Pulse 1 subtracts an extra 1
+    OnesComplement,
+    #[default]
+    TwosComplement,
+}
+
+impl Sweep {
+    pub fn new(negate_mode: NegateMode) -> Self {
+        Sweep {
+            negate_mode,
+            ..Sweep::default()
+        }
+    }
+
+    pub fn write(&mut self, value: u8) {
+        let flags = SweepFlags::from_bits_truncate(value);
+        self.enabled = flags.contains(SweepFlags::ENABLED);
+        self.period = (flags & SweepFlags::PERIOD).bits() >> 4;
+        self.negate = flags.contains(SweepFlags::NEGATE);
+        self.shift = (flags & SweepFlags::SHIFT).bits();
+        self.reload = true;
+    }
+
+    // The channel is silenced if the period is too low or the sweep would take it out of range,
+    // even if the sweep is disabled.
+    pub fn mutes(&self, timer_period: u16) -> bool {
+        timer_period < 8 || self.target_period(timer_period) > 0x7ff
+    }
+
+    // Low-frequency clock to adjust the timer period
+    pub fn clock(&mut self, timer_period: &mut u16) {
+        if self.divider == 0 && self.enabled && self.shift != 0 && !self.mutes(*timer_period) {
+            *timer_period = self.target_period(*timer_period);
+        }
+
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn target_period(&self, timer_period: u16) -> u16 {
+        let change = timer_period >> self.shift;
+        if !self.negate {
+            timer_period + change
+        } else {
+            let change = match self.negate_mode {
+                NegateMode::OnesComplement => change + 1,
+                NegateMode::TwosComplement => change,
+            };
+            timer_period.saturating_sub(change)
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone)]
+    struct SweepFlags: u8 {
+        const ENABLED = 0b1000_0000;
+        const PERIOD  = 0b0111_0000;
+        const NEGATE  = 0b0000_1000;
+        const SHIFT   = 0b0000_0111;
+    }
+}
@@ -4,6 +4,7 @@ use bitflags::bitflags;
 use super::Length;
 
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // A 'triangle wave' is a waveform that goes up and down in a triangle shape.
 pub struct TriangleGenerator {
     enabled: bool,
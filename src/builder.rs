@@ -0,0 +1,209 @@
+//! Configures and constructs an [NES].
+use crate::{
+    AccuracyProfile, Cartridge, MixMode, NESDisplay, NESSpeaker, PowerOnPattern, Region, NES,
+};
+
+/// Builds an [NES] with optional settings, starting with no display or speaker.
+///
+/// ```ignore
+/// let nes = NESBuilder::default()
+///     .cartridge(cartridge)
+///     .display(BufferDisplay::default())
+///     .region(Region::PAL)
+///     .build();
+/// ```
+pub struct NESBuilder<D = (), S = ()> {
+    cartridge: Option<Cartridge>,
+    display: D,
+    speaker: S,
+    region: Region,
+    accuracy: Option<AccuracyProfile>,
+    power_on_pattern: PowerOnPattern,
+    mix_mode: Option<MixMode>,
+    sample_rate: Option<u32>,
+    instruction_limit: Option<u64>,
+}
+
+impl Default for NESBuilder {
+    fn default() -> Self {
+        NESBuilder {
+            cartridge: None,
+            display: (),
+            speaker: (),
+            region: Region::default(),
+            accuracy: None,
+            power_on_pattern: PowerOnPattern::default(),
+            mix_mode: None,
+            sample_rate: None,
+            instruction_limit: None,
+        }
+    }
+}
+
+impl<D: NESDisplay, S: NESSpeaker> NESBuilder<D, S> {
+    pub fn cartridge(self, cartridge: Cartridge) -> Self {
+        NESBuilder {
+            cartridge: Some(cartridge),
+            ..self
+        }
+    }
+
+    pub fn display<D2: NESDisplay>(self, display: D2) -> NESBuilder<D2, S> {
+        NESBuilder {
+            cartridge: self.cartridge,
+            display,
+            speaker: self.speaker,
+            region: self.region,
+            accuracy: self.accuracy,
+            power_on_pattern: self.power_on_pattern,
+            mix_mode: self.mix_mode,
+            sample_rate: self.sample_rate,
+            instruction_limit: self.instruction_limit,
+        }
+    }
+
+    pub fn speaker<S2: NESSpeaker>(self, speaker: S2) -> NESBuilder<D, S2> {
+        NESBuilder {
+            cartridge: self.cartridge,
+            display: self.display,
+            speaker,
+            region: self.region,
+            accuracy: self.accuracy,
+            power_on_pattern: self.power_on_pattern,
+            mix_mode: self.mix_mode,
+            sample_rate: self.sample_rate,
+            instruction_limit: self.instruction_limit,
+        }
+    }
+
+    pub fn region(self, region: Region) -> Self {
+        NESBuilder { region, ..self }
+    }
+
+    /// See [NES::set_accuracy_profile].
+    pub fn accuracy_profile(self, accuracy: AccuracyProfile) -> Self {
+        NESBuilder {
+            accuracy: Some(accuracy),
+            ..self
+        }
+    }
+
+    /// See [NES::set_power_on_pattern].
+    pub fn power_on_pattern(self, pattern: PowerOnPattern) -> Self {
+        NESBuilder {
+            power_on_pattern: pattern,
+            ..self
+        }
+    }
+
+    /// See [NES::set_mix_mode].
+    pub fn mix_mode(self, mix_mode: MixMode) -> Self {
+        NESBuilder {
+            mix_mode: Some(mix_mode),
+            ..self
+        }
+    }
+
+    /// See [NES::set_sample_rate].
+    pub fn sample_rate(self, hz: u32) -> Self {
+        NESBuilder {
+            sample_rate: Some(hz),
+            ..self
+        }
+    }
+
+    /// See [NES::set_instruction_limit].
+    pub fn instruction_limit(self, limit: u64) -> Self {
+        NESBuilder {
+            instruction_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Panics if no cartridge was given.
+    pub fn build(self) -> NES<D, S> {
+        let cartridge = self.cartridge.expect("NESBuilder needs a cartridge");
+        let mut nes = NES::with_region(cartridge, self.display, self.speaker, self.region);
+
+        if let Some(accuracy) = self.accuracy {
+            nes.set_accuracy_profile(accuracy);
+        }
+        nes.set_power_on_pattern(self.power_on_pattern);
+        if let Some(mix_mode) = self.mix_mode {
+            nes.set_mix_mode(mix_mode);
+        }
+        if let Some(hz) = self.sample_rate {
+            nes.set_sample_rate(hz);
+        }
+        nes.set_instruction_limit(self.instruction_limit);
+
+        nes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Address, BufferDisplay, MapperKind};
+
+    use super::*;
+
+    #[test]
+    fn builds_nes_with_given_settings() {
+        let mut nes = NESBuilder::default()
+            .cartridge(cartridge())
+            .display(BufferDisplay::default())
+            .region(Region::PAL)
+            .instruction_limit(10)
+            .build();
+
+        assert_eq!(nes.region(), Region::PAL);
+        assert!(nes.pump(u64::MAX).instruction_limit_reached);
+    }
+
+    #[test]
+    fn defaults_to_ntsc() {
+        let nes = NESBuilder::default().cartridge(cartridge()).build();
+
+        assert_eq!(nes.region(), Region::NTSC);
+    }
+
+    #[test]
+    fn ram_is_filled_with_power_on_pattern() {
+        let mut nes = NESBuilder::default()
+            .cartridge(cartridge())
+            .power_on_pattern(PowerOnPattern::Filled(0xAA))
+            .build();
+
+        assert_eq!(nes.dump_ram(), &[0xAA; 0x800]);
+    }
+
+    #[test]
+    fn fast_profile_reads_open_bus_as_0() {
+        let mut nes = NESBuilder::default()
+            .cartridge(cartridge())
+            .accuracy_profile(AccuracyProfile::Fast)
+            .build();
+
+        nes.read_cpu(Address::new(0x8000));
+        assert_eq!(nes.read_cpu(Address::new(0x4018)), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn building_without_cartridge_panics() {
+        NESBuilder::default().build();
+    }
+
+    fn cartridge() -> Cartridge {
+        let mut prg_rom = [0u8; 0x4000];
+        // JMP $8000
+        prg_rom[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+        Cartridge::new(
+            Box::new(prg_rom),
+            Box::new([0u8; 0x2000]),
+            false,
+            MapperKind::NROM,
+        )
+    }
+}
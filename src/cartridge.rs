@@ -1,12 +1,22 @@
+#[cfg(feature = "serde")]
+use std::any::Any;
+use std::cell::{RefCell, RefMut};
 use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
 
-use crate::mapper::Mapper;
+use crate::mapper::{Axrom, Cnrom, Mapper, MapperKind, Mirroring, Mmc1, Mmc3, Nrom, Uxrom};
 use crate::Address;
 use crate::Memory;
+#[cfg(feature = "serde")]
+use crate::StateError;
+use crate::{INes, INesReadError};
 
 pub struct Cartridge {
-    pub prg: PRG,
-    pub chr: CHR,
+    mapper: Box<dyn Mapper>,
+    mapper_number: u16,
+    mirroring: Mirroring,
+    prg_ram_size: usize,
+    has_battery: bool,
 }
 
 impl Cartridge {
@@ -14,65 +24,180 @@ impl Cartridge {
         prg_rom: Box<[u8]>,
         chr_rom: Box<[u8]>,
         chr_ram_enabled: bool,
-        mapper: Mapper,
+        mapper: MapperKind,
     ) -> Self {
-        let prg_bank_size = match mapper {
-            Mapper::NROM => 0x4000,
-            Mapper::UxROM => 0x4000,
-            Mapper::MMC1 => 0x4000,
-            Mapper::Namco129 => 0x2000,
+        let mapper_number = mapper.number();
+        let mapper: Box<dyn Mapper> = match mapper {
+            MapperKind::NROM => Box::new(Nrom::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::UxROM => Box::new(Uxrom::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::MMC1 => Box::new(Mmc1::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::CNROM => Box::new(Cnrom::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::MMC3 => Box::new(Mmc3::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::AxROM => Box::new(Axrom::new(prg_rom, chr_rom, chr_ram_enabled)),
+            MapperKind::Namco129 => Box::new(Uxrom::with_bank_size(
+                prg_rom,
+                chr_rom,
+                chr_ram_enabled,
+                0x2000,
+            )),
             #[allow(unreachable_patterns)] // Allow because we might add more mappers
             _ => unimplemented!("Unsupported mapper {:?}", mapper),
         };
 
-        let prg_rom_len = prg_rom.len();
-        let prg_bank_size = prg_bank_size.min(prg_rom_len.try_into().unwrap_or(u16::MAX));
-        let last_bank = (prg_rom_len / (prg_bank_size as usize) - 1) as u8;
+        Cartridge {
+            mapper,
+            mapper_number,
+            mirroring: Mirroring::Vertical,
+            prg_ram_size: 0x2000,
+            has_battery: false,
+        }
+    }
 
-        let bank_switcher = match mapper {
-            Mapper::MMC1 => BankSwitcher::MMC1 {
-                shift_register: 0,
-                writes: 0,
-            },
-            _ => BankSwitcher::First,
-        };
+    /// Read a cartridge from the contents of an iNES file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, INesReadError> {
+        Ok(INes::read(bytes)?.into_cartridge())
+    }
+
+    /// The iNES mapper number of the cartridge hardware.
+    pub fn mapper_number(&self) -> u16 {
+        self.mapper_number
+    }
+
+    /// Set the nametable mirroring the cartridge is wired for. Mappers that switch mirroring
+    /// override this, unless it's four-screen mirroring.
+    pub fn with_mirroring(mut self, mirroring: Mirroring) -> Self {
+        self.mirroring = mirroring;
+        self
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// Set the size of PRG RAM declared by the cartridge, and whether it's battery-backed.
+    pub fn with_prg_ram(mut self, prg_ram_size: usize, has_battery: bool) -> Self {
+        self.prg_ram_size = prg_ram_size;
+        self.has_battery = has_battery;
+        self
+    }
 
+    /// Load a trainer into PRG RAM at $7000, as some ROM dumps expect.
+    pub fn with_trainer(mut self, trainer: &[u8]) -> Self {
+        let prg_ram = self.mapper.prg_ram();
+        prg_ram[0x1000..0x1000 + trainer.len()].copy_from_slice(trainer);
+        self
+    }
+
+    /// Whether PRG RAM is battery-backed, so should be saved between sessions.
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    /// The size of PRG RAM declared by the cartridge, in bytes.
+    pub fn prg_ram_size(&self) -> usize {
+        self.prg_ram_size
+    }
+
+    /// The raw PRG ROM data.
+    pub fn prg_rom(&self) -> &[u8] {
+        self.mapper.prg_rom()
+    }
+
+    /// The raw CHR data. If the cartridge has CHR RAM, this is the current contents of the RAM.
+    pub fn chr(&self) -> &[u8] {
+        self.mapper.chr()
+    }
+
+    /// Split the cartridge into the parts connected to the CPU and the PPU, which share the mapper.
+    pub(crate) fn split(self) -> (PRG, CHR) {
+        let mapper = Rc::new(RefCell::new(self.mapper));
         let prg = PRG {
-            rom: prg_rom,
-            bank_mapping: vec![0, last_bank].into(),
-            bank_size: prg_bank_size,
-            bank_switcher,
-            ram: [0; 0x2000],
+            mapper: mapper.clone(),
+            #[cfg(feature = "serde")]
+            mapper_number: self.mapper_number,
         };
-
         let chr = CHR {
-            chr_rom,
-            chr_ram_enabled,
-            ppu_ram: [0; 0x800],
+            mapper,
+            mirroring: self.mirroring,
+            ppu_ram: [0; 0x1000],
+            a12: false,
         };
-
-        log::info!(
-            "Creating cartridge with PRG ROM of size {} and window of size {}",
-            prg_rom_len,
-            prg_bank_size
-        );
-
-        Cartridge { prg, chr }
+        (prg, chr)
     }
 }
 
 /// Program memory on a NES cartridge, connected to the CPU
 pub struct PRG {
-    rom: Box<[u8]>,
-    bank_mapping: Box<[u8]>,
-    bank_size: u16,
-    bank_switcher: BankSwitcher,
-    ram: [u8; 0x2000],
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    // Saved with the mapper state, to check it's loaded into the same kind of cartridge
+    #[cfg(feature = "serde")]
+    mapper_number: u16,
+}
+
+impl PRG {
+    pub fn ram(&mut self) -> RefMut<'_, [u8]> {
+        RefMut::map(self.mapper.borrow_mut(), |mapper| mapper.prg_ram())
+    }
+
+    /// Whether the cartridge is asserting the CPU's IRQ line.
+    pub fn irq(&self) -> bool {
+        self.mapper.borrow().irq()
+    }
+
+    /// The start, inclusive end and currently selected bank of each window into PRG ROM.
+    pub fn windows(&self) -> Vec<(Address, Address, u8)> {
+        self.mapper.borrow().prg_windows()
+    }
 }
 
+/// Saved state of [PRG], which includes the state of the mapper shared with [CHR]. ROM isn't
+/// saved, but its size is, to check the state is loaded into the same kind of cartridge.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PRGState {
+    mapper_number: u16,
+    prg_rom_size: usize,
+    chr_size: usize,
+    mapper: Vec<u8>,
+}
+
+/// Mapper state read by [PRG::read_state], ready to be restored.
+#[cfg(feature = "serde")]
+pub(crate) struct SavedMapper(Box<dyn Any>);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PRG {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mapper = self.mapper.borrow();
+        let state = PRGState {
+            mapper_number: self.mapper_number,
+            prg_rom_size: mapper.prg_rom().len(),
+            chr_size: mapper.chr().len(),
+            mapper: mapper.save_state().map_err(serde::ser::Error::custom)?,
+        };
+        state.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
 impl PRG {
-    pub fn ram(&mut self) -> &mut [u8] {
-        &mut self.ram
+    /// Read saved mapper state, checking it's for this cartridge without changing anything.
+    pub(crate) fn read_state(&self, state: &PRGState) -> Result<SavedMapper, StateError> {
+        let mapper = self.mapper.borrow();
+        if state.mapper_number != self.mapper_number {
+            return Err(StateError::new(format!(
+                "state is for mapper {}, but the cartridge uses mapper {}",
+                state.mapper_number, self.mapper_number
+            )));
+        }
+        if state.prg_rom_size != mapper.prg_rom().len() || state.chr_size != mapper.chr().len() {
+            return Err(StateError::new("ROM size doesn't match the cartridge"));
+        }
+        Ok(SavedMapper(mapper.read_state(&state.mapper)?))
+    }
+
+    pub(crate) fn restore(&mut self, saved: SavedMapper) {
+        self.mapper.borrow_mut().restore_state(saved.0);
     }
 }
 
@@ -84,97 +209,94 @@ impl Debug for PRG {
 
 impl Memory for PRG {
     fn read(&mut self, address: Address) -> u8 {
-        match address.index() {
-            0x6000..=0x7fff => self.ram[address.index() - 0x6000],
-            0x8000..=0xffff => {
-                let relative_address = address - 0x8000;
-                let bank_index = relative_address.bytes() / self.bank_size;
-                let bank = self.bank_mapping[bank_index as usize];
-                let bank_start = bank_index * self.bank_size;
-                let bank_address = relative_address - bank_start;
-                let bank_size = self.bank_size as usize;
-                self.rom[bank as usize * bank_size + (bank_address.index() % bank_size)]
-            }
-            _ => {
-                panic!("Out of addressable range: {:?}", address);
-            }
-        }
+        self.mapper.borrow_mut().cpu_read(address)
     }
 
     fn write(&mut self, address: Address, byte: u8) {
-        match address.index() {
-            0x6000..=0x7fff => {
-                self.ram[address.index() - 0x6000] = byte;
-            }
-            0x8000..=0xffff => match &mut self.bank_switcher {
-                BankSwitcher::First => {
-                    self.bank_mapping[0] = byte;
-                }
-                // MMC1 mapper uses a serial interface, where bits are shifted into a shift register.
-                // After 5 writes, the shift register is used to update a register.
-                BankSwitcher::MMC1 {
-                    shift_register,
-                    writes,
-                } => {
-                    let reset = (byte >> 7) & 1 == 1;
-                    if reset {
-                        *shift_register = 0;
-                        *writes = 0;
-                    } else {
-                        *shift_register >>= 1;
-                        *shift_register |= (byte & 1) << 4;
-                        *writes += 1;
-                        if *writes == 5 {
-                            // TODO: support other MMC1 registers
-                            match address.index() {
-                                0x8000..=0x9fff => {
-                                    // TODO: support MMC1 control
-                                }
-                                0xa000..=0xbfff => {
-                                    if *shift_register != 0 {
-                                        todo!("Support MMC1 CHR bank 0");
-                                    }
-                                }
-                                0xc000..=0xdfff => {
-                                    todo!("Support MMC1 CHR bank 1");
-                                }
-                                0xe000..=0xffff => {
-                                    self.bank_mapping[0] = *shift_register & 0b1111;
-                                }
-                                _ => {
-                                    panic!("Out of addressable range: {:?}", address);
-                                }
-                            }
-
-                            *shift_register = 0;
-                            *writes = 0;
-                        }
-                    }
-                }
-            },
-            _ => {
-                panic!("Out of addressable range: {:?}", address);
-            }
-        }
+        self.mapper.borrow_mut().cpu_write(address, byte);
     }
-}
 
-enum BankSwitcher {
-    First,
-    MMC1 { shift_register: u8, writes: u8 },
+    // Mappers only change state when written to, so cartridge space can be peeked at
+    fn peek(&self, address: Address) -> Option<u8> {
+        (address >= Address::new(0x6000)).then(|| self.mapper.borrow_mut().cpu_read(address))
+    }
 }
 
 /// Character memory on a NES cartridge, stores pattern tables and is connected to the PPU
 pub struct CHR {
-    chr_rom: Box<[u8]>,
-    chr_ram_enabled: bool,
-    ppu_ram: [u8; 0x800],
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    mirroring: Mirroring,
+    // 2KB of nametable RAM in the NES, plus 2KB for cartridges with four-screen mirroring
+    ppu_ram: [u8; 0x1000],
+    // Address line A12 on the last pattern table access
+    a12: bool,
+}
+
+impl CHR {
+    pub fn clear_nametable_ram(&mut self) {
+        self.ppu_ram = [0; 0x1000];
+    }
+
+    /// Watch pattern table accesses, notifying the mapper when A12 rises.
+    fn observe_a12(&mut self, address: Address) {
+        let a12 = address.index() & 0x1000 != 0;
+        if a12 && !self.a12 {
+            self.mapper.borrow_mut().notify_a12_rise();
+        }
+        self.a12 = a12;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            Mirroring::FourScreen => Mirroring::FourScreen,
+            mirroring => self.mapper.borrow().mirroring().unwrap_or(mirroring),
+        }
+    }
+
+    fn nametable_index(&self, address: Address) -> usize {
+        let offset = (address.index() - 0x2000) % 0x1000;
+        match self.mirroring() {
+            Mirroring::Horizontal => (offset / 0x800) * 0x400 + offset % 0x400,
+            Mirroring::Vertical => offset % 0x800,
+            Mirroring::SingleScreenA => offset % 0x400,
+            Mirroring::SingleScreenB => 0x400 + offset % 0x400,
+            Mirroring::FourScreen => offset,
+        }
+    }
+}
+
+/// Saved state of [CHR]. The mapper is saved with [PRG].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CHRState {
+    #[serde(with = "crate::state::array")]
+    ppu_ram: [u8; 0x1000],
+    a12: bool,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CHR {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let state = CHRState {
+            ppu_ram: self.ppu_ram,
+            a12: self.a12,
+        };
+        state.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CHR {
+    pub(crate) fn restore(&mut self, state: CHRState) {
+        self.ppu_ram = state.ppu_ram;
+        self.a12 = state.a12;
+    }
 }
 
 impl Debug for CHR {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CHR")
-            .field("chr_ram_enabled", &self.chr_ram_enabled)
+            .field("mirroring", &self.mirroring())
             .finish()
     }
 }
@@ -182,24 +304,33 @@ impl Debug for CHR {
 impl Memory for CHR {
     fn read(&mut self, address: Address) -> u8 {
         match address.index() {
-            0x0000..=0x1fff => self.chr_rom[address.index()],
-            0x2000..=0x3eff => self.ppu_ram[(address.index() - 0x2000) % 0x800],
+            0x0000..=0x1fff => {
+                self.observe_a12(address);
+                self.mapper.borrow_mut().ppu_read(address)
+            }
+            0x2000..=0x3eff => self.ppu_ram[self.nametable_index(address)],
             _ => {
                 panic!("Out of addressable range: {:?}", address);
             }
         }
     }
 
+    fn peek(&self, address: Address) -> Option<u8> {
+        // Skips observing A12, so the mapper doesn't count this as a PPU fetch
+        match address.index() {
+            0x0000..=0x1fff => Some(self.mapper.borrow_mut().ppu_read(address)),
+            0x2000..=0x3eff => Some(self.ppu_ram[self.nametable_index(address)]),
+            _ => None,
+        }
+    }
+
     fn write(&mut self, address: Address, byte: u8) {
         match address.index() {
             0x0000..=0x1fff => {
-                debug_assert!(
-                    self.chr_ram_enabled,
-                    "Attempted to write to CHR-ROM, but writing is not enabled"
-                );
-                self.chr_rom[address.index()] = byte
+                self.observe_a12(address);
+                self.mapper.borrow_mut().ppu_write(address, byte);
             }
-            0x2000..=0x3eff => self.ppu_ram[(address.index() - 0x2000) % 0x800] = byte,
+            0x2000..=0x3eff => self.ppu_ram[self.nametable_index(address)] = byte,
             _ => {
                 panic!("Out of addressable range: {:?}", address);
             }
@@ -209,7 +340,9 @@ impl Memory for CHR {
 
 #[cfg(test)]
 mod tests {
-    use crate::mapper::Mapper;
+    use std::cell::Cell;
+
+    use crate::mapper::MapperKind;
     use crate::Address;
 
     use super::*;
@@ -218,28 +351,26 @@ mod tests {
     fn cartridge_is_constructed_from_prg_rom_chr_rom_and_mapper() {
         let prg_rom = Box::new([0u8; 1024]);
         let chr_rom = Box::new([0u8; 1024]);
-        let mapper = Mapper::NROM;
+        let mapper = MapperKind::NROM;
         Cartridge::new(prg_rom, chr_rom, false, mapper);
     }
 
     #[test]
     fn rom_cartridge_maps_0x6000_through_0x7fff_to_prg_ram() {
-        let mut prg = nrom_cartridge().prg;
+        let (mut prg, _) = nrom_cartridge().split();
 
         for value in 0x6000..=0x7fff {
             prg.write(Address::new(value), value as u8);
             assert_eq!(prg.read(Address::new(value)), value as u8);
-            assert_eq!(prg.ram[value as usize - 0x6000], value as u8);
+            assert_eq!(prg.ram()[value as usize - 0x6000], value as u8);
         }
     }
 
     #[test]
     fn nrom_cartridge_maps_0x8000_through_0xffff_to_prg_rom() {
-        let mut prg = nrom_cartridge().prg;
-
-        for (i, item) in prg.rom.iter_mut().enumerate() {
-            *item = i as u8;
-        }
+        let prg_rom = (0..0x8000).map(|i| i as u8).collect();
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, _) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::NROM).split();
 
         for value in 0x8000..=0xffff {
             assert_eq!(prg.read(Address::new(value)), value as u8);
@@ -247,43 +378,100 @@ mod tests {
     }
 
     #[test]
-    fn nrom_cartridge_mirrors_rom_if_not_large_enough() {
-        let mut prg_rom = Box::new([0u8; 0x4000]);
-        let chr_rom = Box::new([0u8; 0x8000]);
-        let mapper = Mapper::NROM;
+    fn nrom_cartridge_maps_0x0000_through_0x1fff_to_chr_rom() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = (0..0x2000).map(|i| i as u8).collect();
+        let (_, mut chr) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::NROM).split();
 
-        for (i, item) in prg_rom.iter_mut().enumerate() {
-            *item = i as u8;
+        for value in 0x0000..=0x1fff {
+            assert_eq!(chr.read(Address::new(value)), value as u8);
         }
+    }
 
-        let mut prg = Cartridge::new(prg_rom, chr_rom, false, mapper).prg;
+    #[test]
+    fn prg_and_chr_share_the_cartridge_mapper() {
+        let prg_rom = (0..0x10000).map(|i| (i / 0x4000) as u8).collect();
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, _) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::UxROM).split();
 
-        for value in 0xc000..=0xffff {
-            assert_eq!(prg.read(Address::new(value)), value as u8);
-        }
+        prg.write(Address::new(0x8000), 2);
+
+        assert_eq!(prg.read(Address::new(0x8000)), 2);
+        assert_eq!(
+            prg.windows()[0],
+            (Address::new(0x8000), Address::new(0xbfff), 2)
+        );
     }
 
     #[test]
-    fn nrom_cartridge_maps_0x0000_through_0x1fff_to_chr_rom() {
-        let mut chr = nrom_cartridge().chr;
+    fn nametable_mirroring_is_controlled_by_the_mapper() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, mut chr) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::MMC1).split();
 
-        for (i, item) in chr.chr_rom.iter_mut().enumerate() {
-            *item = i as u8;
+        // Set MMC1 control to horizontal mirroring, one bit at a time
+        for bit in [1, 1, 0, 0, 0] {
+            prg.write(Address::new(0x8000), bit);
         }
 
-        for value in 0x0000..=0x1fff {
-            assert_eq!(chr.read(Address::new(value)), value as u8);
+        chr.write(Address::new(0x2000), 1);
+        chr.write(Address::new(0x2800), 2);
+        assert_eq!(chr.read(Address::new(0x2400)), 1);
+        assert_eq!(chr.read(Address::new(0x2c00)), 2);
+
+        // Then single-screen mirroring of the second nametable
+        for bit in [1, 0, 0, 0, 0] {
+            prg.write(Address::new(0x8000), bit);
         }
+
+        assert_eq!(chr.read(Address::new(0x2000)), 2);
+        assert_eq!(chr.read(Address::new(0x2400)), 2);
     }
 
     #[test]
-    fn nrom_cartridge_maps_0x2000_through_0x27ff_to_ppu_ram() {
-        let mut chr = nrom_cartridge().chr;
+    fn horizontal_mirroring_mirrors_0x2000_to_0x2400() {
+        let (_, mut chr) = nrom_cartridge()
+            .with_mirroring(Mirroring::Horizontal)
+            .split();
+
+        chr.write(Address::new(0x2000), 1);
+        chr.write(Address::new(0x2800), 2);
+
+        assert_eq!(chr.read(Address::new(0x2400)), 1);
+        assert_eq!(chr.read(Address::new(0x2c00)), 2);
+    }
+
+    #[test]
+    fn vertical_mirroring_mirrors_0x2000_to_0x2800() {
+        let (_, mut chr) = nrom_cartridge().with_mirroring(Mirroring::Vertical).split();
 
-        for (i, item) in chr.ppu_ram.iter_mut().enumerate() {
-            *item = i as u8;
+        chr.write(Address::new(0x2000), 1);
+        chr.write(Address::new(0x2400), 2);
+
+        assert_eq!(chr.read(Address::new(0x2800)), 1);
+        assert_eq!(chr.read(Address::new(0x2c00)), 2);
+    }
+
+    #[test]
+    fn four_screen_mirroring_has_four_separate_nametables() {
+        let (_, mut chr) = nrom_cartridge()
+            .with_mirroring(Mirroring::FourScreen)
+            .split();
+
+        for (i, nametable) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            chr.write(Address::new(nametable), i as u8);
         }
 
+        for (i, nametable) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            assert_eq!(chr.read(Address::new(nametable)), i as u8);
+            assert_eq!(chr.read(Address::new(nametable + 0x1000)), i as u8);
+        }
+    }
+
+    #[test]
+    fn nrom_cartridge_maps_0x2000_through_0x27ff_to_ppu_ram() {
+        let (_, mut chr) = nrom_cartridge().split();
+
         for value in 0x2000..=0x27ff {
             chr.write(Address::new(value), value as u8);
             assert_eq!(chr.read(Address::new(value)), value as u8);
@@ -293,11 +481,7 @@ mod tests {
 
     #[test]
     fn nrom_cartridge_mirrors_0x2800_through_0x2fff_to_ppu_ram() {
-        let mut chr = nrom_cartridge().chr;
-
-        for (i, item) in chr.ppu_ram.iter_mut().enumerate() {
-            *item = i as u8;
-        }
+        let (_, mut chr) = nrom_cartridge().split();
 
         for value in 0x2800..=0x2fff {
             chr.write(Address::new(value), value as u8);
@@ -308,11 +492,7 @@ mod tests {
 
     #[test]
     fn nrom_cartridge_mirrors_0x3000_through_0x3eff_to_ppu_ram() {
-        let mut chr = nrom_cartridge().chr;
-
-        for (i, item) in chr.ppu_ram.iter_mut().enumerate() {
-            *item = i as u8;
-        }
+        let (_, mut chr) = nrom_cartridge().split();
 
         for value in 0x3000..=0x37ff {
             chr.write(Address::new(value), value as u8);
@@ -327,17 +507,167 @@ mod tests {
         }
     }
 
+    #[test]
+    fn a12_rises_once_per_scanline_with_sprites_in_upper_pattern_table() {
+        let (rises, mut chr) = a12_counting_chr();
+
+        let per_scanline: Vec<u32> = (0..4)
+            .map(|_| {
+                let before = rises.get();
+                fetch_scanline(&mut chr, 0x0000, 0x1000);
+                rises.get() - before
+            })
+            .collect();
+
+        assert_eq!(per_scanline, [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn a12_rises_once_per_scanline_with_background_in_upper_pattern_table() {
+        let (rises, mut chr) = a12_counting_chr();
+
+        let per_scanline: Vec<u32> = (0..4)
+            .map(|_| {
+                let before = rises.get();
+                fetch_scanline(&mut chr, 0x1000, 0x0000);
+                rises.get() - before
+            })
+            .collect();
+
+        // The first scanline also rises on its first background fetch
+        assert_eq!(per_scanline, [2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn a12_never_rises_when_all_fetches_use_the_same_pattern_table() {
+        let (rises, mut chr) = a12_counting_chr();
+
+        for _ in 0..4 {
+            fetch_scanline(&mut chr, 0x0000, 0x0000);
+        }
+        assert_eq!(rises.get(), 0);
+
+        for _ in 0..4 {
+            fetch_scanline(&mut chr, 0x1000, 0x1000);
+        }
+        assert_eq!(rises.get(), 1);
+    }
+
+    #[test]
+    fn mmc3_irq_counter_is_only_clocked_when_a12_rises() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, mut chr) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::MMC3).split();
+
+        prg.write(Address::new(0xc000), 1);
+        prg.write(Address::new(0xe001), 0);
+
+        // Reload on the first rise, then staying high doesn't clock the counter
+        chr.read(Address::new(0x1000));
+        chr.read(Address::new(0x1010));
+        chr.read(Address::new(0x1020));
+        assert!(!prg.irq());
+
+        chr.read(Address::new(0x0000));
+        chr.read(Address::new(0x1000));
+        assert!(prg.irq());
+    }
+
     #[test]
     #[should_panic]
     fn nrom_cartridge_cannot_write_to_read_only_memory() {
-        let mut prg = nrom_cartridge().prg;
+        let (mut prg, _) = nrom_cartridge().split();
         prg.write(Address::new(0x5000), 10);
     }
 
+    /// Counts A12 rises it's notified of
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct A12Counter {
+        #[cfg_attr(feature = "serde", serde(skip))]
+        rises: Rc<Cell<u32>>,
+        prg_ram: Vec<u8>,
+    }
+
+    #[cfg(feature = "serde")]
+    impl crate::mapper::SavedWithoutRom for A12Counter {
+        fn restore_rom(&mut self, _current: &Self) -> Result<(), StateError> {
+            Ok(())
+        }
+    }
+
+    impl Mapper for A12Counter {
+        fn cpu_read(&mut self, _address: Address) -> u8 {
+            0
+        }
+
+        fn cpu_write(&mut self, _address: Address, _byte: u8) {}
+
+        fn ppu_read(&mut self, _address: Address) -> u8 {
+            0
+        }
+
+        fn ppu_write(&mut self, _address: Address, _byte: u8) {}
+
+        fn notify_a12_rise(&mut self) {
+            self.rises.set(self.rises.get() + 1);
+        }
+
+        fn prg_rom(&self) -> &[u8] {
+            &[]
+        }
+
+        fn chr(&self) -> &[u8] {
+            &[]
+        }
+
+        fn prg_ram(&mut self) -> &mut [u8] {
+            &mut self.prg_ram
+        }
+
+        fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+            vec![]
+        }
+    }
+
+    fn a12_counting_chr() -> (Rc<Cell<u32>>, CHR) {
+        let counter = A12Counter::default();
+        let rises = counter.rises.clone();
+        let mut cartridge = nrom_cartridge();
+        cartridge.mapper = Box::new(counter);
+        (rises, cartridge.split().1)
+    }
+
+    /// Make the accesses the PPU makes to render a scanline, fetching background tiles and sprites
+    /// from the given pattern tables
+    fn fetch_scanline(chr: &mut CHR, background: u16, sprites: u16) {
+        let fetch_tile = |chr: &mut CHR, tile: u16| {
+            chr.read(Address::new(0x2000 + tile));
+            chr.read(Address::new(0x23c0 + tile / 4));
+            chr.read(Address::new(background + tile * 16));
+            chr.read(Address::new(background + tile * 16 + 8));
+        };
+
+        for tile in 0..32 {
+            fetch_tile(chr, tile);
+        }
+        for sprite in 0..8 {
+            chr.read(Address::new(0x2000));
+            chr.read(Address::new(0x2000));
+            chr.read(Address::new(sprites + sprite * 16));
+            chr.read(Address::new(sprites + sprite * 16 + 8));
+        }
+        for tile in 0..2 {
+            fetch_tile(chr, tile);
+        }
+        chr.read(Address::new(0x2000));
+        chr.read(Address::new(0x2000));
+    }
+
     fn nrom_cartridge() -> Cartridge {
         let prg_rom = Box::new([0u8; 0x8000]);
         let chr_rom = Box::new([0u8; 0x8000]);
-        let mapper = Mapper::NROM;
+        let mapper = MapperKind::NROM;
         Cartridge::new(prg_rom, chr_rom, false, mapper)
     }
 }
@@ -24,6 +24,16 @@ macro_rules! def_addressing_modes {
                 }
             }
         }
+
+        impl From<$name> for AddressingMode {
+            fn from(mode: $name) -> Self {
+                match mode {
+                    $(
+                    $name::$mode => AddressingMode::$mode,
+                    )*
+                }
+            }
+        }
         )*
     };
 }
@@ -135,8 +145,8 @@ impl JumpAddressingMode {
     }
 }
 
-#[derive(Copy, Clone)]
-enum AddressingMode {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum AddressingMode {
     Accumulator,
     Immediate,
     ZeroPage,
@@ -167,13 +177,13 @@ impl<M: Memory> CPU<M> {
             }
             AddressingMode::ZeroPageX => {
                 let operand: u8 = self.incr_program_counter();
-                self.read(Address::from_bytes(0, operand)); // CPU does a pointless read
+                self.dummy_read(Address::from_bytes(0, operand)); // CPU does a pointless read
                 let address = Address::from_bytes(0, operand.wrapping_add(self.x));
                 Reference::Address(address)
             }
             AddressingMode::ZeroPageY => {
                 let operand: u8 = self.incr_program_counter();
-                self.read(Address::from_bytes(0, operand)); // CPU does a pointless read
+                self.dummy_read(Address::from_bytes(0, operand)); // CPU does a pointless read
                 let address = Address::from_bytes(0, operand.wrapping_add(self.y));
                 Reference::Address(address)
             }
@@ -193,7 +203,7 @@ impl<M: Memory> CPU<M> {
             }
             AddressingMode::IndexedIndirect => {
                 let offset = self.incr_program_counter();
-                self.read(Address::from_bytes(0, offset)); // Redundant read
+                self.dummy_read(Address::from_bytes(0, offset)); // Redundant read
                 let address = self.read_zero_page_address(offset.wrapping_add(self.x));
                 Reference::Address(address)
             }
@@ -210,7 +220,10 @@ impl<M: Memory> CPU<M> {
 
     fn indirect_address(&mut self) -> Address {
         let address_of_address = self.fetch_address_at_program_counter();
-        self.read_address(address_of_address)
+        let lower = self.read(address_of_address);
+        // The 6502 doesn't carry into the high byte, so JMP ($10FF) reads the high byte from $1000
+        let higher = self.read(address_of_address.incr_lower());
+        Address::from_bytes(higher, lower)
     }
 
     fn read_zero_page_address(&mut self, offset: u8) -> Address {
@@ -30,16 +30,138 @@ impl<M: Memory> CPU<M> {
         let value = self.fetch(addressing_mode);
         self.compare(self.y, value);
     }
+
+    // Unofficial Opcodes
+    pub(in crate::cpu) fn axs(&mut self) {
+        let value = self.incr_program_counter();
+        let register = self.accumulator & self.x;
+        self.compare(register, value);
+        self.x = register.wrapping_sub(value);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         cpu::{tests::run_instr, Status},
-        instructions::{ADC_IMM, CMP_IMM, CPX_IMM, CPY_IMM, SBC_IMM},
+        instructions::{ADC_IMM, AXS, CMP_IMM, CPX_IMM, CPY_IMM, SBC_IMM},
         mem,
     };
 
+    #[test]
+    fn instr_axs_subtracts_from_accumulator_and_x_into_x() {
+        let cpu = run_instr(mem!(AXS, 2u8), |cpu| {
+            cpu.accumulator = 0b0000_1110;
+            cpu.x = 0b0000_0111;
+        });
+
+        assert_eq!(cpu.x, 4);
+        assert_eq!(cpu.accumulator, 0b0000_1110);
+        assert!(cpu.status.contains(Status::CARRY));
+        assert!(!cpu.status.contains(Status::ZERO));
+    }
+
+    #[test]
+    fn instr_axs_clears_carry_on_borrow_ignoring_carry_in() {
+        let cpu = run_instr(mem!(AXS, 7u8), |cpu| {
+            cpu.accumulator = 0b0000_1110;
+            cpu.x = 0b0000_0111;
+        });
+
+        assert_eq!(cpu.x, 0xFF);
+        assert!(!cpu.status.contains(Status::CARRY));
+        assert!(cpu.status.contains(Status::NEGATIVE));
+    }
+
+    #[test]
+    fn instr_adc_ignores_decimal_flag_by_default() {
+        let cpu = run_instr(mem!(ADC_IMM, 0x01u8), |cpu| {
+            cpu.accumulator = 0x09;
+            cpu.status.insert(Status::DECIMAL);
+        });
+
+        assert_eq!(cpu.accumulator, 0x0a);
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn instr_adc_adds_binary_coded_decimal_when_enabled() {
+        let cases = [
+            // (accumulator, value, carry in, result, carry out)
+            (0x09, 0x01, false, 0x10, false),
+            (0x12, 0x34, false, 0x46, false),
+            (0x58, 0x46, true, 0x05, true),
+            (0x99, 0x01, false, 0x00, true),
+            (0x50, 0x50, false, 0x00, true),
+            (0x79, 0x00, true, 0x80, false),
+            (0x00, 0x00, false, 0x00, false),
+        ];
+
+        for (accumulator, value, carry, result, carry_out) in cases {
+            let cpu = run_instr(mem!(ADC_IMM, value), |cpu| {
+                cpu.set_decimal_enabled(true);
+                cpu.accumulator = accumulator;
+                cpu.status.insert(Status::DECIMAL);
+                cpu.status.set(Status::CARRY, carry);
+            });
+
+            let case = format!("{:#04x} + {:#04x} + {}", accumulator, value, carry);
+            assert_eq!(cpu.accumulator, result, "{}", case);
+            assert_eq!(cpu.status.contains(Status::CARRY), carry_out, "{}", case);
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn instr_adc_sets_binary_coded_decimal_flags_like_6502() {
+        let cpu = run_instr(mem!(ADC_IMM, 0x01u8), |cpu| {
+            cpu.set_decimal_enabled(true);
+            cpu.accumulator = 0x99;
+            cpu.status.insert(Status::DECIMAL);
+        });
+
+        // The zero flag comes from the binary result, negative from before adjusting the high digit
+        assert_eq!(cpu.accumulator, 0x00);
+        assert!(!cpu.status.contains(Status::ZERO));
+        assert!(cpu.status.contains(Status::NEGATIVE));
+
+        let cpu = run_instr(mem!(ADC_IMM, 0x10u8), |cpu| {
+            cpu.set_decimal_enabled(true);
+            cpu.accumulator = 0x70;
+            cpu.status.insert(Status::DECIMAL);
+        });
+
+        assert_eq!(cpu.accumulator, 0x80);
+        assert!(cpu.status.contains(Status::OVERFLOW));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn instr_sbc_subtracts_binary_coded_decimal_when_enabled() {
+        let cases = [
+            // (accumulator, value, carry in, result, carry out)
+            (0x46, 0x12, true, 0x34, true),
+            (0x40, 0x13, true, 0x27, true),
+            (0x32, 0x02, false, 0x29, true),
+            (0x00, 0x01, true, 0x99, false),
+            (0x10, 0x10, true, 0x00, true),
+            (0x21, 0x34, true, 0x87, false),
+        ];
+
+        for (accumulator, value, carry, result, carry_out) in cases {
+            let cpu = run_instr(mem!(SBC_IMM, value), |cpu| {
+                cpu.set_decimal_enabled(true);
+                cpu.accumulator = accumulator;
+                cpu.status.insert(Status::DECIMAL);
+                cpu.status.set(Status::CARRY, carry);
+            });
+
+            let case = format!("{:#04x} - {:#04x} - {}", accumulator, value, !carry);
+            assert_eq!(cpu.accumulator, result, "{}", case);
+            assert_eq!(cpu.status.contains(Status::CARRY), carry_out, "{}", case);
+        }
+    }
+
     #[test]
     fn instr_adc_adds_numbers() {
         let cpu = run_instr(mem!(ADC_IMM, 10u8), |cpu| {
@@ -53,9 +53,57 @@ mod tests {
     use crate::{
         cpu::{tests::run_instr, Status},
         instructions::{BCC, BCS, BEQ, BMI, BNE, BPL, BVC, BVS},
-        mem, Address,
+        mem, Address, ArrayMemory, Instruction, CPU,
     };
 
+    // Each branch with a status that makes it branch and a status that doesn't
+    const BRANCHES: [(Instruction, Status, Status); 8] = [
+        (BCC, Status::empty(), Status::CARRY),
+        (BCS, Status::CARRY, Status::empty()),
+        (BEQ, Status::ZERO, Status::empty()),
+        (BMI, Status::NEGATIVE, Status::empty()),
+        (BNE, Status::empty(), Status::ZERO),
+        (BPL, Status::empty(), Status::NEGATIVE),
+        (BVC, Status::empty(), Status::OVERFLOW),
+        (BVS, Status::OVERFLOW, Status::empty()),
+    ];
+
+    #[test]
+    fn branch_not_taken_takes_2_cycles() {
+        for (instruction, _, not_taken) in BRANCHES {
+            let cycles = branch_cycles(instruction, not_taken, 0x1000, 0x10);
+            assert_eq!(cycles, 2, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn branch_taken_to_same_page_takes_3_cycles() {
+        for (instruction, taken, _) in BRANCHES {
+            let cycles = branch_cycles(instruction, taken, 0x1000, 0x10);
+            assert_eq!(cycles, 3, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn branch_taken_to_different_page_takes_4_cycles() {
+        for (instruction, taken, _) in BRANCHES {
+            let forward = branch_cycles(instruction, taken, 0x10F0, 0x10);
+            assert_eq!(forward, 4, "{:?}", instruction);
+
+            let backward = branch_cycles(instruction, taken, 0x1000, -0x10i8 as u8);
+            assert_eq!(backward, 4, "{:?}", instruction);
+        }
+    }
+
+    fn branch_cycles(instruction: Instruction, status: Status, address: u16, offset: u8) -> u8 {
+        let mut cpu = CPU::from_memory(ArrayMemory::default());
+        cpu.write(Address::new(address), instruction.to_opcode());
+        cpu.write(Address::new(address + 1), offset);
+        cpu.program_counter = Address::new(address);
+        cpu.status = status;
+        cpu.run_instruction()
+    }
+
     #[test]
     fn instr_bcc_branches_when_carry_flag_clear() {
         let cpu = run_instr(mem!(90 => { BCC, -10i8 as u8 }), |cpu| {
@@ -0,0 +1,226 @@
+//! Disassembly of instructions into human-readable assembly
+
+use crate::cpu::addressing_modes::AddressingMode;
+use crate::{Address, Instruction, Memory};
+
+/// Decode the instruction at the given address.
+///
+/// Returns the instruction, its assembly (e.g. `CPX #$12`) and its length in bytes.
+pub fn disassemble<M: Memory>(memory: &mut M, address: Address) -> (Instruction, String, u8) {
+    let instruction = Instruction::from_opcode(memory.read(address));
+    let operand = Operand::of(instruction);
+    let size = instruction.size();
+
+    let lower = if size > 1 {
+        memory.read(address + 1)
+    } else {
+        0
+    };
+    let higher = if size > 2 {
+        memory.read(address + 2)
+    } else {
+        0
+    };
+
+    let mnemonic = format!("{:?}", instruction);
+    let mnemonic = mnemonic.split('(').next().unwrap_or_default();
+
+    let text = match operand.format(address + u16::from(size), higher, lower) {
+        Some(operand) => format!("{} {}", mnemonic, operand),
+        None => mnemonic.to_owned(),
+    };
+
+    (instruction, text, size)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(super) enum Operand {
+    Implied,
+    /// Branch offset, relative to the next instruction.
+    Relative,
+    Addressed(AddressingMode),
+}
+
+impl Operand {
+    pub(super) fn of(instruction: Instruction) -> Self {
+        use Instruction::*;
+
+        let mode: AddressingMode =
+            match instruction {
+                LDA(mode) | AND(mode) | EOR(mode) | ORA(mode) | ADC(mode) | SBC(mode)
+                | CMP(mode) => mode.into(),
+                STA(mode) | DCP(mode) | ISC(mode) | SLO(mode) | RLA(mode) | SRE(mode)
+                | RRA(mode) => mode.into(),
+                ASL(mode) | LSR(mode) | ROL(mode) | ROR(mode) => mode.into(),
+                INC(mode) | DEC(mode) | IGN(mode) => mode.into(),
+                CPX(mode) | CPY(mode) => mode.into(),
+                LDX(mode) => mode.into(),
+                LDY(mode) => mode.into(),
+                STX(mode) => mode.into(),
+                STY(mode) => mode.into(),
+                BIT(mode) => mode.into(),
+                JMP(mode) => mode.into(),
+                LAX(mode) => mode.into(),
+                SAX(mode) => mode.into(),
+                JSR => AddressingMode::Absolute,
+                SKB | ANC | ALR | ARR | AXS => AddressingMode::Immediate,
+                BCC | BCS | BEQ | BMI | BNE | BPL | BVC | BVS => return Operand::Relative,
+                TAX | TAY | TXA | TYA | TSX | TXS | PHA | PHP | PLA | PLP | INX | INY | DEX
+                | DEY | RTS | CLC | CLD | CLI | CLV | SEC | SED | SEI | BRK | NOP | RTI | JAM => {
+                    return Operand::Implied
+                }
+            };
+
+        Operand::Addressed(mode)
+    }
+
+    /// Number of bytes following the opcode.
+    pub(super) fn size(self) -> u8 {
+        use AddressingMode::*;
+
+        match self {
+            Operand::Implied | Operand::Addressed(Accumulator) => 0,
+            Operand::Relative => 1,
+            Operand::Addressed(Immediate | ZeroPage | ZeroPageX | ZeroPageY) => 1,
+            Operand::Addressed(IndexedIndirect | IndirectIndexed) => 1,
+            Operand::Addressed(Absolute | AbsoluteX | AbsoluteY | Indirect) => 2,
+        }
+    }
+
+    fn format(self, next_instruction: Address, higher: u8, lower: u8) -> Option<String> {
+        use AddressingMode::*;
+
+        let address = Address::from_bytes(higher, lower);
+
+        let text = match self {
+            Operand::Implied => return None,
+            Operand::Relative => {
+                let target = next_instruction.index() as i32 + i32::from(lower as i8);
+                format!("${:04X}", target as u16)
+            }
+            Operand::Addressed(mode) => match mode {
+                Accumulator => "A".to_owned(),
+                Immediate => format!("#${:02X}", lower),
+                ZeroPage => format!("${:02X}", lower),
+                ZeroPageX => format!("${:02X},X", lower),
+                ZeroPageY => format!("${:02X},Y", lower),
+                Absolute => format!("${:04X}", address.index()),
+                AbsoluteX => format!("${:04X},X", address.index()),
+                AbsoluteY => format!("${:04X},Y", address.index()),
+                Indirect => format!("(${:04X})", address.index()),
+                IndexedIndirect => format!("(${:02X},X)", lower),
+                IndirectIndexed => format!("(${:02X}),Y", lower),
+            },
+        };
+
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::*;
+    use crate::mem;
+
+    use super::*;
+
+    #[test]
+    fn disassembles_implied_instruction() {
+        let mut memory = mem!(0x1234 => { INX });
+
+        let (instruction, text, size) = disassemble(&mut memory, Address::new(0x1234));
+
+        assert_eq!(instruction, INX);
+        assert_eq!(text, "INX");
+        assert_eq!(size, 1);
+    }
+
+    #[test]
+    fn disassembles_accumulator_instruction() {
+        let mut memory = mem!(LSR_ACC);
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(0));
+
+        assert_eq!(text, "LSR A");
+        assert_eq!(size, 1);
+    }
+
+    #[test]
+    fn disassembles_immediate_instruction() {
+        let mut memory = mem!(0xE084 => { CPX_IMM, 0x12 });
+
+        let (instruction, text, size) = disassemble(&mut memory, Address::new(0xE084));
+
+        assert_eq!(instruction, CPX_IMM);
+        assert_eq!(text, "CPX #$12");
+        assert_eq!(size, 2);
+    }
+
+    #[test]
+    fn disassembles_zero_page_instruction() {
+        let mut memory = mem!(STX_ZPY, 0x80);
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(0));
+
+        assert_eq!(text, "STX $80,Y");
+        assert_eq!(size, 2);
+    }
+
+    #[test]
+    fn disassembles_absolute_instruction() {
+        let mut memory = mem!(LDA_ABX, 0x34, 0x12);
+
+        let (instruction, text, size) = disassemble(&mut memory, Address::new(0));
+
+        assert_eq!(instruction, LDA_ABX);
+        assert_eq!(text, "LDA $1234,X");
+        assert_eq!(size, 3);
+    }
+
+    #[test]
+    fn disassembles_jsr_as_absolute() {
+        let mut memory = mem!(JSR, 0x00, 0xC0);
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(0));
+
+        assert_eq!(text, "JSR $C000");
+        assert_eq!(size, 3);
+    }
+
+    #[test]
+    fn disassembles_indirect_instructions() {
+        let mut memory = mem!(
+            0 => { JMP_IND, 0xFF, 0x02 }
+            3 => { LDA_IDX, 0x40 }
+            5 => { STA_IDY, 0x41 }
+        );
+
+        let (instruction, text, size) = disassemble(&mut memory, Address::new(0));
+        assert_eq!(instruction, JMP_IND);
+        assert_eq!(text, "JMP ($02FF)");
+        assert_eq!(size, 3);
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(3));
+        assert_eq!(text, "LDA ($40,X)");
+        assert_eq!(size, 2);
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(5));
+        assert_eq!(text, "STA ($41),Y");
+        assert_eq!(size, 2);
+    }
+
+    #[test]
+    fn disassembles_branch_with_target_address() {
+        let mut memory = mem!(
+            0x8000 => { BNE, 0xFB }
+            0x8002 => { BEQ, 0x10 }
+        );
+
+        let (_, text, size) = disassemble(&mut memory, Address::new(0x8000));
+        assert_eq!(text, "BNE $7FFD");
+        assert_eq!(size, 2);
+
+        let (_, text, _) = disassemble(&mut memory, Address::new(0x8002));
+        assert_eq!(text, "BEQ $8014");
+    }
+}
@@ -42,28 +42,30 @@ impl<M: Memory> CPU<M> {
     // Unofficial Opcodes
     pub(in crate::cpu) fn dcp(&mut self, addressing_mode: StoreAddressingMode) {
         let reference = self.fetch_ref(addressing_mode);
-        self.decrement(reference);
-        let value = self.read_reference(reference, false);
+        let value = self.decrement(reference);
         self.compare(self.accumulator, value);
     }
 
     pub(in crate::cpu) fn isc(&mut self, addressing_mode: StoreAddressingMode) {
         let reference = self.fetch_ref(addressing_mode);
-        self.increment(reference);
-        let value = self.read_reference(reference, false);
+        let value = self.increment(reference);
         self.sub_from_accumulator(value);
     }
 
-    fn increment(&mut self, reference: Reference) {
+    fn increment(&mut self, reference: Reference) -> u8 {
         let value = self.read_reference(reference, false);
         self.set_reference(reference, value, false); // redundant write
-        self.set_reference(reference, value.wrapping_add(1), false);
+        let value = value.wrapping_add(1);
+        self.set_reference(reference, value, false);
+        value
     }
 
-    fn decrement(&mut self, reference: Reference) {
+    fn decrement(&mut self, reference: Reference) -> u8 {
         let value = self.read_reference(reference, false);
         self.set_reference(reference, value, false); // redundant write
-        self.set_reference(reference, value.wrapping_sub(1), false);
+        let value = value.wrapping_sub(1);
+        self.set_reference(reference, value, false);
+        value
     }
 }
 
@@ -67,6 +67,23 @@ mod tests {
         assert_eq!(cpu.program_counter, Address::new(10));
     }
 
+    #[test]
+    fn instr_jmp_indirect_does_not_cross_page_boundary() {
+        let cpu = run_instr(
+            mem!(
+                20 => { JMP_IND, 0xFF, 0x10 }
+                0x10FF => { 0x34 }
+                0x1100 => { 0x56 }
+                0x1000 => { 0x12 }
+            ),
+            |cpu| {
+                cpu.program_counter = Address::new(20);
+            },
+        );
+
+        assert_eq!(cpu.program_counter, Address::new(0x1234));
+    }
+
     #[test]
     fn instr_jsr_jumps_to_operand() {
         let cpu = run_instr(mem!(200 => { JSR, 100, 0 }), |cpu| {
@@ -32,13 +32,20 @@ impl<M: Memory> CPU<M> {
         self.status
             .set(Status::NEGATIVE, (value as i8).is_negative());
     }
+
+    // Unofficial Opcodes
+    pub(in crate::cpu) fn anc(&mut self) {
+        self.and(FlexibleAddressingMode::Immediate);
+        let negative = self.status.contains(Status::NEGATIVE);
+        self.status.set(Status::CARRY, negative);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         cpu::{tests::run_instr, Status},
-        instructions::{AND_IMM, BIT_ABS, EOR_IMM, ORA_IMM},
+        instructions::{ANC, AND_IMM, BIT_ABS, EOR_IMM, ORA_IMM},
         mem,
     };
 
@@ -69,6 +76,26 @@ mod tests {
         assert_eq!(cpu.accumulator, 0b1110);
     }
 
+    #[test]
+    fn instr_anc_performs_bitwise_and_and_copies_negative_to_carry() {
+        let cpu = run_instr(mem!(ANC, 0b1100_0000_u8), |cpu| {
+            cpu.accumulator = 0b1010_1010;
+        });
+
+        assert_eq!(cpu.accumulator, 0b1000_0000);
+        assert!(cpu.status.contains(Status::NEGATIVE));
+        assert!(cpu.status.contains(Status::CARRY));
+
+        let cpu = run_instr(mem!(ANC, 0b0100_0000_u8), |cpu| {
+            cpu.accumulator = 0b1010_1010;
+            cpu.status.insert(Status::CARRY);
+        });
+
+        assert_eq!(cpu.accumulator, 0);
+        assert!(cpu.status.contains(Status::ZERO));
+        assert!(!cpu.status.contains(Status::CARRY));
+    }
+
     #[test]
     fn instr_bit_sets_zero_flag_when_bitwise_and_is_zero() {
         let cpu = run_instr(
@@ -1,3 +1,5 @@
+use self::disassemble::Operand;
+use super::addressing_modes::AddressingMode;
 use super::addressing_modes::BITAddressingMode;
 use super::addressing_modes::CompareAddressingMode;
 use super::addressing_modes::FlexibleAddressingMode;
@@ -12,8 +14,11 @@ use super::addressing_modes::STYAddressingMode;
 use super::addressing_modes::ShiftAddressingMode;
 use super::addressing_modes::StoreAddressingMode;
 
+pub use self::disassemble::disassemble;
+
 pub mod arithmetic;
 pub mod branch;
+mod disassemble;
 pub mod inc_dec;
 pub mod jump;
 pub mod load_store;
@@ -484,6 +489,41 @@ pub enum Instruction {
     ///
     /// Equivalent to ROR value then ADC value, except supporting more addressing modes.
     RRA(StoreAddressingMode),
+
+    /// AND Immediate And Copy Negative To Carry
+    ///
+    /// A,Z,N = A&M, C = N
+    ///
+    /// Equivalent to AND #value, then bit 7 of the result is copied into the carry flag.
+    ANC,
+
+    /// AND Immediate And Logical Shift Right
+    ///
+    /// A,C,Z,N = (A&M)/2
+    ///
+    /// Equivalent to AND #value then LSR A.
+    ALR,
+
+    /// AND Immediate And Rotate Right
+    ///
+    /// A,Z,N = (A&M)/2 | C*128, C = A6, V = A6^A5
+    ///
+    /// Equivalent to AND #value then ROR A, except the carry flag is set from bit 6 of the result
+    /// and the overflow flag from bit 6 XOR bit 5.
+    ARR,
+
+    /// AND X Register And Subtract Immediate
+    ///
+    /// X,Z,C,N = (A&X)-M
+    ///
+    /// Subtracts a value from the bitwise AND of A and X, storing the result in X. Flags are set
+    /// like CMP, so the carry flag is not used as input.
+    AXS,
+
+    /// Jam
+    ///
+    /// Halts the CPU. It stops fetching instructions and ignores interrupts until it is reset.
+    JAM,
 }
 
 macro_rules! def_opcodes {
@@ -500,13 +540,20 @@ macro_rules! def_opcodes {
 
         impl Instruction {
             pub fn from_opcode(opcode: u8) -> Self {
+                Self::try_from_opcode(opcode)
+                    .unwrap_or_else(|| panic!("Unrecognised opcode: {:#04x}", opcode))
+            }
+
+            /// The instruction for an opcode, or `None` for the few unstable opcodes that aren't
+            /// supported.
+            pub fn try_from_opcode(opcode: u8) -> Option<Self> {
                 use super::instructions::*;
 
                 match opcode {
                     $(
-                        $num => $name,
+                        $num => Some($name),
                     )*
-                    _ => panic!("Unrecognised opcode: {:#04x}", opcode)
+                    _ => None
                 }
             }
 
@@ -522,6 +569,72 @@ macro_rules! def_opcodes {
                     )*
                 }
             }
+
+            /// Every opcode for this instruction, in ascending order.
+            pub fn all_opcodes(self) -> Vec<u8> {
+                let mut opcodes: Vec<u8> = [$($num),*]
+                    .into_iter()
+                    .filter(|&opcode| Self::from_opcode(opcode) == self)
+                    .collect();
+                opcodes.sort();
+                opcodes
+            }
+        }
+    }
+}
+
+impl Instruction {
+    /// Length of the instruction in bytes, including the opcode.
+    pub fn size(self) -> u8 {
+        1 + Operand::of(self).size()
+    }
+
+    /// Number of CPU cycles the instruction takes. Reads from an indexed address take a cycle more
+    /// when they cross a page, and branches take one or two more when they're taken.
+    pub fn base_cycles(self) -> u8 {
+        use AddressingMode::*;
+        use Instruction::*;
+
+        let mode = match Operand::of(self) {
+            Operand::Addressed(mode) => mode,
+            Operand::Relative => return 2,
+            Operand::Implied => {
+                return match self {
+                    BRK => 7,
+                    RTS | RTI => 6,
+                    PLA | PLP => 4,
+                    PHA | PHP => 3,
+                    _ => 2,
+                }
+            }
+        };
+
+        match self {
+            JSR => 6,
+            JMP(_) if mode == Indirect => 5,
+            JMP(_) => 3,
+            ASL(_) | LSR(_) | ROL(_) | ROR(_) | INC(_) | DEC(_) | DCP(_) | ISC(_) | SLO(_)
+            | RLA(_) | SRE(_) | RRA(_) => match mode {
+                Accumulator => 2,
+                ZeroPage => 5,
+                ZeroPageX | Absolute => 6,
+                AbsoluteX | AbsoluteY => 7,
+                _ => 8,
+            },
+            // Stores always spend a cycle fixing the high byte of an indexed address
+            STA(_) | STX(_) | STY(_) | SAX(_) => match mode {
+                ZeroPage => 3,
+                ZeroPageX | ZeroPageY | Absolute => 4,
+                AbsoluteX | AbsoluteY => 5,
+                _ => 6,
+            },
+            _ => match mode {
+                Immediate => 2,
+                ZeroPage => 3,
+                IndirectIndexed => 5,
+                IndexedIndirect => 6,
+                _ => 4,
+            },
         }
     }
 }
@@ -529,6 +642,7 @@ macro_rules! def_opcodes {
 def_opcodes! {
     0x00 => BRK     => BRK,
     0x01 => ORA_IDX => ORA(FlexibleAddressingMode::IndexedIndirect),
+    0x02 => JAM     => JAM,
     0x03 => SLO_IDX => SLO(StoreAddressingMode::IndexedIndirect),
     0x04 => IGN_ZPA => IGN(IncDecAddressingMode::ZeroPage),
     0x05 => ORA_ZPA => ORA(FlexibleAddressingMode::ZeroPage),
@@ -537,12 +651,14 @@ def_opcodes! {
     0x08 => PHP     => PHP,
     0x09 => ORA_IMM => ORA(FlexibleAddressingMode::Immediate),
     0x0A => ASL_ACC => ASL(ShiftAddressingMode::Accumulator),
+    0x0B => ANC     => ANC,
     0x0C => IGN_ABS => IGN(IncDecAddressingMode::Absolute),
     0x0D => ORA_ABS => ORA(FlexibleAddressingMode::Absolute),
     0x0E => ASL_ABS => ASL(ShiftAddressingMode::Absolute),
     0x0F => SLO_ABS => SLO(StoreAddressingMode::Absolute),
     0x10 => BPL     => BPL,
     0x11 => ORA_IDY => ORA(FlexibleAddressingMode::IndirectIndexed),
+    0x12 => JAM,
     0x13 => SOL_IDY => SLO(StoreAddressingMode::IndirectIndexed),
     0x15 => ORA_ZPX => ORA(FlexibleAddressingMode::ZeroPageX),
     0x14 => IGN_ZPX => IGN(IncDecAddressingMode::ZeroPageX),
@@ -558,6 +674,7 @@ def_opcodes! {
     0x1F => SLO_ABX => SLO(StoreAddressingMode::AbsoluteX),
     0x20 => JSR     => JSR,
     0x21 => AND_IDX => AND(FlexibleAddressingMode::IndexedIndirect),
+    0x22 => JAM,
     0x23 => RLA_IDX => RLA(StoreAddressingMode::IndexedIndirect),
     0x24 => BIT_ZPA => BIT(BITAddressingMode::ZeroPage),
     0x25 => AND_ZPA => AND(FlexibleAddressingMode::ZeroPage),
@@ -566,12 +683,14 @@ def_opcodes! {
     0x28 => PLP     => PLP,
     0x29 => AND_IMM => AND(FlexibleAddressingMode::Immediate),
     0x2A => ROL_ACC => ROL(ShiftAddressingMode::Accumulator),
+    0x2B => ANC,
     0x2C => BIT_ABS => BIT(BITAddressingMode::Absolute),
     0x2D => AND_ABS => AND(FlexibleAddressingMode::Absolute),
     0x2E => ROL_ABS => ROL(ShiftAddressingMode::Absolute),
     0x2F => RLA_ABS => RLA(StoreAddressingMode::Absolute),
     0x30 => BMI     => BMI,
     0x31 => AND_IDY => AND(FlexibleAddressingMode::IndirectIndexed),
+    0x32 => JAM,
     0x33 => RLA_IDY => RLA(StoreAddressingMode::IndirectIndexed),
     0x34 => IGN_ZPX,
     0x35 => AND_ZPX => AND(FlexibleAddressingMode::ZeroPageX),
@@ -587,6 +706,7 @@ def_opcodes! {
     0x3F => RLA_ABX => RLA(StoreAddressingMode::AbsoluteX),
     0x40 => RTI     => RTI,
     0x41 => EOR_IDX => EOR(FlexibleAddressingMode::IndexedIndirect),
+    0x42 => JAM,
     0x43 => SRE_IDX => SRE(StoreAddressingMode::IndexedIndirect),
     0x44 => IGN_ZPA,
     0x45 => EOR_ZPA => EOR(FlexibleAddressingMode::ZeroPage),
@@ -595,12 +715,14 @@ def_opcodes! {
     0x48 => PHA     => PHA,
     0x49 => EOR_IMM => EOR(FlexibleAddressingMode::Immediate),
     0x4A => LSR_ACC => LSR(ShiftAddressingMode::Accumulator),
+    0x4B => ALR     => ALR,
     0x4C => JMP_ABS => JMP(JumpAddressingMode::Absolute),
     0x4D => EOR_ABS => EOR(FlexibleAddressingMode::Absolute),
     0x4E => LSR_ABS => LSR(ShiftAddressingMode::Absolute),
     0x4F => SRE_ABS => SRE(StoreAddressingMode::Absolute),
     0x50 => BVC     => BVC,
     0x51 => EOR_IDY => EOR(FlexibleAddressingMode::IndirectIndexed),
+    0x52 => JAM,
     0x53 => SRE_IDY => SRE(StoreAddressingMode::IndirectIndexed),
     0x54 => IGN_ZPX,
     0x55 => EOR_ZPX => EOR(FlexibleAddressingMode::ZeroPageX),
@@ -616,6 +738,7 @@ def_opcodes! {
     0x5F => SRE_ABX => SRE(StoreAddressingMode::AbsoluteX),
     0x60 => RTS     => RTS,
     0x61 => ADC_IDX => ADC(FlexibleAddressingMode::IndexedIndirect),
+    0x62 => JAM,
     0x63 => RRA_IDX => RRA(StoreAddressingMode::IndexedIndirect),
     0x64 => IGN_ZPA,
     0x65 => ADC_ZPA => ADC(FlexibleAddressingMode::ZeroPage),
@@ -624,12 +747,14 @@ def_opcodes! {
     0x68 => PLA     => PLA,
     0x69 => ADC_IMM => ADC(FlexibleAddressingMode::Immediate),
     0x6A => ROR_ACC => ROR(ShiftAddressingMode::Accumulator),
+    0x6B => ARR     => ARR,
     0x6C => JMP_IND => JMP(JumpAddressingMode::Indirect),
     0x6D => ADC_ABS => ADC(FlexibleAddressingMode::Absolute),
     0x6E => ROR_ABS => ROR(ShiftAddressingMode::Absolute),
     0x6F => RRA_ABS => RRA(StoreAddressingMode::Absolute),
     0x70 => BVS     => BVS,
     0x71 => ADC_IDY => ADC(FlexibleAddressingMode::IndirectIndexed),
+    0x72 => JAM,
     0x73 => RRA_IDY => RRA(StoreAddressingMode::IndirectIndexed),
     0x74 => IGN_ZPX,
     0x75 => ADC_ZPX => ADC(FlexibleAddressingMode::ZeroPageX),
@@ -660,6 +785,7 @@ def_opcodes! {
     0x8F => SAX_ABS => SAX(SAXAddressingMode::Absolute),
     0x90 => BCC     => BCC,
     0x91 => STA_IDY => STA(StoreAddressingMode::IndirectIndexed),
+    0x92 => JAM,
     0x94 => STY_ZPX => STY(STYAddressingMode::ZeroPageX),
     0x95 => STA_ZPX => STA(StoreAddressingMode::ZeroPageX),
     0x96 => STX_ZPY => STX(STXAddressingMode::ZeroPageY),
@@ -685,6 +811,7 @@ def_opcodes! {
     0xAF => LAX_ABS => LAX(LAXAddressingMode::Absolute),
     0xB0 => BCS     => BCS,
     0xB1 => LDA_IDY => LDA(FlexibleAddressingMode::IndirectIndexed),
+    0xB2 => JAM,
     0xB3 => LAX_IDY => LAX(LAXAddressingMode::IndirectIndexed),
     0xB4 => LDY_ZPX => LDY(LDYAddressingMode::ZeroPageX),
     0xB5 => LDA_ZPX => LDA(FlexibleAddressingMode::ZeroPageX),
@@ -708,12 +835,14 @@ def_opcodes! {
     0xC8 => INY     => INY,
     0xC9 => CMP_IMM => CMP(FlexibleAddressingMode::Immediate),
     0xCA => DEX     => DEX,
+    0xCB => AXS     => AXS,
     0xCC => CPY_ABS => CPY(CompareAddressingMode::Absolute),
     0xCD => CMP_ABS => CMP(FlexibleAddressingMode::Absolute),
     0xCE => DEC_ABS => DEC(IncDecAddressingMode::Absolute),
     0xCF => DCP_ABS => DCP(StoreAddressingMode::Absolute),
     0xD0 => BNE     => BNE,
     0xD1 => CMP_IDY => CMP(FlexibleAddressingMode::IndirectIndexed),
+    0xD2 => JAM,
     0xD3 => DCP_IDY => DCP(StoreAddressingMode::IndirectIndexed),
     0xD4 => IGN_ZPX,
     0xD5 => CMP_ZPX => CMP(FlexibleAddressingMode::ZeroPageX),
@@ -738,13 +867,14 @@ def_opcodes! {
     0xE8 => INX     => INX,
     0xE9 => SBC_IMM => SBC(FlexibleAddressingMode::Immediate),
     0xEA => NOP     => NOP,
-    0xEB => ADC_IMM,
+    0xEB => SBC_IMM,
     0xEC => CPX_ABS => CPX(CompareAddressingMode::Absolute),
     0xED => SBC_ABS => SBC(FlexibleAddressingMode::Absolute),
     0xEE => INC_ABS => INC(IncDecAddressingMode::Absolute),
     0xEF => ISC_ABS => ISC(StoreAddressingMode::Absolute),
     0xF0 => BEQ     => BEQ,
     0xF1 => SBC_IDY => SBC(FlexibleAddressingMode::IndirectIndexed),
+    0xF2 => JAM,
     0xF3 => ISC_IDY => ISC(StoreAddressingMode::IndirectIndexed),
     0xF4 => IGN_ZPX,
     0xF5 => SBC_ZPX => SBC(FlexibleAddressingMode::ZeroPageX),
@@ -759,3 +889,84 @@ def_opcodes! {
     0xFE => INC_ABX => INC(IncDecAddressingMode::AbsoluteX),
     0xFF => ISC_ABX => ISC(StoreAddressingMode::AbsoluteX),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::instructions::*;
+    use super::Instruction;
+
+    #[test]
+    fn all_opcodes_returns_every_encoding_of_an_instruction() {
+        assert_eq!(
+            NOP.all_opcodes(),
+            [0x1A, 0x3A, 0x5A, 0x7A, 0xDA, 0xEA, 0xFA]
+        );
+        assert_eq!(SKB.all_opcodes(), [0x80, 0x82, 0x89, 0xC2, 0xE2]);
+        assert_eq!(SBC_IMM.all_opcodes(), [0xE9, 0xEB]);
+        assert_eq!(LDA_IMM.all_opcodes(), [0xA9]);
+    }
+
+    // Size of each opcode, or 0 for unsupported opcodes
+    #[rustfmt::skip]
+    const SIZES: [u8; 256] = [
+        1, 2, 1, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+        3, 2, 1, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+        1, 2, 1, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+        1, 2, 1, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+        2, 2, 2, 2, 2, 2, 2, 2, 1, 2, 1, 0, 3, 3, 3, 3,
+        2, 2, 1, 0, 2, 2, 2, 2, 1, 3, 1, 0, 0, 3, 0, 0,
+        2, 2, 2, 2, 2, 2, 2, 2, 1, 2, 1, 0, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 0, 3, 3, 3, 3,
+        2, 2, 2, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+        2, 2, 2, 2, 2, 2, 2, 2, 1, 2, 1, 2, 3, 3, 3, 3,
+        2, 2, 1, 2, 2, 2, 2, 2, 1, 3, 1, 3, 3, 3, 3, 3,
+    ];
+
+    // Cycles taken by each opcode without page crosses or branches, or 0 for unsupported opcodes
+    #[rustfmt::skip]
+    const CYCLES: [u8; 256] = [
+        7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 0, 4, 4, 4, 4,
+        2, 6, 2, 0, 4, 4, 4, 4, 2, 5, 2, 0, 0, 5, 0, 0,
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 0, 4, 4, 4, 4,
+        2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 0, 4, 4, 4, 4,
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
+    ];
+
+    #[test]
+    fn every_opcode_has_the_right_size() {
+        for opcode in 0..=0xFF {
+            let size = Instruction::try_from_opcode(opcode).map_or(0, Instruction::size);
+            assert_eq!(size, SIZES[opcode as usize], "opcode {:#04x}", opcode);
+        }
+    }
+
+    #[test]
+    fn every_opcode_has_the_right_base_cycles() {
+        for opcode in 0..=0xFF {
+            let cycles = Instruction::try_from_opcode(opcode).map_or(0, Instruction::base_cycles);
+            assert_eq!(cycles, CYCLES[opcode as usize], "opcode {:#04x}", opcode);
+        }
+    }
+
+    #[test]
+    fn to_opcode_returns_lowest_opcode() {
+        assert_eq!(NOP.to_opcode(), 0x1A);
+        assert_eq!(SBC_IMM.to_opcode(), 0xE9);
+    }
+}
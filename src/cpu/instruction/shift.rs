@@ -43,6 +43,24 @@ impl<M: Memory> CPU<M> {
         self.add_to_accumulator(value);
     }
 
+    pub(in crate::cpu) fn alr(&mut self) {
+        let value = self.accumulator & self.incr_program_counter();
+        self.set_accumulator(value >> 1);
+        self.status.set(Status::CARRY, value & 1 != 0);
+    }
+
+    pub(in crate::cpu) fn arr(&mut self) {
+        let value = self.accumulator & self.incr_program_counter();
+        let carry = self.status.contains(Status::CARRY) as u8;
+        let result = value >> 1 | carry << 7;
+        self.set_accumulator(result);
+
+        let bit_6 = result & 0b0100_0000 != 0;
+        let bit_5 = result & 0b0010_0000 != 0;
+        self.status.set(Status::CARRY, bit_6);
+        self.status.set(Status::OVERFLOW, bit_6 != bit_5);
+    }
+
     fn shift(
         &mut self,
         addressing_mode: impl ReferenceAddressingMode,
@@ -67,10 +85,47 @@ impl<M: Memory> CPU<M> {
 mod tests {
     use crate::{
         cpu::{tests::run_instr, Status},
-        instructions::{ASL_ABS, ASL_ACC, LSR_ACC, ROL_ACC, ROR_ACC},
+        instructions::{ALR, ARR, ASL_ABS, ASL_ACC, LSR_ACC, ROL_ACC, ROR_ACC},
         mem, Address,
     };
 
+    #[test]
+    fn instr_alr_performs_bitwise_and_then_shifts_right() {
+        let cpu = run_instr(mem!(ALR, 0b0000_1111_u8), |cpu| {
+            cpu.accumulator = 0b1010_1011;
+        });
+
+        assert_eq!(cpu.accumulator, 0b0000_0101);
+        assert!(cpu.status.contains(Status::CARRY));
+        assert!(!cpu.status.contains(Status::NEGATIVE));
+    }
+
+    #[test]
+    fn instr_arr_performs_bitwise_and_then_rotates_right() {
+        let cpu = run_instr(mem!(ARR, 0b1111_0000_u8), |cpu| {
+            cpu.accumulator = 0b1010_1111;
+            cpu.status.insert(Status::CARRY);
+        });
+
+        assert_eq!(cpu.accumulator, 0b1101_0000);
+        assert!(cpu.status.contains(Status::NEGATIVE));
+        // Carry is bit 6 of the result, overflow is bit 6 XOR bit 5
+        assert!(cpu.status.contains(Status::CARRY));
+        assert!(cpu.status.contains(Status::OVERFLOW));
+    }
+
+    #[test]
+    fn instr_arr_clears_carry_and_overflow_based_on_result() {
+        let cpu = run_instr(mem!(ARR, 0b0000_1111_u8), |cpu| {
+            cpu.accumulator = 0b1111_1111;
+            cpu.status.insert(Status::OVERFLOW);
+        });
+
+        assert_eq!(cpu.accumulator, 0b0000_0111);
+        assert!(!cpu.status.contains(Status::CARRY));
+        assert!(!cpu.status.contains(Status::OVERFLOW));
+    }
+
     #[test]
     fn instr_asl_shifts_left() {
         let cpu = run_instr(mem!(ASL_ACC), |cpu| {
@@ -1,12 +1,10 @@
 //! System Functions
 
 use crate::{
-    cpu::{addressing_modes::IncDecAddressingMode, Status},
+    cpu::{addressing_modes::IncDecAddressingMode, Status, INTERRUPT_VECTOR},
     Address, Memory, CPU,
 };
 
-const INTERRUPT_VECTOR: Address = Address::new(0xFFFE);
-
 impl<M: Memory> CPU<M> {
     pub(in crate::cpu) fn brk(&mut self) {
         self.ignore_argument();
@@ -28,12 +26,20 @@ impl<M: Memory> CPU<M> {
 
     // Unofficial Opcodes
     pub(in crate::cpu) fn ign(&mut self, addressing_mode: IncDecAddressingMode) {
-        self.fetch_ref(addressing_mode);
+        // The value is read and ignored, taking a cycle like any other read
+        self.fetch(addressing_mode);
     }
 
     pub(in crate::cpu) fn skb(&mut self) {
         self.incr_program_counter();
     }
+
+    pub(in crate::cpu) fn jam(&mut self) {
+        self.ignore_argument();
+        // Stay on the JAM instruction, so it's clear where the CPU halted
+        self.program_counter = self.program_counter - 1;
+        self.halted = true;
+    }
 }
 
 #[cfg(test)]
@@ -41,8 +47,8 @@ mod tests {
     use super::*;
     use crate::{
         cpu::{stack, tests::run_instr, Status},
-        instructions::{BRK, LSR_ACC, RTI},
-        mem, Address,
+        instructions::{BRK, INX, JAM, LSR_ACC, RTI},
+        mem, Address, Instruction, CPU,
     };
 
     #[test]
@@ -91,6 +97,48 @@ mod tests {
         assert!(status.contains(Status::BREAK));
     }
 
+    #[test]
+    fn instr_jam_halts_cpu() {
+        let mut cpu = run_instr(mem!(20 => { JAM, INX }), |cpu| {
+            cpu.program_counter = Address::new(20);
+        });
+
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.program_counter, Address::new(20));
+
+        cpu.non_maskable_interrupt();
+        cpu.run_instruction();
+
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.program_counter, Address::new(20));
+        assert_eq!(cpu.x, 0);
+    }
+
+    #[test]
+    fn jam_opcodes_are_recognised() {
+        for opcode in [
+            0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+        ] {
+            assert_eq!(Instruction::from_opcode(opcode), JAM);
+        }
+    }
+
+    #[test]
+    fn reset_recovers_from_jam() {
+        let mut cpu = CPU::from_memory(mem!(
+            0 => { JAM }
+            0xFFFC => { 0x34, 0x12 }
+        ));
+        cpu.program_counter = Address::new(0);
+        cpu.run_instruction();
+        assert!(cpu.is_halted());
+
+        cpu.reset();
+
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.program_counter, Address::new(0x1234));
+    }
+
     #[test]
     fn instr_nop_increments_program_counter() {
         let cpu = run_instr(mem!(20 => LSR_ACC), |cpu| {
@@ -1,12 +1,16 @@
 use std::borrow::BorrowMut;
 use std::fmt::{Debug, Formatter};
 
-use log::trace;
+use log::{debug, trace};
 
+use crate::accuracy::AccuracyProfile;
 use crate::apu::APU;
-use crate::input::{Controller, Input};
+#[cfg(feature = "serde")]
+use crate::cartridge::{CHRState, PRGState, SavedMapper};
+use crate::input::{Controller, Input, Zapper};
+#[cfg(feature = "serde")]
+use crate::ppu::NESPPUMemory;
 use crate::ppu::{self, PPURegisters};
-use crate::ArrayMemory;
 use crate::Memory;
 use crate::{cartridge, Address};
 
@@ -41,27 +45,102 @@ const APU_DMC_SAMPLE_LENGTH: Address = Address::new(0x4013);
 const OAM_DMA: Address = Address::new(0x4014);
 const APU_STATUS: Address = Address::new(0x4015);
 const JOY1_ADDRESS: Address = Address::new(0x4016);
+// Reads from 0x4017 go to the second controller, writes go to the APU
+const JOY2_ADDRESS: Address = Address::new(0x4017);
 const APU_FRAME_COUNTER: Address = Address::new(0x4017);
+// Disabled APU and I/O test registers
+const APU_TEST_SPACE: Address = Address::new(0x4018);
 const PRG_SPACE: Address = Address::new(0x4020);
 
+/// What internal RAM holds when the NES is powered on.
+///
+/// Real consoles power on with semi-random RAM, and some games read it before initializing it.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PowerOnPattern {
+    #[default]
+    Zeroed,
+    Filled(u8),
+    /// Pseudo-random bytes, the same every time for the same seed.
+    Seeded(u64),
+}
+
+impl PowerOnPattern {
+    fn fill(self, ram: &mut [u8]) {
+        match self {
+            PowerOnPattern::Zeroed => ram.fill(0),
+            PowerOnPattern::Filled(byte) => ram.fill(byte),
+            PowerOnPattern::Seeded(seed) => {
+                // SplitMix64, which works for any seed including zero
+                let mut state = seed;
+                for chunk in ram.chunks_mut(8) {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
+
+/// Called on every OAM DMA transfer with the source page and the bytes transferred.
+pub type OAMDMAHook = Box<dyn FnMut(u8, &[u8; 256])>;
+
+/// An address to stop at when it is read or written, for debugging.
+#[derive(Debug, Copy, Clone)]
+struct Watchpoint {
+    address: Address,
+    read: bool,
+    write: bool,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NESCPUMemory<PRG = cartridge::PRG, PPU = ppu::PPU, IN = Controller> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
     internal_ram: [u8; 0x800],
+    power_on_pattern: PowerOnPattern,
     prg: PRG,
     ppu_registers: PPU,
     apu: APU,
     input: IN,
-    the_rest: ArrayMemory, // TODO
+    input_2: IN,
+    // Plugged into the second port in place of the second controller
+    zapper: Option<Zapper>,
+    // The last value read or written. Reading from an address nothing responds to returns this.
+    last_bus_value: u8,
+    accuracy: AccuracyProfile,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    oam_dma_hook: Option<OAMDMAHook>,
+    // Set when a DMA is written, until the CPU is halted for it
+    #[cfg_attr(feature = "serde", serde(skip))]
+    oam_dma_pending: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoints: Vec<Watchpoint>,
+    // The address and whether it was a write, for the last watchpoint hit
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoint_hit: Option<(Address, bool)>,
 }
 
 impl<PRG: Memory, PPU: PPURegisters, IN: Input> NESCPUMemory<PRG, PPU, IN> {
-    pub fn new(prg: PRG, ppu_registers: PPU, apu: APU, input: IN) -> Self {
+    pub fn new(prg: PRG, ppu_registers: PPU, apu: APU, input: IN, input_2: IN) -> Self {
         NESCPUMemory {
             internal_ram: [0; 0x800],
+            power_on_pattern: PowerOnPattern::default(),
             prg,
             ppu_registers,
             apu,
             input,
-            the_rest: ArrayMemory::default(),
+            input_2,
+            zapper: None,
+            last_bus_value: 0,
+            accuracy: AccuracyProfile::default(),
+            oam_dma_hook: None,
+            oam_dma_pending: false,
+            watchpoints: vec![],
+            watchpoint_hit: None,
         }
     }
 
@@ -77,10 +156,98 @@ impl<PRG: Memory, PPU: PPURegisters, IN: Input> NESCPUMemory<PRG, PPU, IN> {
         &mut self.input
     }
 
+    pub fn input_2(&mut self) -> &mut IN {
+        &mut self.input_2
+    }
+
+    pub fn set_zapper_connected(&mut self, connected: bool) {
+        if connected != self.zapper.is_some() {
+            self.zapper = connected.then(Zapper::default);
+        }
+    }
+
+    pub fn zapper(&mut self) -> Option<&mut Zapper> {
+        self.zapper.as_mut()
+    }
+
     pub fn prg(&mut self) -> &mut PRG {
         &mut self.prg
     }
 
+    pub fn internal_ram(&self) -> &[u8; 0x800] {
+        &self.internal_ram
+    }
+
+    /// Set what internal RAM holds at power on, and fill RAM with it.
+    pub fn set_power_on_pattern(&mut self, pattern: PowerOnPattern) {
+        self.power_on_pattern = pattern;
+        pattern.fill(&mut self.internal_ram);
+    }
+
+    /// Fill internal RAM with the power-on pattern and reset the APU, as when the NES is powered
+    /// on.
+    pub fn power_on(&mut self) {
+        self.power_on_pattern.fill(&mut self.internal_ram);
+        self.last_bus_value = 0;
+        self.apu.power_on();
+    }
+
+    pub fn set_oam_dma_hook(&mut self, hook: impl FnMut(u8, &[u8; 256]) + 'static) {
+        self.oam_dma_hook = Some(Box::new(hook));
+    }
+
+    /// Whether an OAM DMA was written since last called. The CPU is halted while the DMA runs.
+    pub fn take_oam_dma(&mut self) -> bool {
+        std::mem::take(&mut self.oam_dma_pending)
+    }
+
+    pub fn add_watchpoint(&mut self, address: Address, read: bool, write: bool) {
+        self.watchpoints.push(Watchpoint {
+            address,
+            read,
+            write,
+        });
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// The address and whether it was written, for the first watchpoint hit since last called.
+    pub fn take_watchpoint_hit(&mut self) -> Option<(Address, bool)> {
+        self.watchpoint_hit.take()
+    }
+
+    fn check_watchpoints(&mut self, address: Address, write: bool) {
+        if self.watchpoint_hit.is_some() {
+            return;
+        }
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.address == address
+                && if write {
+                    watchpoint.write
+                } else {
+                    watchpoint.read
+                }
+        });
+        if hit {
+            self.watchpoint_hit = Some((address, write));
+        }
+    }
+
+    /// Read the next DMC sample byte if the APU needs one, returning the number of CPU cycles
+    /// stolen by the DMA.
+    pub fn fetch_dmc_sample(&mut self) -> u8 {
+        match self.apu.dmc_sample_request() {
+            Some(address) => {
+                let byte = self.read(Address::new(address));
+                self.apu.load_dmc_sample(byte);
+                4
+            }
+            None => 0,
+        }
+    }
+
     fn write_oam_data(&mut self, page: u8) {
         let address = Address::from_bytes(page, 0);
 
@@ -90,7 +257,68 @@ impl<PRG: Memory, PPU: PPURegisters, IN: Input> NESCPUMemory<PRG, PPU, IN> {
             *byte = self.read(address + offset as u16);
         }
 
+        if let Some(hook) = &mut self.oam_dma_hook {
+            hook(page, &data);
+        }
+
         self.ppu_registers.write_oam_dma(data);
+        self.oam_dma_pending = true;
+    }
+}
+
+impl NESCPUMemory {
+    /// Show the Zapper each pixel as it's drawn, in the PPU's palette.
+    pub fn observe_pixel(&mut self, x: u16, y: u16, color: ppu::Color) {
+        if let Some(zapper) = &mut self.zapper {
+            zapper.observe_pixel(x, y, color, self.ppu_registers.palette());
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl NESCPUMemory<PRGState, ppu::PPU<NESPPUMemory<CHRState>>> {
+    /// Saved state of the cartridge.
+    pub(crate) fn saved_prg(&self) -> &PRGState {
+        &self.prg
+    }
+}
+
+#[cfg(feature = "serde")]
+impl NESCPUMemory {
+    /// Restore RAM and devices from saved memory, with the mapper state already read from it by
+    /// [cartridge::PRG::read_state]. Hooks are kept.
+    pub(crate) fn restore(
+        &mut self,
+        saved: NESCPUMemory<PRGState, ppu::PPU<NESPPUMemory<CHRState>>>,
+        mapper: SavedMapper,
+    ) {
+        let NESCPUMemory {
+            internal_ram,
+            power_on_pattern,
+            prg: _,
+            ppu_registers,
+            apu,
+            input,
+            input_2,
+            zapper,
+            last_bus_value,
+            accuracy,
+            oam_dma_hook: _,
+            oam_dma_pending: _,
+            watchpoints: _,
+            watchpoint_hit: _,
+        } = saved;
+        self.internal_ram = internal_ram;
+        self.power_on_pattern = power_on_pattern;
+        self.prg.restore(mapper);
+        let ppu_memory = self.ppu_registers.restore(ppu_registers);
+        self.ppu_registers.memory().restore(ppu_memory);
+        self.apu = apu;
+        self.input = input;
+        self.input_2 = input_2;
+        self.zapper = zapper;
+        self.last_bus_value = last_bus_value;
+        self.accuracy = accuracy;
     }
 }
 
@@ -100,21 +328,45 @@ impl<PRG: Debug, PPU: Debug, IN: Debug> Debug for NESCPUMemory<PRG, PPU, IN> {
             .field("prg", &self.prg)
             .field("ppu_registers", &self.ppu_registers)
             .field("input", &self.input)
-            .field("the_rest", &self.the_rest)
+            .field("input_2", &self.input_2)
+            .field("zapper", &self.zapper)
+            .field("last_bus_value", &self.last_bus_value)
+            .field("accuracy", &self.accuracy)
             .finish()
     }
 }
 
-impl<PRG: Memory, PPU: PPURegisters, IN: Input> Memory for NESCPUMemory<PRG, PPU, IN> {
-    fn read(&mut self, address: Address) -> u8 {
+impl<PRG: Memory, PPU: PPURegisters, IN: Input> NESCPUMemory<PRG, PPU, IN> {
+    pub fn set_accuracy_profile(&mut self, accuracy: AccuracyProfile) {
+        self.accuracy = accuracy;
+    }
+
+    // What's read when nothing responds. Running fast, this is always 0.
+    fn open_bus(&self) -> u8 {
+        match self.accuracy {
+            AccuracyProfile::Accurate => self.last_bus_value,
+            AccuracyProfile::Fast => 0,
+        }
+    }
+
+    fn read_bus(&mut self, address: Address) -> u8 {
         if address >= PRG_SPACE {
             self.prg.read(address)
         } else if address == JOY1_ADDRESS {
             self.input.read()
+        } else if address == JOY2_ADDRESS {
+            match &mut self.zapper {
+                Some(zapper) => zapper.read(),
+                None => self.input_2.read(),
+            }
         } else if address == APU_STATUS {
             self.apu.read_status()
+        } else if address >= APU_TEST_SPACE {
+            debug!("Read from disabled APU test register {:?}", address);
+            self.open_bus()
         } else if address >= APU_SPACE {
-            self.the_rest.read(address) // TODO
+            // The other APU registers are write-only
+            self.open_bus()
         } else if address >= PPU_SPACE {
             let mirrored = PPU_SPACE + (address.index() % 8) as u16;
             let ppu_registers = self.ppu_registers.borrow_mut();
@@ -122,26 +374,58 @@ impl<PRG: Memory, PPU: PPURegisters, IN: Input> Memory for NESCPUMemory<PRG, PPU
                 PPU_STATUS => ppu_registers.read_status(),
                 OAM_DATA => ppu_registers.read_oam_data(),
                 PPU_DATA => ppu_registers.read_data(),
-                _ => unimplemented!(),
+                // Write-only registers
+                _ => ppu_registers.read_io_latch(),
             }
         } else {
             self.internal_ram[address.index() % 0x0800]
         }
     }
+}
+
+impl<PRG: Memory, PPU: PPURegisters, IN: Input> Memory for NESCPUMemory<PRG, PPU, IN> {
+    fn read(&mut self, address: Address) -> u8 {
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, false);
+        }
+        self.last_bus_value = self.read_bus(address);
+        self.last_bus_value
+    }
+
+    fn peek(&self, address: Address) -> Option<u8> {
+        if address >= PRG_SPACE {
+            self.prg.peek(address)
+        } else if address < PPU_SPACE {
+            Some(self.internal_ram[address.index() % 0x0800])
+        } else {
+            None
+        }
+    }
 
     fn write(&mut self, address: Address, byte: u8) {
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, true);
+        }
+        self.last_bus_value = byte;
+
         if address >= PRG_SPACE {
             self.prg.write(address, byte);
         } else if address == OAM_DMA {
             self.write_oam_data(byte);
         } else if address == JOY1_ADDRESS {
+            // Both controllers are strobed together
             self.input.write(byte);
+            self.input_2.write(byte);
+        } else if address >= APU_TEST_SPACE {
+            debug!("Write to disabled APU test register {:?}", address);
         } else if address >= APU_SPACE {
             match address {
                 APU_PULSE_1_FLAGS => self.apu.write_pulse_1_flags(byte),
+                APU_PULSE_1_SWEEP => self.apu.write_pulse_1_sweep(byte),
                 APU_PULSE_1_TIMER => self.apu.write_pulse_1_timer(byte),
                 APU_PULSE_1_LENGTH => self.apu.write_pulse_1_length(byte),
                 APU_PULSE_2_FLAGS => self.apu.write_pulse_2_flags(byte),
+                APU_PULSE_2_SWEEP => self.apu.write_pulse_2_sweep(byte),
                 APU_PULSE_2_TIMER => self.apu.write_pulse_2_timer(byte),
                 APU_PULSE_2_LENGTH => self.apu.write_pulse_2_length(byte),
                 APU_TRIANGLE_FLAGS => self.apu.write_triangle_flags(byte),
@@ -150,9 +434,13 @@ impl<PRG: Memory, PPU: PPURegisters, IN: Input> Memory for NESCPUMemory<PRG, PPU
                 APU_NOISE_FLAGS => self.apu.write_noise_flags(byte),
                 APU_NOISE_MODE => self.apu.write_noise_mode(byte),
                 APU_NOISE_LENGTH => self.apu.write_noise_length(byte),
+                APU_DMC_FLAGS => self.apu.write_dmc_flags(byte),
+                APU_DMC_DIRECT_LOAD => self.apu.write_dmc_direct_load(byte),
+                APU_DMC_SAMPLE_ADDRESS => self.apu.write_dmc_sample_address(byte),
+                APU_DMC_SAMPLE_LENGTH => self.apu.write_dmc_sample_length(byte),
                 APU_FRAME_COUNTER => self.apu.write_frame_counter(byte),
                 APU_STATUS => self.apu.write_status(byte),
-                _ => self.the_rest.write(address, byte), // TODO
+                _ => {}
             }
         } else if address >= PPU_SPACE {
             let mirrored = PPU_SPACE + (address.index() % 8) as u16;
@@ -194,6 +482,11 @@ impl<PRG: Memory, PPU: PPURegisters, IN: Input> Memory for NESCPUMemory<PRG, PPU
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::ArrayMemory;
+
     use super::*;
 
     #[test]
@@ -208,6 +501,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn zeroed_power_on_pattern_leaves_ram_zero() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0x0123), 0x45);
+
+        memory.set_power_on_pattern(PowerOnPattern::Zeroed);
+        memory.power_on();
+
+        assert_eq!(memory.internal_ram(), &[0; 0x800]);
+    }
+
+    #[test]
+    fn filled_power_on_pattern_fills_ram_with_byte() {
+        let mut memory = nes_cpu_memory();
+
+        memory.set_power_on_pattern(PowerOnPattern::Filled(0xFF));
+
+        assert_eq!(memory.internal_ram(), &[0xFF; 0x800]);
+    }
+
+    #[test]
+    fn seeded_power_on_pattern_is_reproducible_and_not_zero() {
+        let mut memory = nes_cpu_memory();
+        memory.set_power_on_pattern(PowerOnPattern::Seeded(0));
+        let ram = *memory.internal_ram();
+
+        assert!(ram.iter().filter(|&&byte| byte == 0).count() < 0x80);
+
+        memory.write(Address::new(0x0123), !ram[0x123]);
+        memory.power_on();
+        assert_eq!(memory.internal_ram(), &ram);
+
+        memory.set_power_on_pattern(PowerOnPattern::Seeded(1));
+        assert_ne!(memory.internal_ram(), &ram);
+    }
+
+    #[test]
+    fn watchpoints_record_first_matching_access() {
+        let mut memory = nes_cpu_memory();
+        memory.add_watchpoint(Address::new(0x0010), true, false);
+
+        memory.write(Address::new(0x0010), 1);
+        assert_eq!(memory.take_watchpoint_hit(), None);
+
+        memory.read(Address::new(0x0011));
+        memory.read(Address::new(0x0010));
+        assert_eq!(
+            memory.take_watchpoint_hit(),
+            Some((Address::new(0x0010), false))
+        );
+        assert_eq!(memory.take_watchpoint_hit(), None);
+    }
+
     #[test]
     fn nes_cpu_memory_addresses_0x800_to_0x1fff_mirror_internal_ram() {
         let mut memory = nes_cpu_memory();
@@ -294,6 +640,119 @@ mod tests {
         assert_eq!(memory.ppu_registers.oam_dma, expected);
     }
 
+    #[test]
+    fn apu_test_registers_are_open_bus() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0x0010), 0x40);
+        memory.read(Address::new(0x0010));
+
+        assert_eq!(memory.read(Address::new(0x4018)), 0x40);
+        assert_eq!(memory.read(Address::new(0x401F)), 0x40);
+    }
+
+    #[test]
+    fn open_bus_reads_0_when_running_fast() {
+        let mut memory = nes_cpu_memory();
+        memory.set_accuracy_profile(AccuracyProfile::Fast);
+        memory.write(Address::new(0x0010), 0x40);
+        memory.read(Address::new(0x0010));
+
+        assert_eq!(memory.read(Address::new(0x4018)), 0);
+        assert_eq!(memory.read(Address::new(0x4000)), 0);
+    }
+
+    #[test]
+    fn peeking_has_no_side_effects() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0x0010), 0x12);
+        memory.prg.as_mut_slice()[0x8000] = 0x34;
+        memory.add_watchpoint(Address::new(0x0810), true, true);
+        memory.add_watchpoint(Address::new(0x8000), true, true);
+
+        assert_eq!(memory.peek(Address::new(0x0810)), Some(0x12));
+        assert_eq!(memory.peek(Address::new(0x8000)), Some(0x34));
+        assert_eq!(memory.take_watchpoint_hit(), None);
+        // Write-only APU register still reads the last value on the bus
+        assert_eq!(memory.read(Address::new(0x4000)), 0x12);
+    }
+
+    #[test]
+    fn registers_cannot_be_peeked() {
+        let memory = nes_cpu_memory();
+
+        assert_eq!(memory.peek(Address::new(0x2002)), None);
+        assert_eq!(memory.peek(Address::new(0x4016)), None);
+        assert_eq!(memory.peek(Address::new(0x4015)), None);
+    }
+
+    #[test]
+    fn reading_unmapped_address_returns_last_value_on_bus() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0x0010), 0x12);
+
+        assert_eq!(memory.read(Address::new(0x0010)), 0x12);
+        // Write-only APU register
+        assert_eq!(memory.read(Address::new(0x4000)), 0x12);
+    }
+
+    #[test]
+    fn reading_write_only_ppu_register_reads_ppu_io_latch() {
+        let mut memory = nes_cpu_memory();
+        memory.ppu_registers.io_latch = 0x34;
+
+        assert_eq!(memory.read(Address::new(0x2000)), 0x34);
+        assert_eq!(memory.read(Address::new(0x2006)), 0x34);
+    }
+
+    #[test]
+    fn dmc_sample_is_fetched_from_sample_address() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0xc040), 0x12);
+        memory.write(Address::new(0xc041), 0x34);
+
+        assert_eq!(memory.fetch_dmc_sample(), 0);
+
+        memory.write(Address::new(0x4012), 0x01);
+        memory.write(Address::new(0x4013), 0x01);
+        memory.write(Address::new(0x4015), 0x10);
+
+        assert_eq!(memory.apu.dmc_sample_request(), Some(0xc040));
+        assert_eq!(memory.fetch_dmc_sample(), 4);
+        // Sample buffer is full until the sample byte is played
+        assert_eq!(memory.fetch_dmc_sample(), 0);
+        assert_eq!(memory.read(Address::new(0x4015)) & 0x10, 0x10);
+    }
+
+    #[test]
+    fn writing_oam_dma_halts_cpu_once() {
+        let mut memory = nes_cpu_memory();
+        assert!(!memory.take_oam_dma());
+
+        memory.write(Address::new(0x4014), 0x02);
+
+        assert!(memory.take_oam_dma());
+        assert!(!memory.take_oam_dma());
+    }
+
+    #[test]
+    fn oam_dma_hook_receives_page_and_bytes() {
+        let transfers = Rc::new(RefCell::new(vec![]));
+
+        let mut memory = nes_cpu_memory();
+        let hook_transfers = transfers.clone();
+        memory.set_oam_dma_hook(move |page, bytes| {
+            RefCell::borrow_mut(&hook_transfers).push((page, *bytes));
+        });
+
+        for i in 0x0200..=0x02ff {
+            memory.write(Address::new(i), !(i as u8));
+        }
+        memory.write(Address::new(0x4014), 0x02);
+
+        let expected: [u8; 256] = std::array::from_fn(|i| !(i as u8));
+        assert_eq!(*transfers.borrow(), [(0x02, expected)]);
+    }
+
     #[test]
     fn can_write_ppuscroll_in_nes_cpu_memory() {
         let mut memory = nes_cpu_memory();
@@ -354,6 +813,22 @@ mod tests {
         let mut memory = nes_cpu_memory();
         memory.write(Address::new(0x4016), 52);
         assert_eq!(memory.input.0, 52);
+        assert_eq!(memory.input_2.0, 52);
+    }
+
+    #[test]
+    fn reading_from_4017_reads_from_second_input_device() {
+        let mut memory = nes_cpu_memory();
+        memory.input.0 = 24;
+        memory.input_2.0 = 42;
+        assert_eq!(memory.read(Address::new(0x4017)), 42);
+    }
+
+    #[test]
+    fn writing_to_4017_does_not_write_to_second_input_device() {
+        let mut memory = nes_cpu_memory();
+        memory.write(Address::new(0x4017), 52);
+        assert_eq!(memory.input_2.0, 0);
     }
 
     struct MockPPURegisters {
@@ -366,6 +841,7 @@ mod tests {
         address: u8,
         data: u8,
         oam_dma: [u8; 256],
+        io_latch: u8,
     }
 
     impl PPURegisters for MockPPURegisters {
@@ -412,6 +888,10 @@ mod tests {
         fn write_oam_dma(&mut self, bytes: [u8; 256]) {
             self.oam_dma = bytes;
         }
+
+        fn read_io_latch(&mut self) -> u8 {
+            self.io_latch
+        }
     }
 
     struct MockInput(u8);
@@ -437,9 +917,9 @@ mod tests {
             address: 0,
             data: 0,
             oam_dma: [0; 256],
+            io_latch: 0,
         };
         let prg = ArrayMemory::default();
-        let input = MockInput(0);
-        NESCPUMemory::new(prg, ppu, APU::default(), input)
+        NESCPUMemory::new(prg, ppu, APU::default(), MockInput(0), MockInput(0))
     }
 }
@@ -8,6 +8,7 @@
 //! - A 1-byte opcode, comprising instruction and addressing mode.
 //! - 0-2 byte operands.
 
+use std::error::Error;
 use std::fmt;
 use std::fmt::Debug;
 
@@ -15,12 +16,15 @@ use bitflags::bitflags;
 use log::trace;
 use stack::StackPointer;
 
+use crate::accuracy::AccuracyProfile;
 use crate::address::Address;
 use crate::memory::Memory;
 
+pub use self::instruction::disassemble;
 pub use self::instruction::instructions;
 pub use self::instruction::Instruction;
 pub use self::memory::NESCPUMemory;
+pub use self::memory::PowerOnPattern;
 
 mod addressing_modes;
 mod instruction;
@@ -29,8 +33,58 @@ mod stack;
 
 const NMI_VECTOR: Address = Address::new(0xFFFA);
 const RESET_VECTOR: Address = Address::new(0xFFFC);
+const INTERRUPT_VECTOR: Address = Address::new(0xFFFE);
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CpuError {
+    /// An opcode that isn't supported, and the address it was read from.
+    UnknownOpcode(u8, Address),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode(opcode, address) => {
+                write!(f, "Unrecognised opcode {:#04x} at {}", opcode, address)
+            }
+        }
+    }
+}
+
+impl Error for CpuError {}
+
+/// A snapshot of the CPU registers.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub accumulator: u8,
+    pub program_counter: Address,
+    pub x: u8,
+    pub y: u8,
+    pub stack_pointer: u8,
+    pub status: u8,
+}
+
+/// The NES's 6502 CPU.
+///
+/// The CPU can run standalone on any [Memory], without the rest of the NES:
+///
+/// ```
+/// use nes_rust::instructions::{ADC_IMM, LDA_IMM};
+/// use nes_rust::{mem, CPU};
+///
+/// let memory = mem!(
+///     0x8000 => { LDA_IMM, 2u8, ADC_IMM, 3u8 }
+///     // Reset vector
+///     0xFFFC => { 0x00u8, 0x80u8 }
+/// );
+/// let mut cpu = CPU::from_memory(memory);
+///
+/// cpu.run_until(2, |_| false);
+/// assert_eq!(cpu.registers().accumulator, 5);
+/// ```
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CPU<M = NESCPUMemory> {
     memory: M,
     /// A - 8-bit accumulator register.
@@ -46,27 +100,96 @@ pub struct CPU<M = NESCPUMemory> {
     /// P - 7-bit status register.
     status: Status,
     non_maskable_interrupt: bool,
+    // Set after a JAM instruction, until the CPU is reset.
+    halted: bool,
+    // The IRQ line, which stays asserted until the device that raised it acknowledges it.
+    interrupt_request: bool,
     // Counts cycles taken running the current instruction.
     cycle_count: u8,
+    accuracy: AccuracyProfile,
+    // The NES CPU ignores the decimal flag, but other 6502s support binary-coded decimal.
+    #[cfg(feature = "decimal")]
+    decimal_enabled: bool,
 }
 
-impl<M: Memory> CPU<M> {
-    pub fn from_memory(mut memory: M) -> Self {
-        let lower = memory.read(RESET_VECTOR);
-        let higher = memory.read(RESET_VECTOR + 1);
-        let program_counter = Address::from_bytes(higher, lower);
+#[cfg(feature = "serde")]
+impl<M> CPU<M> {
+    /// Memory of a saved CPU, to check it can be restored before restoring anything.
+    pub(crate) fn saved_memory(&self) -> &M {
+        &self.memory
+    }
+}
 
-        CPU {
+impl<M: Memory> CPU<M> {
+    pub fn from_memory(memory: M) -> Self {
+        let mut cpu = CPU {
             memory,
             accumulator: 0,
-            program_counter,
+            program_counter: Address::new(0),
             x: 0,
             y: 0,
             stack_pointer: StackPointer::default(),
             status: Status::empty(),
             non_maskable_interrupt: false,
+            interrupt_request: false,
+            halted: false,
             cycle_count: 0,
+            accuracy: AccuracyProfile::default(),
+            #[cfg(feature = "decimal")]
+            decimal_enabled: false,
+        };
+        cpu.program_counter = cpu.reset_vector();
+        cpu
+    }
+
+    /// Restore registers from a saved CPU, returning the saved memory for the caller to restore.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore<N>(&mut self, saved: CPU<N>) -> N {
+        let CPU {
+            memory,
+            accumulator,
+            program_counter,
+            x,
+            y,
+            stack_pointer,
+            status,
+            non_maskable_interrupt,
+            halted,
+            interrupt_request,
+            cycle_count,
+            accuracy,
+            #[cfg(feature = "decimal")]
+            decimal_enabled,
+        } = saved;
+        self.accumulator = accumulator;
+        self.program_counter = program_counter;
+        self.x = x;
+        self.y = y;
+        self.stack_pointer = stack_pointer;
+        self.status = status;
+        self.non_maskable_interrupt = non_maskable_interrupt;
+        self.halted = halted;
+        self.interrupt_request = interrupt_request;
+        self.cycle_count = cycle_count;
+        self.accuracy = accuracy;
+        #[cfg(feature = "decimal")]
+        {
+            self.decimal_enabled = decimal_enabled;
         }
+        memory
+    }
+
+    pub fn set_accuracy_profile(&mut self, accuracy: AccuracyProfile) {
+        self.accuracy = accuracy;
+    }
+
+    /// Make ADC and SBC use binary-coded decimal when the decimal flag is set, like a standard
+    /// 6502. This is disabled by default, because the NES CPU doesn't support decimal mode.
+    ///
+    /// Only available with the `decimal` feature, so the NES doesn't pay for checking it.
+    #[cfg(feature = "decimal")]
+    pub fn set_decimal_enabled(&mut self, enabled: bool) {
+        self.decimal_enabled = enabled;
     }
 
     pub fn program_counter(&self) -> Address {
@@ -77,10 +200,121 @@ impl<M: Memory> CPU<M> {
         self.program_counter = address;
     }
 
+    pub fn accumulator(&self) -> u8 {
+        self.accumulator
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    /// The processor status flags as a byte, with carry in bit 0 and negative in bit 7.
+    pub fn status_bits(&self) -> u8 {
+        self.status.bits()
+    }
+
+    pub fn stack_pointer_value(&self) -> u8 {
+        self.stack_pointer.0
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            accumulator: self.accumulator,
+            program_counter: self.program_counter,
+            x: self.x,
+            y: self.y,
+            stack_pointer: self.stack_pointer.0,
+            status: self.status.bits(),
+        }
+    }
+
+    /// Jump to the reset vector, as when the reset button is pressed.
+    pub fn reset(&mut self) {
+        self.stack_pointer = StackPointer(self.stack_pointer.0.wrapping_sub(3));
+        self.status.insert(Status::INTERRUPT_DISABLE);
+        self.non_maskable_interrupt = false;
+        self.interrupt_request = false;
+        self.halted = false;
+        self.program_counter = self.reset_vector();
+    }
+
+    /// Reset all registers to how they are when the NES is powered on, then jump to the reset
+    /// vector. Memory is not affected.
+    pub fn power_on(&mut self) {
+        self.accumulator = 0;
+        self.x = 0;
+        self.y = 0;
+        self.stack_pointer = StackPointer::default();
+        self.status = Status::empty();
+        self.non_maskable_interrupt = false;
+        self.interrupt_request = false;
+        self.halted = false;
+        self.program_counter = self.reset_vector();
+    }
+
+    fn reset_vector(&mut self) -> Address {
+        let lower = self.read(RESET_VECTOR);
+        let higher = self.read(RESET_VECTOR + 1);
+        Address::from_bytes(higher, lower)
+    }
+
     pub fn non_maskable_interrupt(&mut self) {
         self.non_maskable_interrupt = true;
     }
 
+    pub fn non_maskable_interrupt_pending(&self) -> bool {
+        self.non_maskable_interrupt
+    }
+
+    /// Assert the IRQ line. The interrupt fires before the next instruction if interrupts are
+    /// enabled, and keeps firing until acknowledged with `acknowledge_irq`.
+    pub fn request_irq(&mut self) {
+        self.interrupt_request = true;
+    }
+
+    pub fn acknowledge_irq(&mut self) {
+        self.interrupt_request = false;
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.interrupt_request
+    }
+
+    /// Whether the CPU has been halted by a JAM instruction. It only recovers on reset.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// If the CPU is about to run a loop waiting for PPUSTATUS to change, such as
+    /// `BIT $2002; BPL -5`, returns the address being read.
+    pub fn ppu_status_poll(&self) -> Option<Address> {
+        let start = self.program_counter;
+        if start > Address::new(0xfffb) {
+            return None;
+        }
+
+        // Peek rather than read, so looking for the loop has no side effects such as changing the
+        // open bus value or triggering watchpoints
+        let mut bytes = [0; 5];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.memory.peek(start + offset as u16)?;
+        }
+        let [opcode, lower, higher, branch, offset] = bytes;
+
+        let reads_status = opcode == instructions::BIT_ABS.to_opcode()
+            || opcode == instructions::LDA_ABS.to_opcode();
+        let loops = branch == instructions::BPL.to_opcode() && offset == -5i8 as u8;
+        let address = Address::from_bytes(higher, lower);
+        let status_address =
+            (0x2000..0x4000).contains(&address.bytes()) && address.index() % 8 == 2;
+
+        (reads_status && loops && status_address).then_some(address)
+    }
+
     pub fn memory(&mut self) -> &mut M {
         &mut self.memory
     }
@@ -90,9 +324,19 @@ impl<M: Memory> CPU<M> {
         self.memory.read(address)
     }
 
+    /// A read where the CPU ignores the result, only performed on the bus when running accurately.
+    fn dummy_read(&mut self, address: Address) {
+        match self.accuracy {
+            AccuracyProfile::Accurate => {
+                self.read(address);
+            }
+            AccuracyProfile::Fast => self.cycle_count += 1,
+        }
+    }
+
     fn read_address(&mut self, address: Address) -> Address {
         let lower = self.read(address);
-        let higher = self.read(address.incr_lower());
+        let higher = self.read(address + 1);
         Address::from_bytes(higher, lower)
     }
 
@@ -101,20 +345,55 @@ impl<M: Memory> CPU<M> {
         self.memory.write(address, byte);
     }
 
+    /// Run instructions until `halt` returns true, or `max_instructions` have run.
+    /// Returns the number of instructions run.
+    pub fn run_until(
+        &mut self,
+        max_instructions: u64,
+        mut halt: impl FnMut(&mut Self) -> bool,
+    ) -> u64 {
+        let mut instructions = 0;
+        while instructions < max_instructions && !halt(self) {
+            self.run_instruction();
+            instructions += 1;
+        }
+        instructions
+    }
+
+    /// Run one instruction, returning the number of cycles taken. Panics on an unsupported opcode.
     pub fn run_instruction(&mut self) -> u8 {
+        self.try_run_instruction()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Run one instruction, returning the number of cycles taken. On an unsupported opcode, the
+    /// program counter is left pointing at it.
+    pub fn try_run_instruction(&mut self) -> Result<u8, CpuError> {
+        if self.halted {
+            // The clock keeps running, but the CPU does nothing
+            return Ok(1);
+        }
+
         self.cycle_count = 0;
 
-        let instruction = Instruction::from_opcode(self.incr_program_counter());
-        trace!("        {:?}", instruction);
+        let opcode_address = self.program_counter;
+        let opcode = self.incr_program_counter();
 
         if self.non_maskable_interrupt {
             self.non_maskable_interrupt = false;
             self.interrupt(NMI_VECTOR, false);
+        } else if self.interrupt_request && !self.status.contains(Status::INTERRUPT_DISABLE) {
+            self.interrupt(INTERRUPT_VECTOR, false);
         } else {
+            let Some(instruction) = Instruction::try_from_opcode(opcode) else {
+                self.program_counter = opcode_address;
+                return Err(CpuError::UnknownOpcode(opcode, opcode_address));
+            };
+            trace!("        {:?}", instruction);
             self.handle_instruction(instruction);
         }
 
-        self.cycle_count
+        Ok(self.cycle_count)
     }
 
     fn handle_instruction(&mut self, instruction: Instruction) {
@@ -210,11 +489,20 @@ impl<M: Memory> CPU<M> {
             RLA(addressing_mode) => self.rla(addressing_mode),
             SRE(addressing_mode) => self.sre(addressing_mode),
             RRA(addressing_mode) => self.rra(addressing_mode),
+            ANC => self.anc(),
+            ALR => self.alr(),
+            ARR => self.arr(),
+            AXS => self.axs(),
+            JAM => self.jam(),
         }
     }
 
     fn sub_from_accumulator(&mut self, value: u8) {
-        self.add_to_accumulator(!value);
+        if self.decimal_mode() {
+            self.sub_decimal_from_accumulator(value);
+        } else {
+            self.add_binary_to_accumulator(!value);
+        }
     }
 
     fn interrupt(&mut self, address_vector: Address, break_flag: bool) {
@@ -225,6 +513,7 @@ impl<M: Memory> CPU<M> {
         self.push_stack(data.higher());
         self.push_stack(data.lower());
         self.push_status(break_flag);
+        self.status.insert(Status::INTERRUPT_DISABLE);
 
         self.program_counter = self.read_address(address_vector);
     }
@@ -236,6 +525,25 @@ impl<M: Memory> CPU<M> {
     }
 
     fn add_to_accumulator(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.add_decimal_to_accumulator(value);
+        } else {
+            self.add_binary_to_accumulator(value);
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    fn decimal_mode(&self) -> bool {
+        self.status.contains(Status::DECIMAL) && self.decimal_enabled
+    }
+
+    // Without the feature this is constant, so the decimal arithmetic is compiled away
+    #[cfg(not(feature = "decimal"))]
+    fn decimal_mode(&self) -> bool {
+        false
+    }
+
+    fn add_binary_to_accumulator(&mut self, value: u8) {
         let accumulator = self.accumulator;
 
         let carry_in = self.status.contains(Status::CARRY) as u16;
@@ -255,6 +563,51 @@ impl<M: Memory> CPU<M> {
         self.status.set(Status::CARRY, carry_out);
     }
 
+    fn add_decimal_to_accumulator(&mut self, value: u8) {
+        let accumulator = u16::from(self.accumulator);
+        let value = u16::from(value);
+        let carry_in = self.status.contains(Status::CARRY) as u16;
+
+        let mut low = (accumulator & 0x0f) + (value & 0x0f) + carry_in;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut result = (accumulator & 0xf0) + (value & 0xf0) + low;
+
+        // The zero flag comes from the binary result, and the negative and overflow flags from the
+        // result before the high digit is adjusted.
+        let binary_result = (accumulator + value + carry_in) as u8;
+        let overflow = !(accumulator ^ value) & (accumulator ^ result) & 0x80 != 0;
+        self.status.set(Status::ZERO, binary_result == 0);
+        self.status.set(Status::NEGATIVE, result & 0x80 != 0);
+        self.status.set(Status::OVERFLOW, overflow);
+
+        if result >= 0xa0 {
+            result += 0x60;
+        }
+        self.status.set(Status::CARRY, result > 0xff);
+        self.accumulator = result as u8;
+    }
+
+    fn sub_decimal_from_accumulator(&mut self, value: u8) {
+        let accumulator = i16::from(self.accumulator);
+        let carry_in = self.status.contains(Status::CARRY) as i16;
+
+        // All flags are set the same as binary subtraction
+        self.add_binary_to_accumulator(!value);
+
+        let value = i16::from(value);
+        let mut low = (accumulator & 0x0f) - (value & 0x0f) + carry_in - 1;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0f) - 0x10;
+        }
+        let mut result = (accumulator & 0xf0) - (value & 0xf0) + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+        self.accumulator = result as u8;
+    }
+
     fn compare(&mut self, register: u8, value: u8) {
         let (result, carry) = register.overflowing_sub(value);
         self.status.set(Status::CARRY, !carry);
@@ -338,7 +691,7 @@ impl<M: Memory> CPU<M> {
 
     /// When instructions have no arguments the CPU still reads the value - emulate to make the clock cycle is correct.
     fn ignore_argument(&mut self) {
-        self.read(self.program_counter);
+        self.dummy_read(self.program_counter);
     }
 
     fn fetch_address_at_program_counter(&mut self) -> Address {
@@ -391,6 +744,7 @@ impl fmt::Display for Reference {
 
 bitflags! {
     #[derive(Copy, Clone, Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     struct Status: u8 {
         const NEGATIVE          = 0b1000_0000;
         const OVERFLOW          = 0b0100_0000;
@@ -416,14 +770,15 @@ mod tests {
 
     use crate::cpu::addressing_modes::{
         BITAddressingMode, CompareAddressingMode, FlexibleAddressingMode, IncDecAddressingMode,
-        JumpAddressingMode, LDXAddressingMode, LDYAddressingMode, STXAddressingMode,
-        STYAddressingMode, ShiftAddressingMode, StoreAddressingMode,
+        JumpAddressingMode, LAXAddressingMode, LDXAddressingMode, LDYAddressingMode,
+        SAXAddressingMode, STXAddressingMode, STYAddressingMode, ShiftAddressingMode,
+        StoreAddressingMode,
     };
     use crate::mem;
     use crate::ArrayMemory;
     use crate::Instruction::{
-        ADC, AND, ASL, BCC, BIT, CMP, CPX, CPY, DEC, EOR, INC, JMP, LDA, LDX, LDY, LSR, ORA, ROL,
-        ROR, SBC, STA, STX, STY, TAX,
+        ADC, AND, ASL, BCC, BIT, CMP, CPX, CPY, DCP, DEC, EOR, IGN, INC, ISC, JMP, LAX, LDA, LDX,
+        LDY, LSR, ORA, RLA, ROL, ROR, RRA, SAX, SBC, SLO, SRE, STA, STX, STY, TAX,
     };
 
     use super::instructions::*;
@@ -577,6 +932,84 @@ mod tests {
         assert_eq!(cpu.program_counter, Address::new(0x5678));
     }
 
+    #[test]
+    fn on_irq_push_program_counter_and_status_with_clear_break_flag_to_stack() {
+        let mut cpu = run_instr(mem!(0x1234 => { INX }), |cpu| {
+            cpu.program_counter = Address::new(0x1234);
+            cpu.status = Status::from_bits_truncate(0b1001_1000);
+            cpu.stack_pointer.0 = 6;
+            cpu.interrupt_request = true;
+        });
+
+        assert_eq!(cpu.read(stack::BASE + 6), 0x12);
+        assert_eq!(cpu.read(stack::BASE + 5), 0x34);
+        assert_eq!(cpu.read(stack::BASE + 4), 0b1010_1000);
+        assert_eq!(cpu.stack_pointer.0, 3);
+    }
+
+    #[test]
+    fn on_irq_jumps_to_address_at_interrupt_vector_and_disables_interrupts() {
+        let cpu = run_instr(
+            mem!(
+                0x1234 => { INX }
+                0xfffe => { 0x78, 0x56 }
+            ),
+            |cpu| {
+                cpu.program_counter = Address::new(0x1234);
+                cpu.interrupt_request = true;
+            },
+        );
+
+        assert_eq!(cpu.program_counter, Address::new(0x5678));
+        assert!(cpu.status.contains(Status::INTERRUPT_DISABLE));
+        assert_eq!(cpu.x, 0);
+    }
+
+    #[test]
+    fn irq_is_ignored_when_interrupts_are_disabled() {
+        let cpu = run_instr(mem!(0x1234 => { INX }), |cpu| {
+            cpu.program_counter = Address::new(0x1234);
+            cpu.status = Status::INTERRUPT_DISABLE;
+            cpu.interrupt_request = true;
+        });
+
+        assert_eq!(cpu.program_counter, Address::new(0x1235));
+        assert_eq!(cpu.x, 1);
+    }
+
+    #[test]
+    fn irq_stays_asserted_until_acknowledged() {
+        let mut cpu = run_instr(mem!(0x1234 => { INX }), |cpu| {
+            cpu.program_counter = Address::new(0x1234);
+            cpu.request_irq();
+        });
+
+        assert!(cpu.irq_pending());
+
+        cpu.acknowledge_irq();
+
+        assert!(!cpu.irq_pending());
+    }
+
+    #[test]
+    fn non_maskable_interrupt_takes_priority_over_irq() {
+        let cpu = run_instr(
+            mem!(
+                0x1234 => { INX }
+                0xfffa => { 0x78, 0x56 }
+                0xfffe => { 0xbc, 0x9a }
+            ),
+            |cpu| {
+                cpu.program_counter = Address::new(0x1234);
+                cpu.non_maskable_interrupt = true;
+                cpu.interrupt_request = true;
+            },
+        );
+
+        assert_eq!(cpu.program_counter, Address::new(0x5678));
+        assert!(cpu.interrupt_request);
+    }
+
     #[test]
     fn calling_non_maskable_interrupt_sets_interrupt_flag() {
         let mut cpu = CPU::from_memory(mem!());
@@ -797,7 +1230,6 @@ mod tests {
 
         rts = { RTS, 6, Normal },
 
-        // TODO: branch failure/success/page cases
         bcc = { BCC, 3, Normal },
         bcc_cross = { BCC, 4, PageCross },
 
@@ -841,6 +1273,104 @@ mod tests {
         nop = { NOP, 2, Normal },
 
         rti = { RTI, 6, Normal },
+
+        anc = { ANC, 2, Normal },
+
+        alr = { ALR, 2, Normal },
+
+        arr = { ARR, 2, Normal },
+
+        axs = { AXS, 2, Normal },
+
+        jam = { JAM, 2, Normal },
+
+        ign_zpa = { IGN(IncDecAddressingMode::ZeroPage), 3, Normal },
+        ign_zpx = { IGN(IncDecAddressingMode::ZeroPageX), 4, Normal },
+        ign_abs = { IGN(IncDecAddressingMode::Absolute), 4, Normal },
+        ign_abx = { IGN(IncDecAddressingMode::AbsoluteX), 4, Normal },
+        ign_abx_cross = { IGN(IncDecAddressingMode::AbsoluteX), 5, PageCross },
+
+        skb = { SKB, 2, Normal },
+
+        lax_zpa = { LAX(LAXAddressingMode::ZeroPage), 3, Normal },
+        lax_zpy = { LAX(LAXAddressingMode::ZeroPageY), 4, Normal },
+        lax_abs = { LAX(LAXAddressingMode::Absolute), 4, Normal },
+        lax_aby = { LAX(LAXAddressingMode::AbsoluteY), 4, Normal },
+        lax_aby_cross = { LAX(LAXAddressingMode::AbsoluteY), 5, PageCross },
+        lax_idx = { LAX(LAXAddressingMode::IndexedIndirect), 6, Normal },
+        lax_idy = { LAX(LAXAddressingMode::IndirectIndexed), 5, Normal },
+        lax_idy_cross = { LAX(LAXAddressingMode::IndirectIndexed), 6, PageCross },
+
+        sax_zpa = { SAX(SAXAddressingMode::ZeroPage), 3, Normal },
+        sax_zpy = { SAX(SAXAddressingMode::ZeroPageY), 4, Normal },
+        sax_abs = { SAX(SAXAddressingMode::Absolute), 4, Normal },
+        sax_idx = { SAX(SAXAddressingMode::IndexedIndirect), 6, Normal },
+
+        dcp_zpa = { DCP(StoreAddressingMode::ZeroPage), 5, Normal },
+        dcp_zpx = { DCP(StoreAddressingMode::ZeroPageX), 6, Normal },
+        dcp_abs = { DCP(StoreAddressingMode::Absolute), 6, Normal },
+        dcp_abx = { DCP(StoreAddressingMode::AbsoluteX), 7, Normal },
+        dcp_abx_cross = { DCP(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        dcp_aby = { DCP(StoreAddressingMode::AbsoluteY), 7, Normal },
+        dcp_aby_cross = { DCP(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        dcp_idx = { DCP(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        dcp_idy = { DCP(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        dcp_idy_cross = { DCP(StoreAddressingMode::IndirectIndexed), 8, PageCross },
+
+        isc_zpa = { ISC(StoreAddressingMode::ZeroPage), 5, Normal },
+        isc_zpx = { ISC(StoreAddressingMode::ZeroPageX), 6, Normal },
+        isc_abs = { ISC(StoreAddressingMode::Absolute), 6, Normal },
+        isc_abx = { ISC(StoreAddressingMode::AbsoluteX), 7, Normal },
+        isc_abx_cross = { ISC(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        isc_aby = { ISC(StoreAddressingMode::AbsoluteY), 7, Normal },
+        isc_aby_cross = { ISC(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        isc_idx = { ISC(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        isc_idy = { ISC(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        isc_idy_cross = { ISC(StoreAddressingMode::IndirectIndexed), 8, PageCross },
+
+        slo_zpa = { SLO(StoreAddressingMode::ZeroPage), 5, Normal },
+        slo_zpx = { SLO(StoreAddressingMode::ZeroPageX), 6, Normal },
+        slo_abs = { SLO(StoreAddressingMode::Absolute), 6, Normal },
+        slo_abx = { SLO(StoreAddressingMode::AbsoluteX), 7, Normal },
+        slo_abx_cross = { SLO(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        slo_aby = { SLO(StoreAddressingMode::AbsoluteY), 7, Normal },
+        slo_aby_cross = { SLO(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        slo_idx = { SLO(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        slo_idy = { SLO(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        slo_idy_cross = { SLO(StoreAddressingMode::IndirectIndexed), 8, PageCross },
+
+        rla_zpa = { RLA(StoreAddressingMode::ZeroPage), 5, Normal },
+        rla_zpx = { RLA(StoreAddressingMode::ZeroPageX), 6, Normal },
+        rla_abs = { RLA(StoreAddressingMode::Absolute), 6, Normal },
+        rla_abx = { RLA(StoreAddressingMode::AbsoluteX), 7, Normal },
+        rla_abx_cross = { RLA(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        rla_aby = { RLA(StoreAddressingMode::AbsoluteY), 7, Normal },
+        rla_aby_cross = { RLA(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        rla_idx = { RLA(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        rla_idy = { RLA(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        rla_idy_cross = { RLA(StoreAddressingMode::IndirectIndexed), 8, PageCross },
+
+        sre_zpa = { SRE(StoreAddressingMode::ZeroPage), 5, Normal },
+        sre_zpx = { SRE(StoreAddressingMode::ZeroPageX), 6, Normal },
+        sre_abs = { SRE(StoreAddressingMode::Absolute), 6, Normal },
+        sre_abx = { SRE(StoreAddressingMode::AbsoluteX), 7, Normal },
+        sre_abx_cross = { SRE(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        sre_aby = { SRE(StoreAddressingMode::AbsoluteY), 7, Normal },
+        sre_aby_cross = { SRE(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        sre_idx = { SRE(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        sre_idy = { SRE(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        sre_idy_cross = { SRE(StoreAddressingMode::IndirectIndexed), 8, PageCross },
+
+        rra_zpa = { RRA(StoreAddressingMode::ZeroPage), 5, Normal },
+        rra_zpx = { RRA(StoreAddressingMode::ZeroPageX), 6, Normal },
+        rra_abs = { RRA(StoreAddressingMode::Absolute), 6, Normal },
+        rra_abx = { RRA(StoreAddressingMode::AbsoluteX), 7, Normal },
+        rra_abx_cross = { RRA(StoreAddressingMode::AbsoluteX), 7, PageCross },
+        rra_aby = { RRA(StoreAddressingMode::AbsoluteY), 7, Normal },
+        rra_aby_cross = { RRA(StoreAddressingMode::AbsoluteY), 7, PageCross },
+        rra_idx = { RRA(StoreAddressingMode::IndexedIndirect), 8, Normal },
+        rra_idy = { RRA(StoreAddressingMode::IndirectIndexed), 8, Normal },
+        rra_idy_cross = { RRA(StoreAddressingMode::IndirectIndexed), 8, PageCross },
     )]
     fn basic_instructions_return_correct_number_of_cycles(
         instruction: Instruction,
@@ -864,6 +1394,27 @@ mod tests {
         assert_eq!(actual_cycles, expected_cycles, "{:?}", instruction);
     }
 
+    #[test]
+    fn instructions_take_base_cycles_without_page_cross_or_branch() {
+        for opcode in 0..=0xFF {
+            let Some(instruction) = Instruction::try_from_opcode(opcode) else {
+                continue;
+            };
+            // Every branch is skipped with one of these
+            let cycles = [Status::empty(), Status::all()]
+                .into_iter()
+                .map(|status| {
+                    let mut cpu = CPU::from_memory(mem!(opcode));
+                    cpu.status = status;
+                    cpu.run_instruction()
+                })
+                .min()
+                .unwrap();
+
+            assert_eq!(cycles, instruction.base_cycles(), "{:?}", instruction);
+        }
+    }
+
     #[test]
     fn instruction_sequence_return_correct_number_of_cycles() {
         let start = Address::new(0xE084);
@@ -934,6 +1485,187 @@ mod tests {
         assert_eq!(cpu.program_counter, start);
     }
 
+    #[test]
+    fn accurate_profile_performs_dummy_reads_on_bus() {
+        let mut cpu = CPU::from_memory(LoggingMemory::new(mem!(0x1234 => { INX })));
+        cpu.program_counter = Address::new(0x1234);
+        cpu.memory.reads.clear();
+
+        let cycles = cpu.run_instruction();
+
+        assert_eq!(cycles, 2);
+        assert_eq!(
+            cpu.memory.reads,
+            [Address::new(0x1234), Address::new(0x1235)]
+        );
+    }
+
+    #[test]
+    fn fast_profile_skips_dummy_reads_on_bus() {
+        let mut cpu = CPU::from_memory(LoggingMemory::new(mem!(0x1234 => { INX })));
+        cpu.set_accuracy_profile(AccuracyProfile::Fast);
+        cpu.program_counter = Address::new(0x1234);
+        cpu.memory.reads.clear();
+
+        let cycles = cpu.run_instruction();
+
+        assert_eq!(cycles, 2);
+        assert_eq!(cpu.memory.reads, [Address::new(0x1234)]);
+    }
+
+    #[test]
+    fn registers_returns_snapshot_of_cpu_registers() {
+        let mut cpu = CPU::from_memory(ArrayMemory::default());
+        cpu.accumulator = 0x12;
+        cpu.program_counter = Address::new(0x1234);
+        cpu.x = 0x34;
+        cpu.y = 0x56;
+        cpu.stack_pointer = StackPointer(0xFD);
+        cpu.status = Status::CARRY;
+
+        assert_eq!(
+            cpu.registers(),
+            Registers {
+                accumulator: 0x12,
+                program_counter: Address::new(0x1234),
+                x: 0x34,
+                y: 0x56,
+                stack_pointer: 0xFD,
+                status: Status::CARRY.bits(),
+            }
+        );
+    }
+
+    #[test]
+    fn register_accessors_reflect_values_after_instructions() {
+        let memory = mem!(
+            0x8000 => { LDA_IMM, 0x80u8, LDX_IMM, 0x12u8, LDY_IMM, 0x34u8, TXS, SEC }
+            0xFFFC => { 0x00u8, 0x80u8 }
+        );
+        let mut cpu = CPU::from_memory(memory);
+        cpu.status = Status::empty();
+
+        cpu.run_until(5, |_| false);
+
+        assert_eq!(cpu.accumulator(), 0x80);
+        assert_eq!(cpu.x(), 0x12);
+        assert_eq!(cpu.y(), 0x34);
+        assert_eq!(cpu.stack_pointer_value(), 0x12);
+        assert_eq!(cpu.status_bits(), Status::CARRY.bits());
+        assert_eq!(cpu.program_counter(), Address::new(0x8008));
+    }
+
+    #[test]
+    fn unknown_opcode_is_an_error_leaving_pc_at_opcode() {
+        let memory = mem!(
+            0x8000 => { NOP, 0x8Bu8 }
+            0xFFFC => { 0x00u8, 0x80u8 }
+        );
+        let mut cpu = CPU::from_memory(memory);
+
+        assert_eq!(cpu.try_run_instruction(), Ok(2));
+
+        assert_eq!(
+            cpu.try_run_instruction(),
+            Err(CpuError::UnknownOpcode(0x8B, Address::new(0x8001)))
+        );
+        assert_eq!(cpu.program_counter(), Address::new(0x8001));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unrecognised opcode 0x8b at 0x8000")]
+    fn run_instruction_panics_on_unknown_opcode() {
+        let mut cpu = CPU::from_memory(mem!(0x8000 => { 0x8Bu8 } 0xFFFC => { 0x00u8, 0x80u8 }));
+
+        cpu.run_instruction();
+    }
+
+    #[test]
+    fn reset_jumps_to_reset_vector_and_disables_interrupts() {
+        let mut cpu = CPU::from_memory(mem!(0xFFFC => { 0x34u8, 0x12u8 }));
+        cpu.program_counter = Address::new(0x8000);
+        cpu.accumulator = 0x56;
+        cpu.stack_pointer = StackPointer(0xFD);
+
+        cpu.reset();
+
+        assert_eq!(cpu.program_counter, Address::new(0x1234));
+        assert_eq!(cpu.accumulator, 0x56);
+        assert_eq!(cpu.stack_pointer, StackPointer(0xFA));
+        assert!(cpu.status.contains(Status::INTERRUPT_DISABLE));
+    }
+
+    #[test]
+    fn power_on_resets_registers() {
+        let mut cpu = CPU::from_memory(mem!(0xFFFC => { 0x34u8, 0x12u8 }));
+        cpu.accumulator = 0x56;
+        cpu.x = 0x78;
+        cpu.status = Status::CARRY;
+
+        cpu.power_on();
+
+        assert_eq!(cpu.program_counter, Address::new(0x1234));
+        assert_eq!(cpu.accumulator, 0);
+        assert_eq!(cpu.x, 0);
+        assert!(cpu.status.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn state_types_are_serializable() {
+        fn assert_serializable<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+
+        assert_serializable::<Registers>();
+        assert_serializable::<crate::input::Controller>();
+        assert_serializable::<crate::mapper::MapperKind>();
+        assert_serializable::<crate::MixMode>();
+        assert_serializable::<crate::Region>();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn cpu_snapshot_round_trips_through_json() {
+        let mut cpu = CPU::from_memory(ArrayMemory::default());
+        cpu.accumulator = 0x12;
+        cpu.program_counter = Address::new(0x1234);
+        cpu.x = 0x34;
+        cpu.y = 0x56;
+        cpu.stack_pointer = StackPointer(0xFD);
+        cpu.status = Status::CARRY | Status::NEGATIVE;
+        let registers = cpu.registers();
+
+        let json = serde_json::to_string(&registers).unwrap();
+        let deserialized: Registers = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized, registers);
+    }
+
+    /// Memory that records every address read from
+    struct LoggingMemory {
+        memory: ArrayMemory,
+        reads: Vec<Address>,
+    }
+
+    impl LoggingMemory {
+        fn new(memory: ArrayMemory) -> Self {
+            LoggingMemory {
+                memory,
+                reads: vec![],
+            }
+        }
+    }
+
+    impl Memory for LoggingMemory {
+        fn read(&mut self, address: Address) -> u8 {
+            self.reads.push(address);
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: Address, byte: u8) {
+            self.memory.write(address, byte);
+        }
+    }
+
     pub fn run_instr<F: FnOnce(&mut CPU<ArrayMemory>)>(
         memory: ArrayMemory,
         cpu_setup: F,
@@ -944,7 +1676,7 @@ mod tests {
 
         cpu.run_instruction();
 
-        hexdump::hexdump(&cpu.memory.slice()[..0x200]);
+        hexdump::hexdump(&cpu.memory.as_slice()[..0x200]);
 
         cpu
     }
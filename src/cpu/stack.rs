@@ -7,6 +7,7 @@ pub const BASE: Address = Address::new(0x0100);
 /// S - 8-bit stack pointer.
 /// Index into the stack when combined with [BASE].
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StackPointer(pub u8);
 
 impl StackPointer {
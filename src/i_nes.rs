@@ -4,12 +4,31 @@ use std::io;
 use std::io::Read;
 
 use crate::cartridge::Cartridge;
-use crate::mapper::Mapper;
+use crate::mapper::{MapperKind, Mirroring};
+
+const MAGIC: [u8; 4] = *b"NES\x1A";
 
 const PRG_ROM_SIZE_LOCATION: usize = 4;
 const CHR_ROM_SIZE_LOCATION: usize = 5;
+const FLAGS_6_LOCATION: usize = 6;
 const MAPPER_LOW_LOCATION: usize = 6;
 const MAPPER_HIGH_LOCATION: usize = 7;
+const PRG_RAM_SIZE_LOCATION: usize = 8;
+
+// NES 2.0 header fields
+const NES_2_MAPPER_LOCATION: usize = 8;
+const NES_2_ROM_SIZE_LOCATION: usize = 9;
+const NES_2_PRG_RAM_SIZE_LOCATION: usize = 10;
+const NES_2_CHR_RAM_SIZE_LOCATION: usize = 11;
+
+const VERTICAL_MIRRORING_FLAG: u8 = 0b0000_0001;
+const BATTERY_FLAG: u8 = 0b0000_0010;
+const TRAINER_FLAG: u8 = 0b0000_0100;
+const FOUR_SCREEN_FLAG: u8 = 0b0000_1000;
+const NES_2_MASK: u8 = 0b0000_1100;
+const NES_2_SIGNATURE: u8 = 0b0000_1000;
+
+const TRAINER_SIZE: usize = 512;
 
 const _8KB: usize = 8_192;
 const _16KB: usize = 16_384;
@@ -17,20 +36,60 @@ const _16KB: usize = 16_384;
 #[derive(Debug)]
 pub enum INesReadError {
     IO(io::Error),
-    UnrecognisedMapper(u8),
+    /// The file doesn't start with `NES<EOF>`, so isn't an iNES file.
+    BadMagic([u8; 4]),
+    UnsupportedMapper(u16),
+    // The file is shorter than the sizes declared in the header
+    TruncatedTrainer {
+        expected: usize,
+        actual: usize,
+    },
+    TruncatedPrg {
+        expected: usize,
+        actual: usize,
+    },
+    TruncatedChr {
+        expected: usize,
+        actual: usize,
+    },
 }
 
 impl fmt::Display for INesReadError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             INesReadError::IO(error) => fmt::Display::fmt(error, f),
-            INesReadError::UnrecognisedMapper(mapper) => {
-                write!(f, "Unrecognised mapper: {}", mapper)
+            INesReadError::BadMagic(magic) => {
+                write!(f, "Not an iNES file, starts with {:02X?}", magic)
+            }
+            INesReadError::UnsupportedMapper(mapper) => {
+                write!(f, "Unsupported mapper: {}", mapper)
+            }
+            INesReadError::TruncatedTrainer { expected, actual } => {
+                write_truncated(f, "trainer", *expected, *actual)
+            }
+            INesReadError::TruncatedPrg { expected, actual } => {
+                write_truncated(f, "PRG ROM", *expected, *actual)
+            }
+            INesReadError::TruncatedChr { expected, actual } => {
+                write_truncated(f, "CHR ROM", *expected, *actual)
             }
         }
     }
 }
 
+fn write_truncated(
+    f: &mut fmt::Formatter<'_>,
+    section: &str,
+    expected: usize,
+    actual: usize,
+) -> fmt::Result {
+    write!(
+        f,
+        "Header declares {} bytes of {}, but file only contains {}",
+        expected, section, actual
+    )
+}
+
 impl Error for INesReadError {}
 
 impl From<io::Error> for INesReadError {
@@ -40,73 +99,221 @@ impl From<io::Error> for INesReadError {
 }
 
 pub struct INes {
+    // Loaded into PRG RAM at $7000
+    trainer: Option<Box<[u8]>>,
     prg_rom: Box<[u8]>,
     chr_rom: Box<[u8]>,
     chr_ram_enabled: bool,
-    mapper: Mapper,
+    mapper: MapperKind,
+    mirroring: Mirroring,
+    prg_ram_size: usize,
+    has_battery: bool,
 }
 
 impl INes {
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, INesReadError> {
+    pub fn read<R: Read>(reader: R) -> Result<Self, INesReadError> {
+        Self::read_with_mapper_override(reader, None)
+    }
+
+    /// Read an iNES file, using the given mapper number instead of the one in the header if
+    /// provided. This is useful for ROM dumps with an incorrect header. NES 2.0 mapper numbers up to
+    /// 4095 can be given.
+    pub fn read_with_mapper_override<R: Read>(
+        mut reader: R,
+        mapper_override: Option<u16>,
+    ) -> Result<Self, INesReadError> {
         let mut header = [0u8; 16];
         reader.read_exact(&mut header)?;
 
-        let mapper = INes::mapper(header)?;
+        let magic = [header[0], header[1], header[2], header[3]];
+        if magic != MAGIC {
+            return Err(INesReadError::BadMagic(magic));
+        }
+
+        let nes_2 = header[MAPPER_HIGH_LOCATION] & NES_2_MASK == NES_2_SIGNATURE;
+        log::info!("Read format as {}", if nes_2 { "NES 2.0" } else { "iNES" });
+
+        let mapper = match mapper_override {
+            Some(mapper) => MapperKind::try_from(mapper)?,
+            None => MapperKind::try_from(INes::mapper(header, nes_2))?,
+        };
         log::info!("Read mapper as {:?}", mapper);
 
-        let prg_rom_size = header[PRG_ROM_SIZE_LOCATION] as usize * _16KB;
+        let flags_6 = header[FLAGS_6_LOCATION];
+
+        let mirroring = if flags_6 & FOUR_SCREEN_FLAG != 0 {
+            Mirroring::FourScreen
+        } else if flags_6 & VERTICAL_MIRRORING_FLAG != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        log::info!("Read mirroring as {:?}", mirroring);
+
+        let has_battery = flags_6 & BATTERY_FLAG != 0;
+
+        let prg_ram_size = if nes_2 {
+            let sizes = header[NES_2_PRG_RAM_SIZE_LOCATION];
+            ram_size(sizes & 0x0f) + ram_size(sizes >> 4)
+        } else {
+            // A size of 0 means 8KB, for compatibility with older files
+            (header[PRG_RAM_SIZE_LOCATION] as usize).max(1) * _8KB
+        };
+        log::info!("Read PRG RAM size as {}", prg_ram_size);
+
+        let rom_size_msb = header[NES_2_ROM_SIZE_LOCATION];
+
+        let prg_rom_size = if nes_2 {
+            rom_size(header[PRG_ROM_SIZE_LOCATION], rom_size_msb & 0x0f, _16KB)
+        } else {
+            header[PRG_ROM_SIZE_LOCATION] as usize * _16KB
+        };
         log::info!("Read PRG ROM size as {}", prg_rom_size);
 
-        let mut prg_rom = vec![0u8; prg_rom_size];
-        reader.read_exact(prg_rom.as_mut())?;
+        // The trainer comes between the header and PRG ROM
+        let trainer = if flags_6 & TRAINER_FLAG != 0 {
+            log::info!("Read trainer");
+            let trainer = read_section(&mut reader, TRAINER_SIZE, |expected, actual| {
+                INesReadError::TruncatedTrainer { expected, actual }
+            })?;
+            Some(trainer.into_boxed_slice())
+        } else {
+            None
+        };
+
+        let prg_rom = read_section(&mut reader, prg_rom_size, |expected, actual| {
+            INesReadError::TruncatedPrg { expected, actual }
+        })?;
 
-        let chr_rom_size = header[CHR_ROM_SIZE_LOCATION] as usize * _8KB;
+        let chr_rom_size = if nes_2 {
+            rom_size(header[CHR_ROM_SIZE_LOCATION], rom_size_msb >> 4, _8KB)
+        } else {
+            header[CHR_ROM_SIZE_LOCATION] as usize * _8KB
+        };
         log::info!("Read CHR ROM size as {}", chr_rom_size);
 
-        let mut chr_rom: Vec<u8>;
+        let chr_rom: Vec<u8>;
         let chr_ram_enabled: bool;
 
         if chr_rom_size == 0 {
-            // when CHR ROM size is zero, it should behave like 8KB RAM instead
-            chr_rom = vec![0u8; _8KB];
+            // when CHR ROM size is zero, it should behave like RAM instead, of at least 8KB
+            let chr_ram_size = if nes_2 {
+                let sizes = header[NES_2_CHR_RAM_SIZE_LOCATION];
+                ram_size(sizes & 0x0f) + ram_size(sizes >> 4)
+            } else {
+                0
+            };
+            chr_rom = vec![0u8; chr_ram_size.max(_8KB)];
             chr_ram_enabled = true;
         } else {
-            chr_rom = vec![0u8; chr_rom_size];
-            reader.read_exact(chr_rom.as_mut())?;
+            chr_rom = read_section(&mut reader, chr_rom_size, |expected, actual| {
+                INesReadError::TruncatedChr { expected, actual }
+            })?;
             chr_ram_enabled = false;
         };
 
         let ines = INes {
+            trainer,
             prg_rom: prg_rom.into_boxed_slice(),
             chr_rom: chr_rom.into_boxed_slice(),
             chr_ram_enabled,
             mapper,
+            mirroring,
+            prg_ram_size,
+            has_battery,
         };
 
         Ok(ines)
     }
 
+    /// Whether the cartridge has battery-backed PRG RAM, which should be persisted.
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    /// The 512-byte trainer, if the file has one.
+    pub fn trainer(&self) -> Option<&[u8]> {
+        self.trainer.as_deref()
+    }
+
     pub fn into_cartridge(self) -> Cartridge {
-        Cartridge::new(
+        let cartridge = Cartridge::new(
             self.prg_rom,
             self.chr_rom,
             self.chr_ram_enabled,
             self.mapper,
         )
+        .with_mirroring(self.mirroring)
+        .with_prg_ram(self.prg_ram_size, self.has_battery);
+
+        match &self.trainer {
+            Some(trainer) => cartridge.with_trainer(trainer),
+            None => cartridge,
+        }
+    }
+
+    fn mapper(header: [u8; 16], nes_2: bool) -> u16 {
+        let low = u16::from(header[MAPPER_LOW_LOCATION] >> 4);
+        let high = u16::from(header[MAPPER_HIGH_LOCATION] & 0b1111_0000);
+        let extended = if nes_2 {
+            u16::from(header[NES_2_MAPPER_LOCATION] & 0b0000_1111) << 8
+        } else {
+            0
+        };
+        extended | high | low
+    }
+}
+
+/// Size of PRG or CHR ROM in a NES 2.0 header, from the size LSB and the 4-bit MSB.
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        // Exponent-multiplier notation: 2^E * (MM * 2 + 1), where LSB is EEEEEEMM
+        let exponent = u32::from(lsb >> 2);
+        let multiplier = usize::from(lsb & 0b11) * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    } else {
+        (usize::from(msb) << 8 | usize::from(lsb)) * unit
+    }
+}
+
+/// Size of RAM in a NES 2.0 header, from a shift count where 0 means no RAM.
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
     }
+}
 
-    fn mapper(header: [u8; 16]) -> Result<Mapper, INesReadError> {
-        let low = header[MAPPER_LOW_LOCATION] >> 4;
-        let high = header[MAPPER_HIGH_LOCATION] & 0b1111_0000;
-        let byte = low | high;
-        Mapper::try_from(byte)
+/// Read a section of the given size, or the error from `truncated` with the expected and actual
+/// sizes if the file ends first.
+fn read_section<R: Read>(
+    reader: &mut R,
+    size: usize,
+    truncated: impl FnOnce(usize, usize) -> INesReadError,
+) -> Result<Vec<u8>, INesReadError> {
+    let mut data = vec![];
+    reader
+        .take(size.try_into().unwrap_or(u64::MAX))
+        .read_to_end(&mut data)?;
+
+    if data.len() != size {
+        return Err(truncated(size, data.len()));
     }
+
+    Ok(data)
 }
 
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
 
+    use crate::ppu::{NESPPUMemory, PPURegisters, PPU};
+    use crate::{Address, Memory};
+
     use super::*;
 
     #[test]
@@ -161,6 +368,25 @@ mod tests {
         assert_eq!(Vec::from(ines.chr_rom), chr_rom_data);
     }
 
+    #[test]
+    fn cartridge_exposes_raw_rom_data_from_ines_file() {
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let prg_rom_data: Vec<u8> = (0..32_768).map(|i| i as u8).collect();
+        let chr_rom_data: Vec<u8> = (0..8_192).map(|i| (i / 2) as u8).collect();
+
+        let prg_cursor = Cursor::new(prg_rom_data.clone());
+        let chr_cursor = Cursor::new(chr_rom_data.clone());
+        let cursor = Cursor::new(header).chain(prg_cursor).chain(chr_cursor);
+
+        let cartridge = INes::read(cursor).unwrap().into_cartridge();
+
+        assert_eq!(cartridge.prg_rom().len(), 2 * _16KB);
+        assert_eq!(cartridge.prg_rom(), prg_rom_data);
+        assert_eq!(cartridge.chr().len(), _8KB);
+        assert_eq!(cartridge.chr(), chr_rom_data);
+    }
+
     #[test]
     fn can_read_mapper_from_ines_file() {
         // this maps to mapper 19
@@ -175,6 +401,363 @@ mod tests {
 
         let ines = INes::read(cursor).unwrap();
 
-        assert_eq!(ines.mapper, Mapper::Namco129);
+        assert_eq!(ines.mapper, MapperKind::Namco129);
+        assert_eq!(ines.into_cartridge().mapper_number(), 19);
+    }
+
+    #[test]
+    fn unimplemented_mapper_is_an_error() {
+        // this maps to mapper 99
+        let low: u8 = 0b0011_0000;
+        let high: u8 = 0b0110_0000;
+
+        let header: [u8; 16] = [
+            0x4E, 0x45, 0x53, 0x1A, 2, 1, low, high, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let result = INes::read(cursor);
+
+        assert!(matches!(result, Err(INesReadError::UnsupportedMapper(99))));
+        assert_eq!(result.err().unwrap().to_string(), "Unsupported mapper: 99");
+    }
+
+    #[test]
+    fn mapper_number_round_trips_through_mapper_kind() {
+        for number in 0..=0xFFF {
+            if let Ok(kind) = MapperKind::try_from(number) {
+                assert_eq!(kind.number(), number);
+            }
+        }
+    }
+
+    #[test]
+    fn can_read_battery_flag_from_ines_file() {
+        let header: [u8; 16] = [
+            0x4E, 0x45, 0x53, 0x1A, 1, 1, 0b10, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let ines = INes::read(cursor).unwrap();
+
+        assert!(ines.has_battery());
+        let cartridge = ines.into_cartridge();
+        assert!(cartridge.has_battery());
+        assert_eq!(cartridge.prg_ram_size(), _8KB);
+    }
+
+    #[test]
+    fn can_read_mirroring_from_ines_file() {
+        for (flags_6, mirroring) in [
+            (0b0000, Mirroring::Horizontal),
+            (0b0001, Mirroring::Vertical),
+            (0b1000, Mirroring::FourScreen),
+            (0b1001, Mirroring::FourScreen),
+        ] {
+            let header: [u8; 16] = [
+                0x4E, 0x45, 0x53, 0x1A, 1, 1, flags_6, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ];
+            let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+            let cartridge = INes::read(cursor).unwrap().into_cartridge();
+
+            assert_eq!(cartridge.mirroring(), mirroring);
+        }
+    }
+
+    #[test]
+    fn cartridge_has_no_battery_when_flag_not_set() {
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let cartridge = INes::read(cursor).unwrap().into_cartridge();
+
+        assert!(!cartridge.has_battery());
+    }
+
+    #[test]
+    fn can_read_prg_ram_size_from_ines_file() {
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let cartridge = INes::read(cursor).unwrap().into_cartridge();
+
+        assert_eq!(cartridge.prg_ram_size(), 4 * _8KB);
+    }
+
+    #[test]
+    fn cartridge_without_chr_rom_has_chr_ram_writable_through_ppu() {
+        // NROM, 1 * 16KB of PRG ROM and no CHR ROM
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+        let ines = INes::read(cursor).unwrap();
+        let (_prg, chr) = ines.into_cartridge().split();
+        let mut ppu = PPU::with_memory(NESPPUMemory::new(chr));
+
+        ppu.write_address(0x01);
+        ppu.write_address(0x23);
+        ppu.write_data(0xab);
+
+        ppu.write_address(0x01);
+        ppu.write_address(0x23);
+        // The first read returns the PPU's read buffer
+        ppu.read_data();
+        assert_eq!(ppu.read_data(), 0xab);
+    }
+
+    #[test]
+    fn trainer_is_read_before_prg_rom_and_loaded_at_0x7000() {
+        // NROM, 1 * 16KB of PRG ROM, 1 * 8KB of CHR ROM, with a trainer
+        let header: [u8; 16] = [
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            1,
+            1,
+            TRAINER_FLAG,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let trainer: Vec<u8> = (0..TRAINER_SIZE).map(|i| (i % 7) as u8).collect();
+        let prg_rom_data: Vec<u8> = (0.._16KB).map(|i| (i / 3) as u8).collect();
+        let chr_rom_data = vec![0xCC; _8KB];
+
+        let cursor = Cursor::new(header)
+            .chain(Cursor::new(trainer.clone()))
+            .chain(Cursor::new(prg_rom_data.clone()))
+            .chain(Cursor::new(chr_rom_data.clone()));
+
+        let ines = INes::read(cursor).unwrap();
+
+        assert_eq!(ines.trainer(), Some(trainer.as_slice()));
+        assert_eq!(Vec::from(ines.prg_rom.clone()), prg_rom_data);
+        assert_eq!(Vec::from(ines.chr_rom.clone()), chr_rom_data);
+
+        let (mut prg, _) = ines.into_cartridge().split();
+        assert_eq!(prg.read(Address::new(0x6fff)), 0);
+        for (offset, &byte) in trainer.iter().enumerate() {
+            assert_eq!(prg.read(Address::new(0x7000 + offset as u16)), byte);
+        }
+        assert_eq!(prg.read(Address::new(0x7200)), 0);
+    }
+
+    #[test]
+    fn can_read_nes_2_header() {
+        // mapper 0x102, 2 * 16KB of PRG ROM, CHR RAM and 8KB of battery-backed PRG RAM
+        let header: [u8; 16] = [
+            0x4E, 0x45, 0x53, 0x1A, 2, 0, 0x22, 0x08, 0x01, 0, 0x70, 0x07, 0, 0, 0, 0,
+        ];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let result = INes::read(cursor);
+
+        // Mappers above 255 aren't supported, but the number should be read correctly
+        assert!(matches!(
+            result,
+            Err(INesReadError::UnsupportedMapper(0x102))
+        ));
+
+        let header: [u8; 16] = [
+            0x4E, 0x45, 0x53, 0x1A, 2, 0, 0x22, 0x08, 0x00, 0, 0x70, 0x08, 0, 0, 0, 0,
+        ];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let ines = INes::read(cursor).unwrap();
+
+        assert_eq!(ines.mapper, MapperKind::UxROM);
+        assert_eq!(ines.prg_rom.len(), 2 * _16KB);
+        assert!(ines.chr_ram_enabled);
+        assert_eq!(ines.chr_rom.len(), 16_384);
+        let cartridge = ines.into_cartridge();
+        assert!(cartridge.has_battery());
+        assert_eq!(cartridge.prg_ram_size(), _8KB);
+    }
+
+    #[test]
+    fn can_read_nes_2_extended_rom_sizes() {
+        // PRG ROM MSB of 1 makes 0x102 * 16KB, CHR ROM uses exponent-multiplier for 3 * 2^13
+        let header: [u8; 16] = [
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            0x02,
+            0b0011_0101,
+            0,
+            0x08,
+            0,
+            0xF1,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let ines = INes::read(cursor).unwrap();
+
+        assert_eq!(ines.prg_rom.len(), 0x102 * _16KB);
+        assert_eq!(ines.chr_rom.len(), 3 * _8KB);
+    }
+
+    #[test]
+    fn nes_2_header_can_have_no_prg_ram() {
+        let header: [u8; 16] = [
+            0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let cartridge = INes::read(cursor).unwrap().into_cartridge();
+
+        assert_eq!(cartridge.prg_ram_size(), 0);
+    }
+
+    #[test]
+    fn ines_file_shorter_than_declared_is_an_error() {
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let prg_rom_data = vec![0u8; _16KB];
+        let cursor = Cursor::new(header).chain(Cursor::new(prg_rom_data));
+
+        let result = INes::read(cursor);
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::TruncatedPrg {
+                expected: 32_768,
+                actual: 16_384,
+            })
+        ));
+    }
+
+    #[test]
+    fn cartridge_can_be_read_from_bytes() {
+        let mut bytes = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend((0.._16KB).map(|i| i as u8));
+        bytes.extend(vec![0xAB; _8KB]);
+
+        let cartridge = Cartridge::from_bytes(&bytes).unwrap();
+
+        assert_eq!(cartridge.prg_rom(), &bytes[16..16 + _16KB]);
+        assert_eq!(cartridge.chr(), vec![0xAB; _8KB]);
+    }
+
+    #[test]
+    fn bytes_without_ines_magic_are_an_error() {
+        let mut bytes = vec![0x4E, 0x45, 0x53, 0x00, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.resize(16 + _16KB + _8KB, 0);
+
+        let result = Cartridge::from_bytes(&bytes);
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::BadMagic([0x4E, 0x45, 0x53, 0x00]))
+        ));
+    }
+
+    #[test]
+    fn bytes_with_truncated_trainer_are_an_error() {
+        let mut bytes = vec![
+            0x4E, 0x45, 0x53, 0x1A, 1, 1, 0b100, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        bytes.resize(16 + 100, 0);
+
+        let result = Cartridge::from_bytes(&bytes);
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::TruncatedTrainer {
+                expected: 512,
+                actual: 100,
+            })
+        ));
+    }
+
+    #[test]
+    fn bytes_with_truncated_prg_rom_are_an_error() {
+        let mut bytes = vec![0x4E, 0x45, 0x53, 0x1A, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.resize(16 + _16KB + 1, 0);
+
+        let result = Cartridge::from_bytes(&bytes);
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::TruncatedPrg {
+                expected: 32_768,
+                actual: 16_385,
+            })
+        ));
+    }
+
+    #[test]
+    fn bytes_with_truncated_chr_rom_are_an_error() {
+        let mut bytes = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.resize(16 + _16KB + 8, 0);
+
+        let result = Cartridge::from_bytes(&bytes);
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::TruncatedChr {
+                expected: 8_192,
+                actual: 8,
+            })
+        ));
+    }
+
+    #[test]
+    fn bytes_with_unsupported_mapper_are_an_error() {
+        // Mapper 5 (MMC5)
+        let mut bytes = vec![
+            0x4E, 0x45, 0x53, 0x1A, 1, 1, 0x50, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        bytes.resize(16 + _16KB + _8KB, 0);
+
+        let result = Cartridge::from_bytes(&bytes);
+
+        assert!(matches!(result, Err(INesReadError::UnsupportedMapper(5))));
+    }
+
+    #[test]
+    fn can_override_mapper_from_ines_file() {
+        // header declares mapper 0 (NROM)
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 4, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        let prg_rom_data: Vec<u8> = (0..4 * _16KB).map(|i| (i / _16KB) as u8).collect();
+        let cursor = Cursor::new(header)
+            .chain(Cursor::new(prg_rom_data))
+            .chain(std::io::repeat(0));
+
+        let ines = INes::read_with_mapper_override(cursor, Some(2)).unwrap();
+        assert_eq!(ines.mapper, MapperKind::UxROM);
+
+        let mut prg = ines.into_cartridge().split().0;
+        assert_eq!(prg.read(Address::new(0x8000)), 0);
+        prg.write(Address::new(0x8000), 2);
+        assert_eq!(prg.read(Address::new(0x8000)), 2);
+        assert_eq!(prg.read(Address::new(0xc000)), 3);
+    }
+
+    #[test]
+    fn mapper_override_must_be_recognised() {
+        let header: [u8; 16] = [0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let cursor = Cursor::new(header).chain(std::io::repeat(0));
+
+        let result = INes::read_with_mapper_override(cursor, Some(0x123));
+
+        assert!(matches!(
+            result,
+            Err(INesReadError::UnsupportedMapper(0x123))
+        ));
     }
 }
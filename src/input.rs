@@ -1,20 +1,76 @@
 use bitflags::bitflags;
 
+use crate::{Color, Palette};
+
+// How long the Zapper's light sensor stays on after seeing a bright pixel
+const ZAPPER_LIGHT_SCANLINES: u8 = 20;
+// Average RGB brightness the Zapper's light sensor responds to
+const ZAPPER_BRIGHTNESS_THRESHOLD: u16 = 0xC0;
+
 pub trait Input {
     fn read(&mut self) -> u8;
     fn write(&mut self, value: u8);
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Controller {
     buttons: Buttons,
     strobe: bool,
-    read_cursor: u8,
+    // Button states captured when the strobe is turned off, shifted out one bit per read
+    latched: Buttons,
+    read_index: u8,
+    macros: Vec<ButtonMacro>,
+    turbo: Vec<Turbo>,
+}
+
+/// A timed sequence of button states, played back while the trigger button is held.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct ButtonMacro {
+    trigger: Buttons,
+    // Pairs of button states and how many frames to hold them for
+    sequence: Vec<(Buttons, u8)>,
+    // Frames since the trigger was pressed
+    frame: usize,
 }
 
-const CURSOR_START: u8 = 0b1000_0000;
+impl ButtonMacro {
+    fn buttons(&self) -> Buttons {
+        let mut end = 0;
+        for &(buttons, frames) in &self.sequence {
+            end += frames as usize;
+            if self.frame < end {
+                return buttons;
+            }
+        }
+        Buttons::empty()
+    }
+}
+
+/// A button that toggles on and off while held.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Turbo {
+    button: Buttons,
+    // Number of frames the button stays on, then off
+    rate: u8,
+    // Frames since the button was pressed
+    frame: usize,
+}
+
+impl Turbo {
+    fn on(&self) -> bool {
+        (self.frame / self.rate as usize) & 1 == 0
+    }
+}
 
 impl Controller {
+    /// The buttons currently held down, before any macros or turbo are applied.
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
     pub fn press(&mut self, buttons: Buttons) {
         self.buttons.insert(buttons);
     }
@@ -22,44 +78,159 @@ impl Controller {
     pub fn release(&mut self, buttons: Buttons) {
         self.buttons.remove(buttons);
     }
-}
 
-impl Default for Controller {
-    fn default() -> Self {
-        Self {
-            buttons: Buttons::default(),
-            strobe: false,
-            read_cursor: CURSOR_START,
+    /// Hold exactly `buttons`, releasing all others.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+
+    /// While `trigger` is held, play back `sequence` instead, holding each button state for the
+    /// given number of frames. The sequence plays once each time the trigger is pressed.
+    pub fn bind_macro(&mut self, trigger: Buttons, sequence: &[(Buttons, u8)]) {
+        self.macros.retain(|m| m.trigger != trigger);
+        self.macros.push(ButtonMacro {
+            trigger,
+            sequence: sequence.to_vec(),
+            frame: 0,
+        });
+    }
+
+    /// While `button` is held, toggle it on and off every `rate` frames. A rate of 0 turns turbo
+    /// off for the button.
+    pub fn set_turbo(&mut self, button: Buttons, rate: u8) {
+        self.turbo.retain(|t| t.button != button);
+        if rate != 0 {
+            self.turbo.push(Turbo {
+                button,
+                rate,
+                frame: 0,
+            });
+        }
+    }
+
+    /// Advance any turbo buttons. Called once per frame.
+    pub fn tick_turbo(&mut self) {
+        for turbo in &mut self.turbo {
+            if self.buttons.contains(turbo.button) {
+                turbo.frame += 1;
+            } else {
+                turbo.frame = 0;
+            }
+        }
+    }
+
+    /// Advance any macros being played back. Called once per frame.
+    pub fn end_frame(&mut self) {
+        for button_macro in &mut self.macros {
+            if self.buttons.contains(button_macro.trigger) {
+                button_macro.frame += 1;
+            } else {
+                button_macro.frame = 0;
+            }
+        }
+    }
+
+    fn state(&self) -> Buttons {
+        let mut state = self.buttons;
+        for button_macro in &self.macros {
+            if self.buttons.contains(button_macro.trigger) {
+                state.remove(button_macro.trigger);
+                state.insert(button_macro.buttons());
+            }
         }
+        for turbo in &self.turbo {
+            if self.buttons.contains(turbo.button) && !turbo.on() {
+                state.remove(turbo.button);
+            }
+        }
+        state
     }
 }
 
 impl Input for Controller {
     fn read(&mut self) -> u8 {
+        // While the strobe is on, the shift register is continuously reloaded
         if self.strobe {
-            self.read_cursor = CURSOR_START;
+            return self.state().contains(Buttons::A).into();
         }
 
-        let button_pressed = (self.buttons.bits() & self.read_cursor) != 0;
-
-        if !self.strobe {
-            self.read_cursor >>= 1;
+        // Buttons are read from A to RIGHT, then the shift register is filled with 1s
+        if self.read_index >= 8 {
+            return 1;
         }
 
-        button_pressed.into()
+        let bit = (self.latched.bits() >> (7 - self.read_index)) & 1;
+        self.read_index += 1;
+        bit
     }
 
     fn write(&mut self, value: u8) {
-        self.strobe = value & 0b1 != 0;
+        let strobe = value & 0b1 != 0;
 
-        if self.strobe {
-            self.read_cursor = CURSOR_START;
+        if self.strobe && !strobe {
+            self.latched = self.state();
+            self.read_index = 0;
         }
+
+        self.strobe = strobe;
     }
 }
 
+/// A light gun, which senses light from the pixel it's aimed at as the PPU draws it.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Zapper {
+    // The pixel the Zapper is pointed at, or None if it's pointed away from the screen
+    aim: Option<(u16, u16)>,
+    trigger: bool,
+    // Scanlines until the light sensor turns off
+    light_scanlines: u8,
+}
+
+impl Zapper {
+    pub fn aim(&mut self, x: u16, y: u16) {
+        self.aim = Some((x, y));
+    }
+
+    pub fn aim_off_screen(&mut self) {
+        self.aim = None;
+    }
+
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+
+    /// Called with each pixel as it's drawn, so the light sensor can see the aimed-at pixel.
+    /// `palette` is the one the pixel is displayed in.
+    pub fn observe_pixel(&mut self, x: u16, y: u16, color: Color, palette: &Palette) {
+        if x == 0 {
+            self.light_scanlines = self.light_scanlines.saturating_sub(1);
+        }
+
+        if self.aim == Some((x, y)) {
+            let (r, g, b) = palette.rgb(color);
+            let brightness = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+            if brightness >= ZAPPER_BRIGHTNESS_THRESHOLD {
+                self.light_scanlines = ZAPPER_LIGHT_SCANLINES;
+            }
+        }
+    }
+}
+
+impl Input for Zapper {
+    fn read(&mut self) -> u8 {
+        // Bit 3 is clear when light is detected, bit 4 is set while the trigger is pulled
+        let no_light = u8::from(self.light_scanlines == 0) << 3;
+        let trigger = u8::from(self.trigger) << 4;
+        no_light | trigger
+    }
+
+    fn write(&mut self, _value: u8) {}
+}
+
 bitflags! {
-    #[derive(Default, Debug, Copy, Clone)]
+    #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Buttons: u8 {
         const A      = 0b1000_0000;
         const B      = 0b0100_0000;
@@ -105,6 +276,15 @@ mod tests {
         assert_eq!(controller.buttons.bits(), 0b0111_0000);
     }
 
+    #[test]
+    fn setting_buttons_replaces_held_buttons() {
+        let mut controller = Controller::default();
+        controller.press(Buttons::A | Buttons::UP);
+
+        controller.set_buttons(Buttons::B | Buttons::START);
+        assert_eq!(controller.buttons(), Buttons::B | Buttons::START);
+    }
+
     #[test]
     fn when_strobe_is_toggled_off_button_status_is_reported() {
         let mut controller = Controller {
@@ -162,7 +342,7 @@ mod tests {
     }
 
     #[test]
-    fn after_reading_status_subsequent_reads_return_zero() {
+    fn after_reading_status_subsequent_reads_return_one() {
         let mut controller = Controller {
             buttons: Buttons::from_bits_truncate(0b1001_0110),
             ..Controller::default()
@@ -176,7 +356,163 @@ mod tests {
         }
 
         for _ in 0..100 {
-            assert_eq!(controller.read(), 0);
+            assert_eq!(controller.read(), 1);
+        }
+    }
+
+    #[test]
+    fn buttons_are_latched_when_strobe_is_turned_off() {
+        let mut controller = Controller::default();
+        controller.press(Buttons::A | Buttons::START | Buttons::LEFT);
+
+        controller.write(1);
+        controller.write(0);
+
+        // Changes after the latch aren't seen until the next strobe
+        controller.release(Buttons::A);
+        controller.press(Buttons::B | Buttons::RIGHT);
+
+        let reads: Vec<u8> = (0..10).map(|_| controller.read()).collect();
+        // A, B, Select, Start, Up, Down, Left, Right, then all 1s
+        assert_eq!(reads, [1, 0, 0, 1, 0, 0, 1, 0, 1, 1]);
+
+        controller.write(1);
+        controller.write(0);
+
+        let reads: Vec<u8> = (0..8).map(|_| controller.read()).collect();
+        assert_eq!(reads, [0, 1, 0, 1, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn macro_plays_back_sequence_while_trigger_is_held() {
+        let mut controller = Controller::default();
+        controller.bind_macro(
+            Buttons::SELECT,
+            &[
+                (Buttons::DOWN, 1),
+                (Buttons::DOWN | Buttons::RIGHT, 2),
+                (Buttons::B, 1),
+            ],
+        );
+
+        assert_eq!(latch(&mut controller), Buttons::empty());
+
+        controller.press(Buttons::SELECT);
+        let mut frames = vec![];
+        for _ in 0..5 {
+            frames.push(latch(&mut controller));
+            controller.end_frame();
+        }
+
+        assert_eq!(
+            frames,
+            [
+                Buttons::DOWN,
+                Buttons::DOWN | Buttons::RIGHT,
+                Buttons::DOWN | Buttons::RIGHT,
+                Buttons::B,
+                Buttons::empty(),
+            ]
+        );
+
+        // Releasing and pressing the trigger again restarts the macro
+        controller.release(Buttons::SELECT);
+        controller.end_frame();
+        controller.press(Buttons::SELECT);
+        assert_eq!(latch(&mut controller), Buttons::DOWN);
+    }
+
+    #[test]
+    fn turbo_button_alternates_at_configured_rate() {
+        let mut controller = Controller::default();
+        controller.set_turbo(Buttons::A, 2);
+
+        controller.press(Buttons::A | Buttons::B);
+        let mut frames = vec![];
+        for _ in 0..6 {
+            frames.push(latch(&mut controller));
+            controller.tick_turbo();
         }
+
+        let on = Buttons::A | Buttons::B;
+        let off = Buttons::B;
+        assert_eq!(frames, [on, on, off, off, on, on]);
+
+        // Turning turbo off reports the button as held again
+        controller.set_turbo(Buttons::A, 0);
+        controller.tick_turbo();
+        controller.tick_turbo();
+        assert_eq!(latch(&mut controller), on);
+    }
+
+    // Strobe the controller and read back all buttons
+    fn latch(controller: &mut Controller) -> Buttons {
+        controller.write(1);
+        controller.write(0);
+        let bits = (0..8).fold(0, |bits, _| (bits << 1) | controller.read());
+        Buttons::from_bits_truncate(bits)
+    }
+
+    #[test]
+    fn zapper_reports_trigger_pull() {
+        let mut zapper = Zapper::default();
+
+        assert_eq!(zapper.read() & 0b1_0000, 0);
+
+        zapper.set_trigger(true);
+        assert_eq!(zapper.read() & 0b1_0000, 0b1_0000);
+
+        zapper.set_trigger(false);
+        assert_eq!(zapper.read() & 0b1_0000, 0);
+    }
+
+    #[test]
+    fn zapper_detects_light_when_bright_pixel_drawn_where_aimed() {
+        let palette = Palette::default();
+        let mut zapper = Zapper::default();
+        zapper.aim(100, 50);
+
+        // No light detected
+        assert_eq!(zapper.read() & 0b1000, 0b1000);
+
+        // A bright pixel somewhere else, then a dark pixel where aimed
+        zapper.observe_pixel(10, 50, Color::from_index(0x30), &palette);
+        zapper.observe_pixel(100, 50, Color::from_index(0x0F), &palette);
+        assert_eq!(zapper.read() & 0b1000, 0b1000);
+
+        zapper.observe_pixel(100, 50, Color::from_index(0x30), &palette);
+        assert_eq!(zapper.read() & 0b1000, 0);
+    }
+
+    #[test]
+    fn zapper_sees_pixels_in_the_given_palette() {
+        let dark = Palette::from_colors([(0x10, 0x10, 0x10); 64]);
+        let mut zapper = Zapper::default();
+        zapper.aim(100, 50);
+
+        zapper.observe_pixel(100, 50, Color::from_index(0x30), &dark);
+        assert_eq!(zapper.read() & 0b1000, 0b1000);
+    }
+
+    #[test]
+    fn zapper_light_sensor_turns_off_after_some_scanlines() {
+        let palette = Palette::default();
+        let mut zapper = Zapper::default();
+        zapper.aim(0, 0);
+        zapper.observe_pixel(0, 0, Color::from_index(0x30), &palette);
+        zapper.aim_off_screen();
+
+        for y in 1..ZAPPER_LIGHT_SCANLINES as u16 {
+            zapper.observe_pixel(0, y, Color::from_index(0x30), &palette);
+            assert_eq!(zapper.read() & 0b1000, 0);
+        }
+
+        zapper.observe_pixel(
+            0,
+            ZAPPER_LIGHT_SCANLINES as u16,
+            Color::from_index(0x30),
+            &palette,
+        );
+        assert_eq!(zapper.read() & 0b1000, 0b1000);
     }
 }
@@ -1,43 +1,82 @@
 #![allow(clippy::upper_case_acronyms)] // Allow upper case acronyms like NES, CPU because I think it's more readable!
 
+use std::cell::RefMut;
 use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
 
 use apu::APU;
 
+pub use crate::accuracy::AccuracyProfile;
 pub use crate::address::Address;
+pub use crate::apu::MixMode;
+pub use crate::builder::NESBuilder;
 pub use crate::cartridge::Cartridge;
+pub use crate::cpu::disassemble;
 pub use crate::cpu::instructions;
+pub use crate::cpu::CpuError;
 pub use crate::cpu::Instruction;
 use crate::cpu::NESCPUMemory;
+pub use crate::cpu::PowerOnPattern;
+pub use crate::cpu::Registers;
 pub use crate::cpu::CPU;
 pub use crate::i_nes::INes;
 pub use crate::i_nes::INesReadError;
 pub use crate::input::Buttons;
 use crate::input::Controller;
+pub use crate::input::Zapper;
+pub use crate::mapper::Mapper;
+pub use crate::mapper::MapperKind;
+pub use crate::mapper::Mirroring;
 pub use crate::memory::ArrayMemory;
 pub use crate::memory::Memory;
+pub use crate::memory_map::MemoryArea;
+pub use crate::memory_map::MemoryAreaKind;
+pub use crate::memory_map::MemoryMap;
+pub use crate::movie::InputPlayer;
+pub use crate::movie::InputRecorder;
 pub use crate::ppu::Color;
 use crate::ppu::NESPPUMemory;
+pub use crate::ppu::Palette;
+pub use crate::ppu::PatternTable;
+pub use crate::ppu::VramSnapshot;
 use crate::ppu::PPU;
+pub use crate::region::Region;
 pub use crate::runtime::ActiveRuntime;
+pub use crate::runtime::AudioRecorder;
 pub use crate::runtime::Runtime;
 pub use crate::serialize::SerializeByte;
+pub use crate::serialize::SerializeBytes;
+#[cfg(feature = "serde")]
+pub use crate::state::StateError;
+pub use crate::test_rom::TestRomMonitor;
+pub use crate::test_rom::TestStatus;
 
+mod accuracy;
 mod address;
 mod apu;
+mod builder;
 mod cartridge;
 mod cpu;
 mod i_nes;
 mod input;
 mod mapper;
 mod memory;
+mod memory_map;
+mod movie;
 mod ppu;
+mod region;
 mod runtime;
 mod serialize;
+#[cfg(feature = "serde")]
+mod state;
+mod test_rom;
 
 pub const WIDTH: u16 = 256;
 pub const HEIGHT: u16 = 240;
 
+// CPU cycles halted while OAM DMA reads and writes 256 bytes, plus a cycle before it starts
+const OAM_DMA_CYCLES: u16 = 513;
+
 #[cfg_attr(feature = "web", wasm_bindgen::prelude::wasm_bindgen(start))]
 pub fn run() {
     if let Err(e) = run_inner() {
@@ -53,6 +92,12 @@ fn run_inner() -> Result<(), Box<dyn std::error::Error>> {
 pub trait NESDisplay {
     fn draw_pixel(&mut self, color: Color);
     fn enter_vblank(&mut self);
+
+    /// Called once when the last visible scanline has been drawn, so the frame can be presented.
+    fn end_frame(&mut self) {}
+
+    /// Set the RGB colors that colors are drawn as. Displays that don't show colors can ignore it.
+    fn set_palette(&mut self, _palette: Rc<Palette>) {}
 }
 
 impl NESDisplay for () {
@@ -65,6 +110,8 @@ pub struct BufferDisplay {
     x: usize,
     y: usize,
     vblank: bool,
+    dirty_rect: Option<(u16, u16, u16, u16)>,
+    palette: Rc<Palette>,
 }
 
 impl Default for BufferDisplay {
@@ -74,6 +121,8 @@ impl Default for BufferDisplay {
             x: 0,
             y: 0,
             vblank: false,
+            dirty_rect: None,
+            palette: Rc::default(),
         }
     }
 }
@@ -86,6 +135,45 @@ impl BufferDisplay {
     pub fn vblank(&self) -> bool {
         self.vblank
     }
+
+    /// Encode the current contents of the display as a PNG image.
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(self.buffer())
+    }
+
+    /// Bounds `(min_x, min_y, max_x, max_y)` of the pixels that changed in the current frame,
+    /// or `None` if nothing changed. This is reset when the next frame starts drawing.
+    pub fn dirty_rect(&self) -> Option<(u16, u16, u16, u16)> {
+        self.dirty_rect
+    }
+
+    fn mark_dirty(&mut self) {
+        let (x, y) = (self.x as u16, self.y as u16);
+        self.dirty_rect = Some(match self.dirty_rect {
+            None => (x, y, x, y),
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+        });
+    }
+}
+
+/// Encode a full screen of RGBA pixels as a PNG image.
+#[cfg(feature = "png")]
+pub(crate) fn encode_png(rgba: &[u8]) -> Vec<u8> {
+    use image::ImageEncoder;
+
+    let mut png = vec![];
+    image::codecs::png::PngEncoder::new(&mut png)
+        .write_image(
+            rgba,
+            WIDTH.into(),
+            HEIGHT.into(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .expect("screen should always be encodable as PNG");
+    png
 }
 
 impl Debug for BufferDisplay {
@@ -96,18 +184,19 @@ impl Debug for BufferDisplay {
 
 impl NESDisplay for BufferDisplay {
     fn draw_pixel(&mut self, color: Color) {
-        // if self.vblank {
-        //     log::debug!("Exit vblank");
-        // }
+        if self.vblank {
+            self.dirty_rect = None;
+        }
         self.vblank = false;
 
         let offset = (self.y * WIDTH as usize + self.x) * 4;
         if offset + 3 < self.buffer.len() {
-            let (r, g, b) = color.to_rgb();
-            self.buffer[offset] = r;
-            self.buffer[offset + 1] = g;
-            self.buffer[offset + 2] = b;
-            self.buffer[offset + 3] = 0xFF;
+            let (r, g, b) = self.palette.rgb(color);
+            let pixel = [r, g, b, 0xFF];
+            if self.buffer[offset..offset + 4] != pixel {
+                self.buffer[offset..offset + 4].copy_from_slice(&pixel);
+                self.mark_dirty();
+            }
         }
 
         self.x += 1;
@@ -123,40 +212,150 @@ impl NESDisplay for BufferDisplay {
     fn enter_vblank(&mut self) {
         self.vblank = true;
     }
+
+    fn set_palette(&mut self, palette: Rc<Palette>) {
+        self.palette = palette;
+    }
 }
 
 pub trait NESSpeaker {
+    /// Called with each sample, which is every CPU cycle unless [NES::set_sample_rate] is used.
     fn emit(&mut self, wave: f32);
+
+    /// Called instead of [NESSpeaker::emit] when panning is set with [NES::set_panning]. By
+    /// default, the two sides are mixed down to mono.
+    fn emit_stereo(&mut self, left: f32, right: f32) {
+        self.emit((left + right) / 2.0);
+    }
+
+    /// Called when the PPU completes a frame, so buffered samples can be flushed.
+    fn end_frame(&mut self) {}
 }
 
 impl NESSpeaker for () {
     fn emit(&mut self, _wave: f32) {}
 }
 
+/// Result of [NES::tick_until_break], describing why it stopped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TickBreak {
+    /// A frame completed or the instruction limit was reached, without hitting a breakpoint.
+    Step,
+    /// The CPU is about to run the instruction at this breakpoint.
+    Breakpoint(Address),
+    /// The last instruction read or wrote a watched address.
+    Watchpoint { address: Address, write: bool },
+}
+
+/// Result of [NES::pump], describing why it stopped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PumpResult {
+    /// Number of CPU cycles that were run.
+    pub cycles: u64,
+    /// Whether a frame was completed, in which case the display is ready to be shown.
+    pub frame_complete: bool,
+    /// Whether the limit set by [NES::set_instruction_limit] was reached.
+    pub instruction_limit_reached: bool,
+}
+
+/// Result of [NES::tick_detailed], describing what happened while ticking.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TickResult {
+    /// Number of CPU cycles that were run.
+    pub cycles: u64,
+    /// Whether a frame was completed, in which case the display is ready to be shown.
+    pub frame_complete: bool,
+    /// Whether the PPU raised an NMI.
+    pub nmi: bool,
+    /// Whether the cartridge or APU asserted the IRQ line.
+    pub irq: bool,
+    /// The last sample sent to the speaker, if any. Stereo samples are mixed down to mono.
+    pub audio_sample: Option<f32>,
+}
+
 #[derive(Debug)]
 pub struct NES<D, S> {
     cpu: CPU,
     display: D,
     speaker: S,
+    vblank: bool,
+    frame_complete: bool,
+    frame_count: u64,
+    instruction_count: u64,
+    instruction_limit: Option<u64>,
+    irq_line: bool,
+    region: Region,
+    // PPU dots still to run, in fifths of a dot, as PAL runs a fractional number per CPU cycle
+    ppu_fifth_dots: u16,
+    // Whether an odd number of CPU cycles have run, which decides how long OAM DMA takes
+    odd_cpu_cycle: bool,
+    breakpoints: Vec<Address>,
+    // Events seen during the current call to `tick_detailed`
+    tick_events: TickResult,
+}
+
+/// State saved by [NES::save_state]. `C` is the CPU, which owns everything else.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NESState<C> {
+    cpu: C,
+    vblank: bool,
+    frame_complete: bool,
+    frame_count: u64,
+    instruction_count: u64,
+    irq_line: bool,
+    ppu_fifth_dots: u16,
+    odd_cpu_cycle: bool,
 }
 
+/// A CPU as read from saved state, which is restored into the running CPU.
+#[cfg(feature = "serde")]
+type SavedCPU = CPU<NESCPUMemory<cartridge::PRGState, PPU<NESPPUMemory<cartridge::CHRState>>>>;
+
 impl<D: NESDisplay, S: NESSpeaker> NES<D, S> {
     pub fn new(cartridge: Cartridge, display: D, speaker: S) -> Self {
-        let ppu_memory = NESPPUMemory::new(cartridge.chr);
-        let ppu = PPU::with_memory(ppu_memory);
-        let controller = Controller::default();
-        let apu = APU::default();
+        NESBuilder::default()
+            .cartridge(cartridge)
+            .display(display)
+            .speaker(speaker)
+            .build()
+    }
+
+    /// Create an NES with the timing and colors of the given region.
+    pub fn with_region(cartridge: Cartridge, display: D, speaker: S, region: Region) -> Self {
+        let (prg, chr) = cartridge.split();
+        let ppu_memory = NESPPUMemory::new(chr);
+        let mut ppu = PPU::with_memory(ppu_memory);
+        ppu.set_region(region);
+        let mut apu = APU::default();
+        apu.set_region(region);
 
-        let cpu_memory = NESCPUMemory::new(cartridge.prg, ppu, apu, controller);
+        let cpu_memory =
+            NESCPUMemory::new(prg, ppu, apu, Controller::default(), Controller::default());
         let cpu = CPU::from_memory(cpu_memory);
 
         NES {
             cpu,
             display,
             speaker,
+            vblank: false,
+            frame_complete: false,
+            frame_count: 0,
+            instruction_count: 0,
+            instruction_limit: None,
+            irq_line: false,
+            region,
+            ppu_fifth_dots: 0,
+            odd_cpu_cycle: false,
+            breakpoints: vec![],
+            tick_events: TickResult::default(),
         }
     }
 
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
     pub fn display(&self) -> &D {
         &self.display
     }
@@ -165,6 +364,11 @@ impl<D: NESDisplay, S: NESSpeaker> NES<D, S> {
         self.cpu.program_counter()
     }
 
+    /// A snapshot of the CPU registers, for inspecting state between instructions.
+    pub fn cpu_registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
     pub fn set_program_counter(&mut self, address: Address) {
         self.cpu.set_program_counter(address);
     }
@@ -173,21 +377,448 @@ impl<D: NESDisplay, S: NESSpeaker> NES<D, S> {
         self.cpu.read(address)
     }
 
+    /// A copy of the PPU nametables and pattern tables, for a tile viewer.
+    pub fn ppu_vram_snapshot(&mut self) -> VramSnapshot {
+        self.ppu().vram_snapshot()
+    }
+
+    /// The 2KB of internal RAM at $0000-$07FF, without the side effects of reading it.
+    pub fn dump_ram(&mut self) -> &[u8; 0x800] {
+        self.cpu.memory().internal_ram()
+    }
+
+    /// Set what internal RAM holds when the NES is powered on, and fill RAM with it. RAM is zeroed
+    /// by default.
+    pub fn set_power_on_pattern(&mut self, pattern: PowerOnPattern) {
+        self.cpu.memory().set_power_on_pattern(pattern);
+    }
+
+    /// Set the RGB colors that output colors are displayed as, by the display and to the Zapper.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu().set_palette(palette);
+        let palette = self.ppu().palette().clone();
+        self.display.set_palette(palette);
+    }
+
+    pub fn set_accuracy_profile(&mut self, accuracy: AccuracyProfile) {
+        self.cpu.set_accuracy_profile(accuracy);
+        self.cpu.memory().set_accuracy_profile(accuracy);
+        self.ppu().set_accuracy_profile(accuracy);
+    }
+
+    pub fn set_mix_mode(&mut self, mix_mode: MixMode) {
+        self.cpu.memory().apu().set_mix_mode(mix_mode);
+    }
+
+    /// Emit samples to the speaker at `hz` samples per second, rather than one every CPU cycle.
+    pub fn set_sample_rate(&mut self, hz: u32) {
+        self.cpu.memory().apu().set_sample_rate(hz);
+    }
+
+    /// Turn the filters applied to the audio output on or off. They're on by default.
+    pub fn set_audio_filter_enabled(&mut self, enabled: bool) {
+        self.cpu.memory().apu().set_filter_enabled(enabled);
+    }
+
+    /// Output stereo audio with [NESSpeaker::emit_stereo], placing each channel between left
+    /// (-1.0) and right (1.0), in the order pulse 1, pulse 2, triangle, noise and DMC. `None`, the
+    /// default, gives mono output.
+    pub fn set_panning(&mut self, panning: Option<[f32; 5]>) {
+        self.cpu.memory().apu().set_panning(panning);
+    }
+
+    /// The raw output of each APU channel on the last cycle, in the order pulse 1, pulse 2,
+    /// triangle, noise and DMC.
+    pub fn channel_outputs(&mut self) -> [u8; 5] {
+        self.cpu.memory().apu().channel_outputs()
+    }
+
+    /// Set a callback fired on every OAM DMA transfer, with the source page and the bytes transferred.
+    pub fn set_oam_dma_hook(&mut self, hook: impl FnMut(u8, &[u8; 256]) + 'static) {
+        self.cpu.memory().set_oam_dma_hook(hook);
+    }
+
+    /// The cartridge's PRG RAM, which holds saved games on cartridges with a battery.
+    pub fn prg_ram(&mut self) -> RefMut<'_, [u8]> {
+        self.cpu.memory().prg().ram()
+    }
+
+    /// Describe the current layout of the CPU address space.
+    pub fn memory_map(&mut self) -> MemoryMap {
+        MemoryMap::new(self.cpu.memory().prg())
+    }
+
+    /// Press the reset button. RAM is preserved and the CPU jumps to the reset vector.
+    pub fn soft_reset(&mut self) {
+        self.cpu.memory().apu().reset();
+        self.ppu().reset();
+        self.cpu.reset();
+    }
+
+    /// Turn the NES off and on again. RAM, VRAM and every subsystem are reset to their power-on
+    /// state. The cartridge, including PRG RAM, is not affected.
+    pub fn hard_reset(&mut self) {
+        self.cpu.memory().power_on();
+        self.ppu().power_on();
+        self.ppu().memory().power_on();
+        self.cpu.power_on();
+        self.vblank = false;
+        self.frame_complete = false;
+        self.frame_count = 0;
+        self.ppu_fifth_dots = 0;
+        self.odd_cpu_cycle = false;
+    }
+
+    /// Save the state of the CPU, PPU, APU, controller and cartridge, so it can be restored with
+    /// [NES::load_state]. The state doesn't include the ROM, display or speaker.
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = NESState {
+            cpu: &self.cpu,
+            vblank: self.vblank,
+            frame_complete: self.frame_complete,
+            frame_count: self.frame_count,
+            instruction_count: self.instruction_count,
+            irq_line: self.irq_line,
+            ppu_fifth_dots: self.ppu_fifth_dots,
+            odd_cpu_cycle: self.odd_cpu_cycle,
+        };
+        state::to_bytes(&state).expect("NES state should always be serializable")
+    }
+
+    /// Restore state saved by [NES::save_state]. Hooks and settings such as the instruction limit
+    /// are kept.
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let state: NESState<SavedCPU> = state::from_bytes(state)?;
+        // Everything that can fail is checked before restoring anything, so a bad state has no
+        // effect
+        let saved_prg = state.cpu.saved_memory().saved_prg();
+        let mapper = self.cpu.memory().prg().read_state(saved_prg)?;
+
+        let memory = self.cpu.restore(state.cpu);
+        self.cpu.memory().restore(memory, mapper);
+        self.vblank = state.vblank;
+        self.frame_complete = state.frame_complete;
+        self.frame_count = state.frame_count;
+        self.instruction_count = state.instruction_count;
+        self.irq_line = state.irq_line;
+        self.ppu_fifth_dots = state.ppu_fifth_dots;
+        self.odd_cpu_cycle = state.odd_cpu_cycle;
+        Ok(())
+    }
+
+    /// Set a callback fired at the end of each visible scanline, with the scanline index and that
+    /// line's colors.
+    pub fn set_scanline_hook(&mut self, hook: impl FnMut(u16, &[Color]) + 'static) {
+        self.ppu().set_scanline_hook(hook);
+    }
+
+    /// Whether the CPU has crashed by running a JAM instruction. Reset the NES to recover.
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
     pub fn controller(&mut self) -> &mut Controller {
         self.cpu.memory().input()
     }
 
+    /// The second controller, read from `$4017`.
+    pub fn controller2(&mut self) -> &mut Controller {
+        self.cpu.memory().input_2()
+    }
+
+    /// Hold exactly `buttons` on the controller in `port`, 0 for the first controller and 1 for
+    /// the second, releasing all others. Useful for replaying input a frame at a time.
+    ///
+    /// Panics if `port` isn't 0 or 1.
+    pub fn set_controller_state(&mut self, port: u8, buttons: Buttons) {
+        self.controller_in_port(port).set_buttons(buttons);
+    }
+
+    /// The buttons held on the controller in `port`, 0 for the first controller and 1 for the
+    /// second.
+    ///
+    /// Panics if `port` isn't 0 or 1.
+    pub fn controller_state(&mut self, port: u8) -> Buttons {
+        self.controller_in_port(port).buttons()
+    }
+
+    fn controller_in_port(&mut self, port: u8) -> &mut Controller {
+        match port {
+            0 => self.controller(),
+            1 => self.controller2(),
+            _ => panic!("No controller port {}", port),
+        }
+    }
+
+    /// Plug a [Zapper] into the second port in place of the second controller, or unplug it.
+    pub fn set_zapper_connected(&mut self, connected: bool) {
+        self.cpu.memory().set_zapper_connected(connected);
+    }
+
+    pub fn zapper(&mut self) -> Option<&mut Zapper> {
+        self.cpu.memory().zapper()
+    }
+
+    /// Whether the last tick completed a frame, i.e. the PPU entered vblank.
+    pub fn is_frame_complete(&self) -> bool {
+        self.frame_complete
+    }
+
+    /// Total number of frames completed since power-on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Total number of instructions run.
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Stop [NES::pump] once this many instructions have been run in total, so a misbehaving ROM
+    /// can't run forever.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    fn remaining_instructions(&self) -> u64 {
+        match self.instruction_limit {
+            Some(limit) => limit.saturating_sub(self.instruction_count),
+            None => u64::MAX,
+        }
+    }
+
+    /// Run a single instruction, or a whole loop waiting on PPUSTATUS.
     pub fn tick(&mut self) {
-        let cpu_cycles = self.cpu.run_instruction();
+        self.tick_detailed();
+    }
+
+    /// Like [NES::tick], but reports the cycles run and any events that happened meanwhile.
+    pub fn tick_detailed(&mut self) -> TickResult {
+        self.try_tick().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like [NES::tick_detailed], but returns an error instead of panicking on an unsupported
+    /// opcode. The CPU is left pointing at the opcode, so ticking again fails the same way.
+    pub fn try_tick(&mut self) -> Result<TickResult, CpuError> {
+        self.tick_events = TickResult::default();
+        let cycles = self.try_tick_cycles(u64::MAX)?;
+        Ok(TickResult {
+            cycles,
+            frame_complete: self.frame_complete,
+            ..self.tick_events
+        })
+    }
+
+    /// Stop [NES::tick_until_break] before running the instruction at this address.
+    pub fn add_breakpoint(&mut self, address: Address) {
+        self.breakpoints.push(address);
+    }
+
+    /// Stop [NES::tick_until_break] after an instruction reads and/or writes this address.
+    pub fn add_watchpoint(&mut self, address: Address, read: bool, write: bool) {
+        self.cpu.memory().add_watchpoint(address, read, write);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.cpu.memory().clear_watchpoints();
+    }
+
+    /// Run instructions until a breakpoint or watchpoint is hit, or a frame is completed.
+    /// The first instruction is always run, so this can be called again to continue from a
+    /// breakpoint.
+    pub fn tick_until_break(&mut self) -> TickBreak {
+        self.frame_complete = false;
+        // Forget watchpoints hit while ticking some other way
+        self.cpu.memory().take_watchpoint_hit();
+        while !self.frame_complete && self.remaining_instructions() > 0 {
+            self.run_instruction();
 
-        // There are 3 PPU cycles to 1 CPU cycle
-        for _ in 0..3 * cpu_cycles {
+            if let Some((address, write)) = self.cpu.memory().take_watchpoint_hit() {
+                return TickBreak::Watchpoint { address, write };
+            }
+            let program_counter = self.cpu.program_counter();
+            if self.breakpoints.contains(&program_counter) {
+                return TickBreak::Breakpoint(program_counter);
+            }
+        }
+        TickBreak::Step
+    }
+
+    /// Tick until either a frame is completed or at least `max_cycles` CPU cycles have run.
+    pub fn pump(&mut self, max_cycles: u64) -> PumpResult {
+        let mut cycles = 0;
+        while cycles < max_cycles && self.remaining_instructions() > 0 {
+            cycles += self.tick_cycles(max_cycles - cycles);
+            if self.frame_complete {
+                break;
+            }
+        }
+        PumpResult {
+            cycles,
+            frame_complete: self.frame_complete,
+            instruction_limit_reached: self.remaining_instructions() == 0,
+        }
+    }
+
+    /// Run until the PPU next enters vblank, completing one frame, returning the new frame count.
+    /// Stops early if the instruction limit is reached.
+    pub fn run_frame(&mut self) -> u64 {
+        self.pump(u64::MAX);
+        self.frame_count
+    }
+
+    /// Run whole instructions until at least `cpu_cycles` CPU cycles have run, returning the
+    /// number of cycles actually run. Unlike [NES::pump], this doesn't stop at the end of a frame.
+    /// Stops early if the instruction limit is reached.
+    pub fn run_cycles(&mut self, cpu_cycles: u32) -> u64 {
+        let cpu_cycles = u64::from(cpu_cycles);
+        let mut cycles = 0;
+        while cycles < cpu_cycles && self.remaining_instructions() > 0 {
+            cycles += self.tick_cycles(cpu_cycles - cycles);
+        }
+        cycles
+    }
+
+    /// Run exactly `instructions` instructions, returning the number of CPU cycles taken.
+    pub fn run_instructions(&mut self, instructions: u64) -> u64 {
+        self.frame_complete = false;
+        (0..instructions)
+            .map(|_| u64::from(self.run_instruction()))
+            .sum()
+    }
+
+    fn tick_cycles(&mut self, max_cycles: u64) -> u64 {
+        self.try_tick_cycles(max_cycles)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_tick_cycles(&mut self, max_cycles: u64) -> Result<u64, CpuError> {
+        self.frame_complete = false;
+
+        match self.cpu.ppu_status_poll() {
+            // Each iteration of the loop is two instructions
+            Some(status_address) if self.remaining_instructions() >= 2 => {
+                self.skip_ppu_status_poll(status_address, max_cycles)
+            }
+            _ => Ok(u64::from(self.try_run_instruction()?)),
+        }
+    }
+
+    fn run_instruction(&mut self) -> u16 {
+        self.try_run_instruction()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_run_instruction(&mut self) -> Result<u16, CpuError> {
+        let (cpu_cycles, stalled_cycles) = self.try_run_instruction_with_stall()?;
+        Ok(cpu_cycles + stalled_cycles)
+    }
+
+    /// Returns the cycles the instruction took, and the cycles the CPU was halted for afterwards.
+    fn try_run_instruction_with_stall(&mut self) -> Result<(u16, u16), CpuError> {
+        let cpu_cycles = u16::from(self.cpu.try_run_instruction()?);
+        self.instruction_count += 1;
+        let mut stalled_cycles = self.tick_devices(cpu_cycles);
+
+        // The CPU is halted while OAM DMA copies a page, plus a cycle to start on an even cycle
+        if self.cpu.memory().take_oam_dma() {
+            let dma_cycles = OAM_DMA_CYCLES + u16::from(self.odd_cpu_cycle);
+            stalled_cycles += dma_cycles + self.tick_devices(dma_cycles);
+        }
+
+        Ok((cpu_cycles, stalled_cycles))
+    }
+
+    /// Returns the cycles the CPU was stalled for by the DMC reading samples meanwhile, which the
+    /// devices have also been ticked for.
+    fn tick_devices(&mut self, cpu_cycles: u16) -> u16 {
+        self.odd_cpu_cycle ^= cpu_cycles & 1 == 1;
+
+        // There are 3 PPU cycles to 1 CPU cycle on NTSC, and 3.2 on PAL
+        self.ppu_fifth_dots += self.region.ppu_fifth_dots_per_cpu_cycle() * cpu_cycles;
+        for _ in 0..self.ppu_fifth_dots / 5 {
             self.tick_ppu();
         }
+        self.ppu_fifth_dots %= 5;
 
+        let mut stolen_cycles = 0;
         for _ in 0..cpu_cycles {
-            self.tick_apu();
+            stolen_cycles += self.tick_apu();
+        }
+
+        // The CPU is stalled while the DMC reads samples, but everything else keeps running
+        let mut stalled_cycles = u16::from(stolen_cycles);
+        if stolen_cycles > 0 {
+            stalled_cycles += self.tick_devices(stalled_cycles);
         }
+
+        self.update_irq();
+        stalled_cycles
+    }
+
+    /// The cartridge and APU share the IRQ line. It stays asserted until the game acknowledges the
+    /// interrupt through the device that raised it.
+    fn update_irq(&mut self) {
+        let memory = self.cpu.memory();
+        let irq = memory.prg().irq() || memory.apu().irq();
+        if irq && !self.irq_line {
+            self.tick_events.irq = true;
+        }
+        if irq {
+            self.cpu.request_irq();
+        } else if self.irq_line {
+            self.cpu.acknowledge_irq();
+        }
+        self.irq_line = irq;
+    }
+
+    /// Games often wait for vblank with a loop like `BIT $2002; BPL -5`.
+    /// While PPUSTATUS doesn't change, every iteration leaves the CPU in the same state, so we can
+    /// skip running the instructions. PPUSTATUS is still read every iteration for its side-effects.
+    fn skip_ppu_status_poll(
+        &mut self,
+        status_address: Address,
+        max_cycles: u64,
+    ) -> Result<u64, CpuError> {
+        let start = self.cpu.program_counter();
+        let status = self.ppu().peek_status();
+
+        // Run the first iteration normally, to set the CPU flags and measure timing
+        let (read_cycles, read_stall) = self.try_run_instruction_with_stall()?;
+        let mut cycles = u64::from(read_cycles + read_stall);
+        if self.interrupted() {
+            return Ok(cycles);
+        }
+        let (branch_cycles, branch_stall) = self.try_run_instruction_with_stall()?;
+        cycles += u64::from(branch_cycles + branch_stall);
+
+        while self.cpu.program_counter() == start
+            && cycles < max_cycles
+            && self.remaining_instructions() >= 2
+            && !self.interrupted()
+            && self.ppu().peek_status() == status
+        {
+            self.cpu.memory().read(status_address);
+            self.instruction_count += 1;
+            cycles += u64::from(read_cycles + self.tick_devices(read_cycles));
+            if self.interrupted() {
+                // Stop before the branch, as if we'd just run the read instruction
+                self.cpu.set_program_counter(start + 3);
+                break;
+            }
+
+            self.instruction_count += 1;
+            cycles += u64::from(branch_cycles + self.tick_devices(branch_cycles));
+        }
+
+        Ok(cycles)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.frame_complete || self.cpu.non_maskable_interrupt_pending() || self.cpu.irq_pending()
     }
 
     fn ppu(&mut self) -> &mut PPU {
@@ -195,25 +826,49 @@ impl<D: NESDisplay, S: NESSpeaker> NES<D, S> {
     }
 
     fn tick_ppu(&mut self) {
+        let (x, y) = self.ppu().beam_position();
         let output = self.ppu().tick();
 
         if output.interrupt {
             self.cpu.non_maskable_interrupt();
+            self.tick_events.nmi = true;
         }
 
         if let Some(color) = output.color {
+            self.cpu.memory().observe_pixel(x, y, color);
             self.display.draw_pixel(color);
         }
 
         if output.vblank {
             self.display.enter_vblank();
         }
+
+        if output.vblank && !self.vblank {
+            self.frame_complete = true;
+            self.frame_count += 1;
+            self.display.end_frame();
+            self.controller().end_frame();
+            self.controller().tick_turbo();
+            self.controller2().end_frame();
+            self.controller2().tick_turbo();
+            self.speaker.end_frame();
+        }
+        self.vblank = output.vblank;
     }
 
-    fn tick_apu(&mut self) {
+    /// Returns the number of CPU cycles stolen by the DMC reading a sample.
+    fn tick_apu(&mut self) -> u8 {
         let apu = self.cpu.memory().apu();
-        let wave = apu.tick();
-        self.speaker.emit(wave);
+        if apu.is_stereo() {
+            if let Some((left, right)) = apu.tick_stereo_sample() {
+                self.speaker.emit_stereo(left, right);
+                self.tick_events.audio_sample = Some((left + right) / 2.0);
+            }
+        } else if let Some(wave) = apu.tick_sample() {
+            self.speaker.emit(wave);
+            self.tick_events.audio_sample = Some(wave);
+        }
+        self.cpu.memory().fetch_dmc_sample()
     }
 }
 
@@ -230,9 +885,10 @@ macro_rules! mem {
                 #[allow(unused_variables, unused_mut)]
                 let mut addr: $crate::Address = $crate::Address::from($offset);
                 $(
-                    let byte = $crate::SerializeByte::to_byte($data);
-                    $crate::Memory::write(&mut memory, addr, byte);
-                    addr += 1u16;
+                    for byte in $crate::SerializeBytes::to_bytes($data) {
+                        $crate::Memory::write(&mut memory, addr, byte);
+                        addr += 1u16;
+                    }
                 )*
             )*
             memory
@@ -242,3 +898,857 @@ macro_rules! mem {
         mem!{$offset => { $data }}
     };
 }
+
+/// A 16-bit value for the [mem!] macro, written little-endian.
+///
+/// ```
+/// use nes_rust::{le16, mem, Address, Memory};
+///
+/// let mut memory = mem!(0xFFFC => le16!(0x1234));
+/// assert_eq!(memory.read(Address::new(0xFFFC)), 0x34);
+/// assert_eq!(memory.read(Address::new(0xFFFD)), 0x12);
+/// ```
+#[macro_export]
+macro_rules! le16 {
+    ($value: expr) => {
+        $crate::Address::new($value)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::mapper::MapperKind;
+
+    use super::*;
+
+    #[test]
+    fn pump_stops_when_frame_is_complete() {
+        let mut nes = looping_nes();
+
+        let result = nes.pump(u64::MAX);
+        assert!(result.frame_complete);
+        assert!(nes.is_frame_complete());
+
+        // A full frame is roughly 29780 CPU cycles
+        let result = nes.pump(u64::MAX);
+        assert!(result.frame_complete);
+        assert!((29_780..29_790).contains(&result.cycles));
+    }
+
+    #[test]
+    fn try_tick_returns_error_on_unknown_opcode() {
+        // NOP; XAA #$00
+        let mut nes = nes_with_program(&[0xEA, 0x8B, 0x00]);
+
+        assert_eq!(nes.try_tick().unwrap().cycles, 2);
+        let error = Err(CpuError::UnknownOpcode(0x8B, Address::new(0x8001)));
+        assert_eq!(nes.try_tick(), error);
+        assert_eq!(nes.try_tick(), error);
+        assert_eq!(nes.instruction_count(), 1);
+    }
+
+    #[test]
+    fn tick_detailed_counts_cycles_stalled_by_dmc() {
+        let mut nes = nes_with_program(&[
+            0xA9, 0x4F, 0x8D, 0x10, 0x40, // LDA #$4F; STA $4010 (loop at the fastest rate)
+            0xA9, 0xFF, 0x8D, 0x13, 0x40, // LDA #$FF; STA $4013
+            0xA9, 0x10, 0x8D, 0x15, 0x40, // LDA #$10; STA $4015
+            0x4C, 0x0F, 0x80, // JMP $800F
+        ]);
+        while !nes.tick_detailed().frame_complete {}
+
+        // A full frame is roughly 29780 CPU cycles, including the ones the DMC stole
+        let mut cycles = 0;
+        loop {
+            let result = nes.tick_detailed();
+            cycles += result.cycles;
+            if result.frame_complete {
+                break;
+            }
+        }
+        assert!((29_770..29_790).contains(&cycles), "{}", cycles);
+    }
+
+    #[test]
+    fn tick_detailed_reports_cycles_of_instruction() {
+        // LDX #$01; NOP; LDA $01FF,X; JMP $8000
+        let mut nes = nes_with_program(&[0xA2, 0x01, 0xEA, 0xBD, 0xFF, 0x01, 0x4C, 0x00, 0x80]);
+        nes.tick();
+
+        let result = nes.tick_detailed();
+        assert_eq!(result.cycles, 2);
+        assert!(!result.frame_complete);
+        assert!(!result.nmi);
+        assert!(!result.irq);
+        assert!(result.audio_sample.is_some());
+
+        // Crosses a page boundary
+        assert_eq!(nes.tick_detailed().cycles, 5);
+        assert_eq!(nes.tick_detailed().cycles, 3);
+    }
+
+    #[test]
+    fn tick_detailed_reports_frame_and_nmi() {
+        let mut nes = looping_nes();
+        nes.cpu.memory().write(Address::new(0x2000), 0b1000_0000);
+
+        let mut frame_completed = false;
+        let nmi = (0..20_000).any(|_| {
+            let result = nes.tick_detailed();
+            frame_completed |= result.frame_complete;
+            result.nmi
+        });
+
+        // The NMI is raised just after vblank starts, completing the frame
+        assert!(nmi);
+        assert!(frame_completed);
+        assert_eq!(nes.frame_count(), 1);
+    }
+
+    #[test]
+    fn run_frame_runs_until_next_vblank() {
+        let mut nes = looping_nes();
+        assert_eq!(nes.frame_count(), 0);
+
+        assert_eq!(nes.run_frame(), 1);
+        assert!(nes.is_frame_complete());
+        assert_eq!(nes.run_frame(), 2);
+        assert_eq!(nes.frame_count(), 2);
+    }
+
+    #[test]
+    fn pal_frame_takes_longer() {
+        let mut nes = NES::with_region(nes_cartridge(&[0x4C, 0x00, 0x80]), (), (), Region::PAL);
+        nes.pump(u64::MAX);
+
+        // 312 scanlines of 341 dots, at 3.2 dots per CPU cycle
+        let result = nes.pump(u64::MAX);
+        assert!(result.frame_complete);
+        assert!((33_246..33_252).contains(&result.cycles));
+    }
+
+    #[test]
+    fn pump_stops_when_cycle_budget_is_exhausted() {
+        let mut nes = looping_nes();
+
+        let result = nes.pump(100);
+        assert!(!result.frame_complete);
+        assert!(!nes.is_frame_complete());
+        assert!((100..103).contains(&result.cycles));
+    }
+
+    #[test]
+    fn buffer_display_dirty_rect_bounds_changed_pixels() {
+        let mut display = BufferDisplay::default();
+        draw_frame(&mut display, |_, _| Color::from_index(0x0F));
+        display.enter_vblank();
+
+        draw_frame(&mut display, |x, y| {
+            if (x, y) == (5, 2) {
+                Color::from_index(0x30)
+            } else {
+                Color::from_index(0x0F)
+            }
+        });
+
+        assert_eq!(display.dirty_rect(), Some((5, 2, 5, 2)));
+    }
+
+    #[test]
+    fn buffer_display_dirty_rect_is_none_when_frame_is_unchanged() {
+        let mut display = BufferDisplay::default();
+        draw_frame(&mut display, |_, _| Color::from_index(0x0F));
+        display.enter_vblank();
+
+        draw_frame(&mut display, |_, _| Color::from_index(0x0F));
+
+        assert_eq!(display.dirty_rect(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn buffer_display_encodes_screen_as_png() {
+        let mut display = BufferDisplay::default();
+        draw_frame(&mut display, |_, _| Color::from_index(0x16));
+
+        let png = display.to_png();
+
+        let image = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (WIDTH.into(), HEIGHT.into()));
+        let (r, g, b) = Color::from_index(0x16).to_rgb();
+        assert_eq!(image.get_pixel(100, 50).0, [r, g, b, 0xff]);
+    }
+
+    #[test]
+    fn buffer_display_draws_colors_from_its_palette() {
+        let mut display = BufferDisplay::default();
+        display.set_palette(Rc::new(Palette::from_colors([(0xFF, 0, 0); 64])));
+
+        draw_frame(&mut display, |_, _| Color::from_index(0x16));
+
+        assert_eq!(display.buffer()[..4], [0xFF, 0, 0, 0xFF]);
+    }
+
+    fn draw_frame(display: &mut BufferDisplay, color: impl Fn(u16, u16) -> Color) {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                display.draw_pixel(color(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn polling_ppu_status_skips_to_vblank() {
+        // BIT $2002; BPL -5; JMP $8005
+        let program = [0x2C, 0x02, 0x20, 0x10, 0xFB, 0x4C, 0x05, 0x80];
+
+        let mut nes = nes_with_program(&program);
+        let mut ticks = 0;
+        let mut cycles = 0;
+        while nes.program_counter() != Address::new(0x8005) {
+            cycles += nes.tick_cycles(u64::MAX);
+            ticks += 1;
+        }
+
+        // Run the same program an instruction at a time
+        let mut expected = nes_with_program(&program);
+        let mut expected_ticks = 0;
+        let mut expected_cycles = 0;
+        while expected.program_counter() != Address::new(0x8005) {
+            expected_cycles += u64::from(expected.run_instruction());
+            expected_ticks += 1;
+        }
+
+        assert!(ticks < 10, "Took {} ticks", ticks);
+        assert!(expected_ticks > 1000);
+        assert_eq!(cycles, expected_cycles);
+        assert_eq!(nes.cpu.registers(), expected.cpu.registers());
+        assert_eq!(nes.ppu().peek_status(), expected.ppu().peek_status());
+    }
+
+    #[test]
+    fn speaker_end_frame_is_called_once_per_frame() {
+        #[derive(Default)]
+        struct CountingSpeaker {
+            samples: usize,
+            frames: usize,
+        }
+
+        impl NESSpeaker for CountingSpeaker {
+            fn emit(&mut self, _wave: f32) {
+                self.samples += 1;
+            }
+
+            fn end_frame(&mut self) {
+                self.frames += 1;
+            }
+        }
+
+        let mut nes = NES::new(
+            nes_cartridge(&[0x4C, 0x00, 0x80]),
+            (),
+            CountingSpeaker::default(),
+        );
+        for _ in 0..3 {
+            nes.pump(u64::MAX);
+        }
+
+        assert_eq!(nes.speaker.frames, 3);
+        assert!(nes.speaker.samples > 0);
+    }
+
+    #[test]
+    fn speaker_receives_stereo_samples_when_panning_is_set() {
+        #[derive(Default)]
+        struct StereoSpeaker {
+            mono: usize,
+            stereo: usize,
+        }
+
+        impl NESSpeaker for StereoSpeaker {
+            fn emit(&mut self, _wave: f32) {
+                self.mono += 1;
+            }
+
+            fn emit_stereo(&mut self, _left: f32, _right: f32) {
+                self.stereo += 1;
+            }
+        }
+
+        let mut nes = NES::new(
+            nes_cartridge(&[0x4C, 0x00, 0x80]),
+            (),
+            StereoSpeaker::default(),
+        );
+        nes.pump(u64::MAX);
+        assert!(nes.speaker.mono > 0);
+        assert_eq!(nes.speaker.stereo, 0);
+
+        let mono = nes.speaker.mono;
+        nes.set_panning(Some([-1.0, 1.0, 0.0, 0.0, 0.0]));
+        nes.pump(u64::MAX);
+        assert_eq!(nes.speaker.mono, mono);
+        assert!(nes.speaker.stereo > 0);
+    }
+
+    #[test]
+    fn emit_stereo_mixes_down_to_mono_by_default() {
+        struct MonoSpeaker(Vec<f32>);
+
+        impl NESSpeaker for MonoSpeaker {
+            fn emit(&mut self, wave: f32) {
+                self.0.push(wave);
+            }
+        }
+
+        let mut speaker = MonoSpeaker(vec![]);
+        speaker.emit_stereo(0.25, 0.75);
+        assert_eq!(speaker.0, vec![0.5]);
+    }
+
+    #[test]
+    fn display_end_frame_is_called_once_per_frame() {
+        #[derive(Default)]
+        struct CountingDisplay {
+            pixels: usize,
+            frames: usize,
+        }
+
+        impl NESDisplay for CountingDisplay {
+            fn draw_pixel(&mut self, _color: Color) {
+                self.pixels += 1;
+            }
+
+            fn enter_vblank(&mut self) {}
+
+            fn end_frame(&mut self) {
+                self.frames += 1;
+            }
+        }
+
+        let mut nes = NES::new(
+            nes_cartridge(&[0x4C, 0x00, 0x80]),
+            CountingDisplay::default(),
+            (),
+        );
+        nes.pump(u64::MAX);
+        let first_frame_pixels = nes.display.pixels;
+        for _ in 0..3 {
+            nes.pump(u64::MAX);
+        }
+
+        assert_eq!(nes.display.frames, 4);
+        assert_eq!(
+            nes.display.pixels - first_frame_pixels,
+            3 * (WIDTH as usize * HEIGHT as usize)
+        );
+    }
+
+    #[test]
+    fn run_instructions_runs_exact_number_of_instructions() {
+        // LDA #$01 (2 cycles); STA $10 (3 cycles); JMP $8000 (3 cycles)
+        let mut nes = nes_with_program(&[0xA9, 0x01, 0x85, 0x10, 0x4C, 0x00, 0x80]);
+
+        let cycles = nes.run_instructions(100);
+
+        // 33 loops, then one more LDA
+        assert_eq!(cycles, 33 * 8 + 2);
+        assert_eq!(nes.instruction_count(), 100);
+        assert_eq!(nes.program_counter(), Address::new(0x8002));
+    }
+
+    #[test]
+    fn oam_dma_halts_cpu_for_513_cycles_from_even_cycle() {
+        // LDA #$02 (2 cycles); STA $4014 (4 cycles)
+        let mut nes = nes_with_program(&[0xA9, 0x02, 0x8D, 0x14, 0x40]);
+
+        assert_eq!(nes.run_instructions(1), 2);
+        assert_eq!(nes.run_instructions(1), 4 + 513);
+    }
+
+    #[test]
+    fn oam_dma_halts_cpu_for_514_cycles_from_odd_cycle() {
+        // LDA $00 (3 cycles); STA $4014 (4 cycles)
+        let mut nes = nes_with_program(&[0xA5, 0x00, 0x8D, 0x14, 0x40]);
+
+        assert_eq!(nes.run_instructions(1), 3);
+        assert_eq!(nes.run_instructions(1), 4 + 514);
+    }
+
+    #[test]
+    fn ppu_keeps_running_during_oam_dma() {
+        // STA $4014 (4 cycles)
+        let mut nes = nes_with_program(&[0x8D, 0x14, 0x40]);
+        let start = nes.ppu().beam_position();
+
+        nes.run_instructions(1);
+
+        let (cycle, scanline) = nes.ppu().beam_position();
+        let dots = (scanline - start.1) * 341 + cycle - start.0;
+        assert_eq!(dots, 3 * (4 + 513));
+    }
+
+    #[test]
+    fn mem_writes_le16_values_lower_byte_first() {
+        let memory = mem!(0xFFFB => { 0xEAu8, le16!(0x1234), 0x56u8 });
+
+        assert_eq!(memory.as_slice()[0xFFFB..], [0xEA, 0x34, 0x12, 0x56, 0x00]);
+    }
+
+    #[test]
+    fn dump_ram_returns_internal_ram() {
+        // LDA #$42; STA $0123; JMP $8005
+        let mut nes = nes_with_program(&[0xA9, 0x42, 0x8D, 0x23, 0x01, 0x4C, 0x05, 0x80]);
+
+        nes.run_instructions(2);
+
+        let ram = nes.dump_ram();
+        assert_eq!(ram.len(), 0x800);
+        assert_eq!(ram[0x0123], 0x42);
+        assert_eq!(ram[0x0122], 0x00);
+    }
+
+    #[test]
+    fn cpu_registers_reflect_state_after_instructions() {
+        // LDX #$05; INX; TXA
+        let mut nes = nes_with_program(&[0xA2, 0x05, 0xE8, 0x8A]);
+
+        nes.run_instructions(3);
+
+        let registers = nes.cpu_registers();
+        assert_eq!(registers.accumulator, 0x06);
+        assert_eq!(registers.x, 0x06);
+        assert_eq!(registers.program_counter, Address::new(0x8004));
+    }
+
+    #[test]
+    fn tick_until_break_stops_at_breakpoint() {
+        // LDA #$01; STA $10; JMP $8000
+        let mut nes = nes_with_program(&[0xA9, 0x01, 0x85, 0x10, 0x4C, 0x00, 0x80]);
+        nes.add_breakpoint(Address::new(0x8004));
+
+        assert_eq!(
+            nes.tick_until_break(),
+            TickBreak::Breakpoint(Address::new(0x8004))
+        );
+        assert_eq!(nes.instruction_count(), 2);
+
+        // Continuing runs the instruction at the breakpoint, then loops back round to it
+        assert_eq!(
+            nes.tick_until_break(),
+            TickBreak::Breakpoint(Address::new(0x8004))
+        );
+        assert_eq!(nes.instruction_count(), 5);
+    }
+
+    #[test]
+    fn looking_for_ppu_status_poll_does_not_trigger_watchpoints() {
+        let mut nes = looping_nes();
+        // Past the end of the loop, but within the bytes checked for a PPUSTATUS poll
+        nes.add_watchpoint(Address::new(0x8004), true, false);
+
+        nes.pump(100);
+
+        assert_eq!(nes.cpu.memory().take_watchpoint_hit(), None);
+    }
+
+    #[test]
+    fn tick_until_break_stops_after_write_to_watchpoint() {
+        // LDA $10; STA $10; JMP $8000
+        let mut nes = nes_with_program(&[0xA5, 0x10, 0x85, 0x10, 0x4C, 0x00, 0x80]);
+        nes.add_watchpoint(Address::new(0x0010), false, true);
+
+        assert_eq!(
+            nes.tick_until_break(),
+            TickBreak::Watchpoint {
+                address: Address::new(0x0010),
+                write: true
+            }
+        );
+        assert_eq!(nes.program_counter(), Address::new(0x8004));
+    }
+
+    #[test]
+    fn tick_until_break_ignores_watchpoints_hit_before_it_was_called() {
+        // STA $10; JMP $8002
+        let mut nes = nes_with_program(&[0x85, 0x10, 0x4C, 0x02, 0x80]);
+        nes.add_watchpoint(Address::new(0x0010), false, true);
+        nes.tick();
+
+        assert_eq!(nes.tick_until_break(), TickBreak::Step);
+        assert_eq!(nes.frame_count(), 1);
+    }
+
+    #[test]
+    fn tick_until_break_stops_at_end_of_frame_without_breakpoints() {
+        let mut nes = looping_nes();
+
+        assert_eq!(nes.tick_until_break(), TickBreak::Step);
+        assert_eq!(nes.frame_count(), 1);
+    }
+
+    #[test]
+    fn run_cycles_runs_at_least_requested_cycles_and_advances_ppu() {
+        // LDA #$01 (2 cycles); STA $10 (3 cycles); JMP $8000 (3 cycles)
+        let mut nes = nes_with_program(&[0xA9, 0x01, 0x85, 0x10, 0x4C, 0x00, 0x80]);
+        let dot = |nes: &mut NES<(), ()>| {
+            let (cycle, scanline) = nes.ppu().beam_position();
+            u64::from(scanline) * 341 + u64::from(cycle)
+        };
+        let start = dot(&mut nes);
+
+        let cycles = nes.run_cycles(1001);
+
+        assert!((1001..1001 + 3).contains(&cycles));
+        assert_eq!(dot(&mut nes) - start, cycles * 3);
+
+        // Frames don't stop it early
+        let cycles = nes.run_cycles(100_000);
+        assert!(cycles >= 100_000);
+        assert!(nes.frame_count() >= 3);
+    }
+
+    #[test]
+    fn pump_stops_at_instruction_limit() {
+        let mut nes = looping_nes();
+        nes.set_instruction_limit(Some(50));
+
+        let result = nes.pump(u64::MAX);
+
+        assert!(result.instruction_limit_reached);
+        assert!(!result.frame_complete);
+        assert_eq!(result.cycles, 50 * 3);
+        assert_eq!(nes.instruction_count(), 50);
+
+        // Once the limit is reached, pumping does nothing
+        assert_eq!(nes.pump(u64::MAX).cycles, 0);
+    }
+
+    #[test]
+    fn instruction_limit_applies_when_polling_ppu_status() {
+        // BIT $2002; BPL -5
+        let mut nes = nes_with_program(&[0x2C, 0x02, 0x20, 0x10, 0xFB]);
+        nes.set_instruction_limit(Some(51));
+
+        let result = nes.pump(u64::MAX);
+
+        assert!(result.instruction_limit_reached);
+        assert_eq!(nes.instruction_count(), 51);
+    }
+
+    #[test]
+    fn soft_reset_preserves_internal_ram() {
+        let mut nes = looping_nes();
+        nes.pump(1000);
+        nes.cpu.memory().write(Address::new(0x0010), 0x42);
+        nes.cpu.memory().write(Address::new(0x6000), 0x43);
+
+        nes.soft_reset();
+
+        assert_eq!(nes.program_counter(), Address::new(0x8000));
+        assert_eq!(nes.read_cpu(Address::new(0x0010)), 0x42);
+        assert_eq!(nes.read_cpu(Address::new(0x6000)), 0x43);
+    }
+
+    #[test]
+    fn hard_reset_clears_internal_ram() {
+        let mut nes = looping_nes();
+        nes.pump(1000);
+        nes.cpu.memory().write(Address::new(0x0010), 0x42);
+        nes.cpu.memory().write(Address::new(0x6000), 0x43);
+
+        nes.hard_reset();
+
+        assert_eq!(nes.program_counter(), Address::new(0x8000));
+        assert_eq!(nes.read_cpu(Address::new(0x0010)), 0);
+        // PRG RAM is on the cartridge, so is unaffected
+        assert_eq!(nes.read_cpu(Address::new(0x6000)), 0x43);
+    }
+
+    #[test]
+    fn cartridge_irq_is_raised_on_scanline_set_by_mapper() {
+        let mut prg_rom = vec![0u8; 0x8000];
+        // SEI; JMP $E001
+        prg_rom[0x6000..0x6004].copy_from_slice(&[0x78, 0x4C, 0x01, 0xE0]);
+        // Reset vector
+        prg_rom[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0xE0]);
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let cartridge = Cartridge::new(prg_rom.into(), chr_rom, false, MapperKind::MMC3);
+
+        let mut nes = NES::new(cartridge, (), ());
+
+        let last_scanline = Rc::new(Cell::new(0));
+        let hook_last_scanline = last_scanline.clone();
+        nes.set_scanline_hook(move |scanline, _| hook_last_scanline.set(scanline));
+
+        let memory = nes.cpu.memory();
+        // Sprites from 0x1000 and background from 0x0000, so A12 rises once per scanline
+        memory.write(Address::new(0x2000), 0b0000_1000);
+        memory.write(Address::new(0x2001), 0b0001_1000);
+        // Set IRQ latch, reload and enable
+        memory.write(Address::new(0xc000), 10);
+        memory.write(Address::new(0xc001), 0);
+        memory.write(Address::new(0xe001), 0);
+
+        let fired = (0..30_000).any(|_| {
+            nes.run_instructions(1);
+            nes.cpu.irq_pending()
+        });
+
+        assert!(fired);
+        assert_eq!(last_scanline.get(), 9);
+
+        // Acknowledging the IRQ through the mapper releases the IRQ line
+        nes.cpu.memory().write(Address::new(0xe000), 0);
+        nes.run_instructions(1);
+        assert!(!nes.cpu.irq_pending());
+    }
+
+    #[test]
+    fn dmc_irq_is_raised_when_sample_finishes() {
+        let mut nes = looping_nes();
+
+        let memory = nes.cpu.memory();
+        // Enable IRQ and play a 1 byte sample
+        memory.write(Address::new(0x4010), 0b1000_0000);
+        memory.write(Address::new(0x4013), 0);
+        memory.write(Address::new(0x4015), 0b0001_0000);
+
+        nes.run_instructions(1);
+        assert!(nes.cpu.irq_pending());
+
+        // Writing to the status register acknowledges the IRQ
+        nes.cpu.memory().write(Address::new(0x4015), 0);
+        nes.run_instructions(1);
+        assert!(!nes.cpu.irq_pending());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn loading_saved_state_continues_with_identical_output() {
+        use std::cell::RefCell;
+
+        #[rustfmt::skip]
+        let program = [
+            // Keep changing the background color
+            0xA9, 0x3F,       // LDA #$3F
+            0x8D, 0x06, 0x20, // STA $2006
+            0xA9, 0x00,       // LDA #$00
+            0x8D, 0x06, 0x20, // STA $2006
+            0xE8,             // INX
+            0x8A,             // TXA
+            0x29, 0x3F,       // AND #$3F
+            0x8D, 0x07, 0x20, // STA $2007
+            0x4C, 0x00, 0x80, // JMP $8000
+        ];
+
+        let mut nes = nes_with_program(&program);
+        // Stop part way through a frame
+        nes.run_instructions(12_345);
+        let state = nes.save_state();
+
+        let mut restored = nes_with_program(&program);
+        restored.load_state(&state).unwrap();
+
+        let record_lines = |nes: &mut NES<(), ()>| {
+            let lines = Rc::new(RefCell::new(vec![]));
+            let hook_lines = lines.clone();
+            nes.set_scanline_hook(move |scanline, colors| {
+                hook_lines.borrow_mut().push((scanline, colors.to_vec()));
+            });
+            nes.run_instructions(50_000);
+            let lines = lines.borrow().clone();
+            lines
+        };
+
+        let lines = record_lines(&mut nes);
+        let restored_lines = record_lines(&mut restored);
+
+        assert!(!lines.is_empty());
+        assert!(
+            lines == restored_lines,
+            "Output differs after loading state"
+        );
+        assert_eq!(nes.cpu.registers(), restored.cpu.registers());
+        assert_eq!(nes.save_state(), restored.save_state());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn loading_invalid_state_is_an_error() {
+        let mut nes = looping_nes();
+
+        assert!(nes.load_state(&[1, 2, 3]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn loading_state_for_another_cartridge_changes_nothing() {
+        // LDA #$12; STA $0010; JMP $8000
+        let program = [0xA9, 0x12, 0x8D, 0x10, 0x00, 0x4C, 0x00, 0x80];
+        let prg_rom = nes_cartridge(&program).prg_rom().to_vec();
+
+        let other_mapper = Cartridge::new(
+            prg_rom.clone().into(),
+            Box::new([0u8; 0x2000]),
+            false,
+            MapperKind::CNROM,
+        );
+        let mut bigger_rom = prg_rom.clone();
+        bigger_rom.extend(prg_rom);
+        let bigger_rom = Cartridge::new(
+            bigger_rom.into(),
+            Box::new([0u8; 0x2000]),
+            false,
+            MapperKind::NROM,
+        );
+
+        for cartridge in [other_mapper, bigger_rom] {
+            let mut other = NES::new(cartridge, (), ());
+            other.run_instructions(1001);
+            let other_state = other.save_state();
+
+            let mut nes = nes_with_program(&program);
+            nes.run_instructions(10);
+            let registers = nes.cpu.registers();
+            let state = nes.save_state();
+
+            assert!(nes.load_state(&other_state).is_err());
+            assert_eq!(nes.cpu.registers(), registers);
+            assert_eq!(nes.save_state(), state);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_state_does_not_include_prg_rom() {
+        let mut prg_rom = vec![0u8; 0x8000];
+        prg_rom[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0x80]);
+        let cartridge = Cartridge::new(
+            prg_rom.into(),
+            Box::new([0u8; 0x2000]),
+            false,
+            MapperKind::NROM,
+        );
+
+        let small = looping_nes().save_state();
+        let large = NES::new(cartridge, (), ()).save_state();
+
+        assert_eq!(small.len(), large.len());
+    }
+
+    #[test]
+    fn second_controller_is_read_from_0x4017() {
+        let mut nes = looping_nes();
+        nes.controller2().press(Buttons::A | Buttons::START);
+
+        nes.cpu.memory().write(Address::new(0x4016), 1);
+        nes.cpu.memory().write(Address::new(0x4016), 0);
+
+        let controller_1: Vec<u8> = (0..8).map(|_| nes.read_cpu(Address::new(0x4016))).collect();
+        let controller_2: Vec<u8> = (0..8).map(|_| nes.read_cpu(Address::new(0x4017))).collect();
+
+        assert_eq!(controller_1, [0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(controller_2, [1, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn setting_controller_state_replaces_all_buttons() {
+        let mut nes = looping_nes();
+        nes.controller().press(Buttons::SELECT);
+        nes.set_controller_state(0, Buttons::all());
+        nes.set_controller_state(1, Buttons::B | Buttons::RIGHT);
+
+        assert_eq!(nes.controller_state(0), Buttons::all());
+        assert_eq!(nes.controller_state(1), Buttons::B | Buttons::RIGHT);
+
+        nes.cpu.memory().write(Address::new(0x4016), 1);
+        nes.cpu.memory().write(Address::new(0x4016), 0);
+
+        let controller_1: Vec<u8> = (0..8).map(|_| nes.read_cpu(Address::new(0x4016))).collect();
+        let controller_2: Vec<u8> = (0..8).map(|_| nes.read_cpu(Address::new(0x4017))).collect();
+
+        assert_eq!(controller_1, [1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(controller_2, [0, 1, 0, 0, 0, 0, 0, 1]);
+
+        nes.set_controller_state(0, Buttons::A);
+        assert_eq!(nes.controller_state(0), Buttons::A);
+    }
+
+    #[test]
+    #[should_panic]
+    fn setting_state_of_missing_controller_port_panics() {
+        let mut nes = looping_nes();
+        nes.set_controller_state(2, Buttons::A);
+    }
+
+    #[test]
+    fn zapper_senses_light_drawn_where_it_is_aimed() {
+        // Set the backdrop color to white, then loop
+        let mut nes = nes_with_program(&[
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F; STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00; STA $2006
+            0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30; STA $2007
+            0x4C, 0x0F, 0x80, // JMP $800F
+        ]);
+        nes.set_zapper_connected(true);
+        nes.run_frame();
+
+        nes.zapper().unwrap().aim(10, 230);
+        nes.zapper().unwrap().set_trigger(true);
+        nes.run_frame();
+        assert_eq!(nes.read_cpu(Address::new(0x4017)), 0b1_0000);
+
+        // By the end of the frame, the light from the top of the screen has faded
+        nes.zapper().unwrap().aim(10, 10);
+        nes.run_frame();
+        assert_eq!(nes.read_cpu(Address::new(0x4017)), 0b1_1000);
+
+        nes.set_zapper_connected(false);
+        assert!(nes.zapper().is_none());
+    }
+
+    #[test]
+    fn zapper_senses_light_in_the_active_palette() {
+        // Set the backdrop color to white, then loop
+        let mut nes = nes_with_program(&[
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F; STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00; STA $2006
+            0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30; STA $2007
+            0x4C, 0x0F, 0x80, // JMP $800F
+        ]);
+        nes.set_palette(Palette::from_colors([(0, 0, 0); 64]));
+        nes.set_zapper_connected(true);
+        nes.run_frame();
+
+        nes.zapper().unwrap().aim(10, 230);
+        nes.run_frame();
+        assert_eq!(nes.read_cpu(Address::new(0x4017)), 0b1000);
+    }
+
+    pub fn looping_nes() -> NES<(), ()> {
+        // JMP $8000
+        nes_with_program(&[0x4C, 0x00, 0x80])
+    }
+
+    pub(crate) fn nes_with_program(program: &[u8]) -> NES<(), ()> {
+        NES::new(nes_cartridge(program), (), ())
+    }
+
+    fn nes_cartridge(program: &[u8]) -> Cartridge {
+        let mut prg_rom = [0u8; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        // Reset vector
+        prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+
+        Cartridge::new(
+            Box::new(prg_rom),
+            Box::new([0u8; 0x2000]),
+            false,
+            MapperKind::NROM,
+        )
+    }
+}
@@ -1,23 +0,0 @@
-use crate::INesReadError;
-
-#[derive(Debug, Eq, PartialEq)]
-pub enum Mapper {
-    NROM,
-    UxROM,
-    MMC1,
-    Namco129,
-}
-
-impl TryFrom<u8> for Mapper {
-    type Error = INesReadError;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        Ok(match value {
-            0 => Self::NROM,
-            1 => Self::MMC1,
-            2 => Self::UxROM,
-            19 => Self::Namco129,
-            _ => return Err(Self::Error::UnrecognisedMapper(value)),
-        })
-    }
-}
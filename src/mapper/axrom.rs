@@ -0,0 +1,133 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{read_bank, windows, CHRMemory, Mapper, Mirroring, PRG_RAM_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/// Mapper 7. Writes to PRG ROM select a 32KB PRG bank in bits 0-2, and which nametable is used for
+/// single-screen mirroring in bit 4.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Axrom {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    bank: u8,
+    mirroring: Mirroring,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Axrom {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Axrom {
+            prg_rom,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenA,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => read_bank(&self.prg_rom, self.bank, PRG_BANK_SIZE, address.index()),
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => {
+                self.bank = byte & 0b111;
+                self.mirroring = if byte & 0b1_0000 == 0 {
+                    Mirroring::SingleScreenA
+                } else {
+                    Mirroring::SingleScreenB
+                };
+            }
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(address.index())
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        self.chr.write(address.index(), byte);
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        windows(PRG_BANK_SIZE, &[self.bank])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Axrom {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axrom_switches_32kb_prg_banks() {
+        let mut axrom = axrom();
+
+        for bank in 0..8 {
+            axrom.cpu_write(Address::new(0x8000), bank);
+            assert_eq!(axrom.cpu_read(Address::new(0x8000)), bank);
+            assert_eq!(axrom.cpu_read(Address::new(0xffff)), bank);
+        }
+    }
+
+    #[test]
+    fn axrom_selects_single_screen_nametable_with_bit_4() {
+        let mut axrom = axrom();
+        assert_eq!(axrom.mirroring(), Some(Mirroring::SingleScreenA));
+
+        axrom.cpu_write(Address::new(0x8000), 0b1_0011);
+        assert_eq!(axrom.mirroring(), Some(Mirroring::SingleScreenB));
+        assert_eq!(axrom.cpu_read(Address::new(0x8000)), 3);
+
+        axrom.cpu_write(Address::new(0x8000), 0b0_0011);
+        assert_eq!(axrom.mirroring(), Some(Mirroring::SingleScreenA));
+    }
+
+    fn axrom() -> Axrom {
+        let prg_rom = (0..0x40000).map(|i| (i / PRG_BANK_SIZE) as u8).collect();
+        Axrom::new(prg_rom, Box::new([0; 0x2000]), true)
+    }
+}
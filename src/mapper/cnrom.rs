@@ -0,0 +1,120 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{windows, CHRMemory, Mapper, PRG_RAM_SIZE};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 3. PRG ROM is fixed like NROM, but writes to PRG ROM select an 8KB bank of CHR ROM.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cnrom {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    chr_bank: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Cnrom {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Cnrom {
+            prg_rom,
+            chr_bank: 0,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+
+    fn chr_address(&self, address: Address) -> usize {
+        self.chr_bank as usize * CHR_BANK_SIZE + address.index()
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => self.prg_rom[(address.index() - 0x8000) % self.prg_rom.len()],
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => self.chr_bank = byte & 0b11,
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(self.chr_address(address))
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        let chr_address = self.chr_address(address);
+        self.chr.write(chr_address, byte);
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        let bank_size = self.prg_rom.len().min(0x8000);
+        windows(bank_size, &vec![0; 0x8000 / bank_size])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Cnrom {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cnrom_fixes_prg_rom() {
+        let prg_rom = (0..0x4000).map(|i| i as u8).collect();
+        let mut cnrom = Cnrom::new(prg_rom, Box::new([0; 0x8000]), false);
+
+        cnrom.cpu_write(Address::new(0x8000), 1);
+
+        for value in [0x8000, 0xbfff, 0xc000, 0xffff] {
+            assert_eq!(cnrom.cpu_read(Address::new(value)), value as u8);
+        }
+    }
+
+    #[test]
+    fn cnrom_switches_8kb_chr_banks() {
+        let chr_rom = (0..0x8000).map(|i| (i / CHR_BANK_SIZE) as u8).collect();
+        let mut cnrom = Cnrom::new(Box::new([0; 0x8000]), chr_rom, false);
+
+        for bank in 0..4 {
+            cnrom.cpu_write(Address::new(0x8000), bank);
+            assert_eq!(cnrom.ppu_read(Address::new(0x0000)), bank);
+            assert_eq!(cnrom.ppu_read(Address::new(0x1fff)), bank);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{read_bank, windows, CHRMemory, Mapper, Mirroring, PRG_RAM_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+// PRG bank mode 3, fixing the last bank at 0xc000, is selected on power-on and reset
+const CONTROL_RESET: u8 = 0b0_1100;
+
+/// Mapper 1. Registers are written serially: bits are shifted into a shift register, and after 5
+/// writes the shift register is used to update a register.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmc1 {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    shift_register: u8,
+    writes: u8,
+    /// Mirroring in bits 0-1, PRG bank mode in bits 2-3 and CHR bank mode in bit 4.
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Mmc1 {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Mmc1 {
+            prg_rom,
+            shift_register: 0,
+            writes: 0,
+            control: CONTROL_RESET,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+
+    fn write_register(&mut self, address: Address, byte: u8) {
+        let reset = (byte >> 7) & 1 == 1;
+        if reset {
+            self.shift_register = 0;
+            self.writes = 0;
+            self.control |= CONTROL_RESET;
+            return;
+        }
+
+        self.shift_register >>= 1;
+        self.shift_register |= (byte & 1) << 4;
+        self.writes += 1;
+
+        if self.writes < 5 {
+            return;
+        }
+
+        let value = self.shift_register;
+        match address.index() {
+            0x8000..=0x9fff => self.control = value,
+            0xa000..=0xbfff => self.chr_bank_0 = value,
+            0xc000..=0xdfff => self.chr_bank_1 = value,
+            0xe000..=0xffff => self.prg_bank = value & 0b1111,
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+
+        self.shift_register = 0;
+        self.writes = 0;
+    }
+
+    fn last_prg_bank(&self) -> u8 {
+        (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1) as u8
+    }
+
+    /// The 16KB PRG banks at 0x8000 and 0xc000.
+    fn prg_banks(&self) -> [u8; 2] {
+        match (self.control >> 2) & 0b11 {
+            // Switch 32KB at 0x8000, ignoring the low bit of the bank number
+            0 | 1 => [self.prg_bank & !1, self.prg_bank | 1],
+            // Fix first bank at 0x8000, switch 16KB bank at 0xc000
+            2 => [0, self.prg_bank],
+            // Fix last bank at 0xc000, switch 16KB bank at 0x8000
+            _ => [self.prg_bank, self.last_prg_bank()],
+        }
+    }
+
+    /// The 4KB CHR banks at 0x0000 and 0x1000.
+    fn chr_banks(&self) -> [u8; 2] {
+        if self.control & 0b1_0000 == 0 {
+            // Switch 8KB at a time, ignoring the low bit of the bank number
+            [self.chr_bank_0 & !1, self.chr_bank_0 | 1]
+        } else {
+            [self.chr_bank_0, self.chr_bank_1]
+        }
+    }
+
+    fn chr_address(&self, address: Address) -> usize {
+        let bank = self.chr_banks()[address.index() / CHR_BANK_SIZE];
+        bank as usize * CHR_BANK_SIZE + address.index() % CHR_BANK_SIZE
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => {
+                let offset = address.index() - 0x8000;
+                let bank = self.prg_banks()[offset / PRG_BANK_SIZE];
+                read_bank(&self.prg_rom, bank, PRG_BANK_SIZE, offset)
+            }
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => self.write_register(address, byte),
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(self.chr_address(address))
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        let chr_address = self.chr_address(address);
+        self.chr.write(chr_address, byte);
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(match self.control & 0b11 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        })
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        windows(PRG_BANK_SIZE, &self.prg_banks())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Mmc1 {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mmc1_selects_prg_bank_after_five_serial_writes() {
+        let mut mmc1 = mmc1();
+
+        // Shift in 0b00101, least significant bit first
+        for bit in [1, 0, 1, 0] {
+            mmc1.cpu_write(Address::new(0xe000), bit);
+            assert_eq!(mmc1.cpu_read(Address::new(0x8000)), 0);
+        }
+        mmc1.cpu_write(Address::new(0xe000), 0);
+
+        assert_eq!(mmc1.cpu_read(Address::new(0x8000)), 5);
+        assert_eq!(mmc1.cpu_read(Address::new(0xc000)), 7);
+    }
+
+    #[test]
+    fn mmc1_shift_register_is_reset_by_writing_bit_7() {
+        let mut mmc1 = mmc1();
+
+        mmc1.cpu_write(Address::new(0xe000), 1);
+        mmc1.cpu_write(Address::new(0xe000), 0x80);
+        write_register(&mut mmc1, 0xe000, 2);
+
+        assert_eq!(mmc1.cpu_read(Address::new(0x8000)), 2);
+    }
+
+    #[test]
+    fn mmc1_can_fix_first_prg_bank() {
+        let mut mmc1 = mmc1();
+
+        write_register(&mut mmc1, 0x8000, 0b0_1000);
+        write_register(&mut mmc1, 0xe000, 3);
+
+        assert_eq!(mmc1.cpu_read(Address::new(0x8000)), 0);
+        assert_eq!(mmc1.cpu_read(Address::new(0xc000)), 3);
+    }
+
+    #[test]
+    fn mmc1_can_switch_32kb_prg_banks() {
+        let mut mmc1 = mmc1();
+
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        // Low bit of the bank number is ignored
+        write_register(&mut mmc1, 0xe000, 5);
+
+        assert_eq!(mmc1.cpu_read(Address::new(0x8000)), 4);
+        assert_eq!(mmc1.cpu_read(Address::new(0xc000)), 5);
+        assert_eq!(
+            mmc1.prg_windows(),
+            vec![
+                (Address::new(0x8000), Address::new(0xbfff), 4),
+                (Address::new(0xc000), Address::new(0xffff), 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn mmc1_can_switch_4kb_chr_banks() {
+        let mut mmc1 = mmc1();
+
+        write_register(&mut mmc1, 0x8000, 0b1_1100);
+        write_register(&mut mmc1, 0xa000, 3);
+        write_register(&mut mmc1, 0xc000, 6);
+
+        assert_eq!(mmc1.ppu_read(Address::new(0x0000)), 3);
+        assert_eq!(mmc1.ppu_read(Address::new(0x0fff)), 3);
+        assert_eq!(mmc1.ppu_read(Address::new(0x1000)), 6);
+        assert_eq!(mmc1.ppu_read(Address::new(0x1fff)), 6);
+    }
+
+    #[test]
+    fn mmc1_can_switch_8kb_chr_banks() {
+        let mut mmc1 = mmc1();
+
+        write_register(&mut mmc1, 0x8000, 0b0_1100);
+        // Low bit of the bank number is ignored, and bank 1 is unused
+        write_register(&mut mmc1, 0xa000, 3);
+        write_register(&mut mmc1, 0xc000, 6);
+
+        assert_eq!(mmc1.ppu_read(Address::new(0x0000)), 2);
+        assert_eq!(mmc1.ppu_read(Address::new(0x1000)), 3);
+    }
+
+    #[test]
+    fn mmc1_control_register_sets_mirroring() {
+        let mut mmc1 = mmc1();
+
+        for (control, mirroring) in [
+            (0b0_1100, Mirroring::SingleScreenA),
+            (0b0_1101, Mirroring::SingleScreenB),
+            (0b0_1110, Mirroring::Vertical),
+            (0b0_1111, Mirroring::Horizontal),
+        ] {
+            write_register(&mut mmc1, 0x8000, control);
+            assert_eq!(mmc1.mirroring(), Some(mirroring));
+        }
+    }
+
+    fn write_register(mmc1: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(Address::new(address), (value >> bit) & 1);
+        }
+    }
+
+    fn mmc1() -> Mmc1 {
+        let prg_rom = (0..0x20000).map(|i| (i / PRG_BANK_SIZE) as u8).collect();
+        let chr_rom = (0..0x8000).map(|i| (i / CHR_BANK_SIZE) as u8).collect();
+        Mmc1::new(prg_rom, chr_rom, false)
+    }
+}
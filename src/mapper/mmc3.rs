@@ -0,0 +1,333 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{read_bank, windows, CHRMemory, Mapper, Mirroring, PRG_RAM_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Mapper 4. Eight bank registers are selected by writing to `0x8000` then set by writing to
+/// `0x8001`. A counter clocked by PPU address line A12 rising, which happens once per scanline
+/// when rendering, can raise an IRQ on a chosen scanline.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmc3 {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    /// Register to update in bits 0-2, PRG bank mode in bit 6 and CHR A12 inversion in bit 7.
+    bank_select: u8,
+    /// R0-R5 select CHR banks, R6 and R7 select PRG banks.
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Mmc3 {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Mmc3 {
+            prg_rom,
+            bank_select: 0,
+            registers: [0; 8],
+            mirroring: Mirroring::Vertical,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq: false,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+
+    fn write_register(&mut self, address: Address, byte: u8) {
+        let even = address.index() & 1 == 0;
+        match (address.index(), even) {
+            (0x8000..=0x9fff, true) => self.bank_select = byte,
+            (0x8000..=0x9fff, false) => {
+                self.registers[(self.bank_select & 0b111) as usize] = byte;
+            }
+            (0xa000..=0xbfff, true) => {
+                self.mirroring = if byte & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            (0xa000..=0xbfff, false) => {
+                // PRG RAM protect, which emulators usually ignore for compatibility with MMC6
+            }
+            (0xc000..=0xdfff, true) => self.irq_latch = byte,
+            (0xc000..=0xdfff, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000..=0xffff, true) => {
+                self.irq_enabled = false;
+                self.irq = false;
+            }
+            (0xe000..=0xffff, false) => self.irq_enabled = true,
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn last_prg_bank(&self) -> u8 {
+        (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1) as u8
+    }
+
+    /// The 8KB PRG banks at 0x8000, 0xa000, 0xc000 and 0xe000.
+    fn prg_banks(&self) -> [u8; 4] {
+        let second_last = self.last_prg_bank().saturating_sub(1);
+        let [.., r6, r7] = self.registers;
+        if self.bank_select & 0b0100_0000 == 0 {
+            [r6, r7, second_last, self.last_prg_bank()]
+        } else {
+            [second_last, r7, r6, self.last_prg_bank()]
+        }
+    }
+
+    fn chr_address(&self, address: Address) -> usize {
+        // When inverted, the 2KB banks are at 0x1000 and the 1KB banks are at 0x0000
+        let inverted = self.bank_select & 0b1000_0000 != 0;
+        let address = address.index() ^ if inverted { 0x1000 } else { 0x0000 };
+
+        let [r0, r1, r2, r3, r4, r5, ..] = self.registers;
+        let bank = match address / CHR_BANK_SIZE {
+            0 => r0 & !1,
+            1 => r0 | 1,
+            2 => r1 & !1,
+            3 => r1 | 1,
+            4 => r2,
+            5 => r3,
+            6 => r4,
+            _ => r5,
+        };
+
+        bank as usize * CHR_BANK_SIZE + address % CHR_BANK_SIZE
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => {
+                let offset = address.index() - 0x8000;
+                let bank = self.prg_banks()[offset / PRG_BANK_SIZE];
+                read_bank(&self.prg_rom, bank, PRG_BANK_SIZE, offset)
+            }
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => self.write_register(address, byte),
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(self.chr_address(address))
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        let chr_address = self.chr_address(address);
+        self.chr.write(chr_address, byte);
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        Some(self.mirroring)
+    }
+
+    fn notify_a12_rise(&mut self) {
+        self.clock_irq_counter();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        windows(PRG_BANK_SIZE, &self.prg_banks())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Mmc3 {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mmc3_switches_prg_banks_at_0x8000_and_0xa000() {
+        let mut mmc3 = mmc3();
+
+        set_register(&mut mmc3, 6, 3);
+        set_register(&mut mmc3, 7, 5);
+
+        assert_eq!(read_banks(&mut mmc3), [3, 5, 14, 15]);
+    }
+
+    #[test]
+    fn mmc3_prg_bank_mode_swaps_0x8000_and_0xc000() {
+        let mut mmc3 = mmc3();
+
+        set_register(&mut mmc3, 6, 3);
+        set_register(&mut mmc3, 7, 5);
+        mmc3.cpu_write(Address::new(0x8000), 0b0100_0000);
+
+        assert_eq!(read_banks(&mut mmc3), [14, 5, 3, 15]);
+    }
+
+    #[test]
+    fn mmc3_switches_2kb_and_1kb_chr_banks() {
+        let mut mmc3 = mmc3();
+
+        for (register, bank) in [(0, 8), (1, 11), (2, 20), (3, 21), (4, 22), (5, 23)] {
+            set_register(&mut mmc3, register, bank);
+        }
+
+        let banks: Vec<u8> = (0..8)
+            .map(|i| mmc3.ppu_read(Address::new(i * 0x400)))
+            .collect();
+        // Low bit is ignored for 2KB banks
+        assert_eq!(banks, [8, 9, 10, 11, 20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn mmc3_chr_inversion_swaps_pattern_tables() {
+        let mut mmc3 = mmc3();
+
+        set_register(&mut mmc3, 0, 8);
+        set_register(&mut mmc3, 2, 20);
+        mmc3.cpu_write(Address::new(0x8000), 0b1000_0000);
+
+        assert_eq!(mmc3.ppu_read(Address::new(0x0000)), 20);
+        assert_eq!(mmc3.ppu_read(Address::new(0x1000)), 8);
+        assert_eq!(mmc3.ppu_read(Address::new(0x1400)), 9);
+    }
+
+    #[test]
+    fn mmc3_sets_mirroring() {
+        let mut mmc3 = mmc3();
+
+        mmc3.cpu_write(Address::new(0xa000), 1);
+        assert_eq!(mmc3.mirroring(), Some(Mirroring::Horizontal));
+
+        mmc3.cpu_write(Address::new(0xa000), 0);
+        assert_eq!(mmc3.mirroring(), Some(Mirroring::Vertical));
+    }
+
+    #[test]
+    fn mmc3_irq_fires_on_scanline_set_by_latch() {
+        let mut mmc3 = mmc3();
+
+        mmc3.cpu_write(Address::new(0xc000), 3);
+        mmc3.cpu_write(Address::new(0xc001), 0);
+        mmc3.cpu_write(Address::new(0xe001), 0);
+
+        let irq_scanline = (0..10).find(|_| {
+            render_scanline(&mut mmc3);
+            mmc3.irq()
+        });
+
+        // The first scanline reloads the counter, then it counts down to 0
+        assert_eq!(irq_scanline, Some(3));
+    }
+
+    #[test]
+    fn mmc3_irq_is_not_raised_when_disabled() {
+        let mut mmc3 = mmc3();
+
+        mmc3.cpu_write(Address::new(0xc000), 1);
+        mmc3.cpu_write(Address::new(0xc001), 0);
+
+        for _ in 0..10 {
+            render_scanline(&mut mmc3);
+        }
+
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn mmc3_irq_is_acknowledged_by_writing_0xe000() {
+        let mut mmc3 = mmc3();
+
+        mmc3.cpu_write(Address::new(0xc000), 0);
+        mmc3.cpu_write(Address::new(0xe001), 0);
+        render_scanline(&mut mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.cpu_write(Address::new(0xe000), 0);
+
+        assert!(!mmc3.irq());
+    }
+
+    /// A12 rises once per scanline when fetching background tiles from 0x0000 and sprites from
+    /// 0x1000
+    fn render_scanline(mmc3: &mut Mmc3) {
+        mmc3.notify_a12_rise();
+    }
+
+    fn set_register(mmc3: &mut Mmc3, register: u8, value: u8) {
+        mmc3.cpu_write(Address::new(0x8000), register);
+        mmc3.cpu_write(Address::new(0x8001), value);
+    }
+
+    fn read_banks(mmc3: &mut Mmc3) -> [u8; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|address| mmc3.cpu_read(Address::new(address)))
+    }
+
+    fn mmc3() -> Mmc3 {
+        let prg_rom = (0..0x20000).map(|i| (i / PRG_BANK_SIZE) as u8).collect();
+        let chr_rom = (0..0x8000).map(|i| (i / CHR_BANK_SIZE) as u8).collect();
+        Mmc3::new(prg_rom, chr_rom, false)
+    }
+}
@@ -0,0 +1,256 @@
+//! Cartridge hardware that decides where the CPU and PPU's accesses to the cartridge go.
+#[cfg(feature = "serde")]
+use std::any::Any;
+
+use log::warn;
+
+use crate::Address;
+use crate::INesReadError;
+#[cfg(feature = "serde")]
+use crate::{state, StateError};
+
+pub use self::axrom::Axrom;
+pub use self::cnrom::Cnrom;
+pub use self::mmc1::Mmc1;
+pub use self::mmc3::Mmc3;
+pub use self::nrom::Nrom;
+pub use self::uxrom::Uxrom;
+
+mod axrom;
+mod cnrom;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod uxrom;
+
+const CHR_RAM_SIZE: usize = 0x2000;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+
+#[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MapperKind {
+    NROM,
+    UxROM,
+    MMC1,
+    CNROM,
+    MMC3,
+    AxROM,
+    Namco129,
+}
+
+impl MapperKind {
+    /// The iNES mapper number.
+    pub fn number(&self) -> u16 {
+        match self {
+            Self::NROM => 0,
+            Self::MMC1 => 1,
+            Self::UxROM => 2,
+            Self::CNROM => 3,
+            Self::MMC3 => 4,
+            Self::AxROM => 7,
+            Self::Namco129 => 19,
+        }
+    }
+}
+
+impl TryFrom<u16> for MapperKind {
+    type Error = INesReadError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::NROM,
+            1 => Self::MMC1,
+            2 => Self::UxROM,
+            3 => Self::CNROM,
+            4 => Self::MMC3,
+            7 => Self::AxROM,
+            19 => Self::Namco129,
+            _ => return Err(Self::Error::UnsupportedMapper(value)),
+        })
+    }
+}
+
+/// How the four nametables in the PPU address space are mapped onto the 2KB of nametable RAM.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mirroring {
+    /// `0x2000` mirrors `0x2400`, and `0x2800` mirrors `0x2c00`.
+    Horizontal,
+    /// `0x2000` mirrors `0x2800`, and `0x2400` mirrors `0x2c00`.
+    Vertical,
+    /// All four nametables mirror the first 1KB of nametable RAM.
+    SingleScreenA,
+    /// All four nametables mirror the second 1KB of nametable RAM.
+    SingleScreenB,
+    /// The cartridge provides another 2KB of nametable RAM, so there's no mirroring.
+    FourScreen,
+}
+
+/// Connects the CPU and PPU to memory on the cartridge, and switches which banks of it are visible.
+pub trait Mapper: MapperState {
+    /// Read from cartridge space in the CPU address space, from `0x6000` to `0xffff`.
+    fn cpu_read(&mut self, address: Address) -> u8;
+
+    /// Write to cartridge space in the CPU address space, from `0x6000` to `0xffff`.
+    fn cpu_write(&mut self, address: Address, byte: u8);
+
+    /// Read from the pattern tables in the PPU address space, from `0x0000` to `0x1fff`.
+    fn ppu_read(&mut self, address: Address) -> u8;
+
+    /// Write to the pattern tables in the PPU address space, from `0x0000` to `0x1fff`.
+    fn ppu_write(&mut self, address: Address, byte: u8);
+
+    /// The mirroring selected by the mapper, or `None` if it's fixed by how the cartridge is wired.
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    /// Called when PPU address line A12 rises, as pattern table accesses move from `0x0000-0x0fff`
+    /// to `0x1000-0x1fff`. Mappers such as MMC3 count these to raise an IRQ on a given scanline.
+    fn notify_a12_rise(&mut self) {}
+
+    /// Whether the mapper is asserting the CPU's IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn prg_rom(&self) -> &[u8];
+
+    /// The raw CHR data. If the cartridge has CHR RAM, this is the current contents of the RAM.
+    fn chr(&self) -> &[u8];
+
+    fn prg_ram(&mut self) -> &mut [u8];
+
+    /// The start, inclusive end and currently selected bank of each window into PRG ROM.
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)>;
+}
+
+/// Save and restore all state of a mapper, including the contents of ROM and RAM.
+#[cfg(feature = "serde")]
+pub trait MapperState {
+    fn save_state(&self) -> Result<Vec<u8>, StateError>;
+
+    /// Read state saved by [MapperState::save_state], checking it's valid for this mapper without
+    /// changing anything. The result is applied with [MapperState::restore_state].
+    fn read_state(&self, state: &[u8]) -> Result<Box<dyn Any>, StateError>;
+
+    /// Replace the mapper's state with state returned by [MapperState::read_state].
+    fn restore_state(&mut self, state: Box<dyn Any>);
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned + SavedWithoutRom + 'static> MapperState
+    for T
+{
+    fn save_state(&self) -> Result<Vec<u8>, StateError> {
+        state::to_bytes(self)
+    }
+
+    fn read_state(&self, state: &[u8]) -> Result<Box<dyn Any>, StateError> {
+        let mut saved: T = state::from_bytes(state)?;
+        saved.restore_rom(self)?;
+        Ok(Box::new(saved))
+    }
+
+    fn restore_state(&mut self, state: Box<dyn Any>) {
+        *self = *state
+            .downcast()
+            .expect("State should be read by the same mapper");
+    }
+}
+
+/// ROM is left out of a mapper's saved state, as it never changes.
+#[cfg(feature = "serde")]
+pub(crate) trait SavedWithoutRom {
+    /// Copy ROM from `current`, the mapper that this saved state is being loaded into.
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError>;
+}
+
+#[cfg(not(feature = "serde"))]
+pub trait MapperState {}
+
+#[cfg(not(feature = "serde"))]
+impl<T> MapperState for T {}
+
+/// Pattern table memory on the cartridge, which is writable if the cartridge has CHR RAM.
+struct CHRMemory {
+    data: Box<[u8]>,
+    ram_enabled: bool,
+}
+
+// Only CHR RAM is saved, CHR ROM is restored with `CHRMemory::restore_rom`
+#[cfg(feature = "serde")]
+impl serde::Serialize for CHRMemory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ram_enabled.then_some(&self.data).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CHRMemory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ram: Option<Box<[u8]>> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(CHRMemory {
+            ram_enabled: ram.is_some(),
+            data: ram.unwrap_or_default(),
+        })
+    }
+}
+
+impl CHRMemory {
+    fn new(data: Box<[u8]>, ram_enabled: bool) -> Self {
+        // A cartridge without CHR ROM must have CHR RAM instead
+        if data.is_empty() {
+            warn!("Cartridge has no CHR ROM, using 8KB of CHR RAM");
+            return CHRMemory {
+                data: Box::new([0; CHR_RAM_SIZE]),
+                ram_enabled: true,
+            };
+        }
+        CHRMemory { data, ram_enabled }
+    }
+
+    fn read(&self, address: usize) -> u8 {
+        self.data[address % self.data.len()]
+    }
+
+    /// Copy CHR ROM from `current`, the memory that this saved memory is being loaded into.
+    #[cfg(feature = "serde")]
+    fn restore_rom(&mut self, current: &CHRMemory) -> Result<(), StateError> {
+        if self.ram_enabled != current.ram_enabled {
+            return Err(StateError::new("CHR RAM doesn't match the cartridge"));
+        }
+        if !self.ram_enabled {
+            self.data = current.data.clone();
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, address: usize, byte: u8) {
+        if !self.ram_enabled {
+            warn!("Ignoring write to CHR ROM at {:#06x}", address);
+            return;
+        }
+        let len = self.data.len();
+        self.data[address % len] = byte;
+    }
+}
+
+/// Read from a bank of PRG ROM, mirroring the ROM if it isn't large enough.
+fn read_bank(rom: &[u8], bank: u8, bank_size: usize, offset: usize) -> u8 {
+    rom[(bank as usize * bank_size + offset % bank_size) % rom.len()]
+}
+
+/// Describe consecutive windows into PRG ROM starting at `0x8000`, each showing the given bank.
+fn windows(bank_size: usize, banks: &[u8]) -> Vec<(Address, Address, u8)> {
+    banks
+        .iter()
+        .enumerate()
+        .map(|(index, &bank)| {
+            let start = Address::new(0x8000) + (index * bank_size) as u16;
+            let end = start + (bank_size - 1) as u16;
+            (start, end, bank)
+        })
+        .collect()
+}
@@ -0,0 +1,168 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{windows, CHRMemory, Mapper, PRG_RAM_SIZE};
+
+/// Mapper 0, with no bank switching. 16KB of PRG ROM is mirrored to fill the 32KB window.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nrom {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Nrom {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Nrom {
+            prg_rom,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => self.prg_rom[(address.index() - 0x8000) % self.prg_rom.len()],
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => {
+                log::debug!("Ignoring write to PRG ROM at {:?}", address);
+            }
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(address.index())
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        self.chr.write(address.index(), byte);
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        let bank_size = self.prg_rom.len().min(0x8000);
+        windows(bank_size, &vec![0; 0x8000 / bank_size])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Nrom {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nrom_maps_0x6000_through_0x7fff_to_prg_ram() {
+        let mut nrom = nrom(0x8000);
+
+        for value in 0x6000..=0x7fff {
+            nrom.cpu_write(Address::new(value), value as u8);
+            assert_eq!(nrom.cpu_read(Address::new(value)), value as u8);
+            assert_eq!(nrom.prg_ram[value as usize - 0x6000], value as u8);
+        }
+    }
+
+    #[test]
+    fn nrom_maps_0x8000_through_0xffff_to_prg_rom() {
+        let mut nrom = nrom(0x8000);
+
+        for value in 0x8000..=0xffff {
+            assert_eq!(nrom.cpu_read(Address::new(value)), value as u8);
+        }
+    }
+
+    #[test]
+    fn nrom_mirrors_prg_rom_if_not_large_enough() {
+        let mut nrom = nrom(0x4000);
+
+        for value in 0xc000..=0xffff {
+            assert_eq!(nrom.cpu_read(Address::new(value)), value as u8);
+        }
+    }
+
+    #[test]
+    fn nrom_ignores_writes_to_prg_rom() {
+        let mut nrom = nrom(0x8000);
+
+        nrom.cpu_write(Address::new(0x8000), 0xff);
+
+        assert_eq!(nrom.cpu_read(Address::new(0x8000)), 0x00);
+    }
+
+    #[test]
+    fn nrom_maps_0x0000_through_0x1fff_to_chr_rom() {
+        let mut nrom = nrom(0x8000);
+
+        for value in 0x0000..=0x1fff {
+            assert_eq!(nrom.ppu_read(Address::new(value)), (value >> 1) as u8);
+        }
+    }
+
+    #[test]
+    fn nrom_ignores_writes_to_chr_rom() {
+        let mut nrom = nrom(0x8000);
+
+        nrom.ppu_write(Address::new(0x0002), 0xff);
+
+        assert_eq!(nrom.ppu_read(Address::new(0x0002)), 0x01);
+    }
+
+    #[test]
+    fn nrom_without_chr_rom_has_writable_chr_ram() {
+        let mut nrom = Nrom::new(Box::new([0; 0x8000]), Box::new([]), false);
+
+        nrom.ppu_write(Address::new(0x1fff), 0xab);
+
+        assert_eq!(nrom.ppu_read(Address::new(0x1fff)), 0xab);
+        assert_eq!(nrom.chr().len(), 0x2000);
+    }
+
+    #[test]
+    #[should_panic]
+    fn nrom_cannot_write_below_prg_ram() {
+        let mut nrom = nrom(0x8000);
+        nrom.cpu_write(Address::new(0x5000), 10);
+    }
+
+    fn nrom(prg_rom_size: usize) -> Nrom {
+        let prg_rom = (0..prg_rom_size).map(|i| i as u8).collect();
+        let chr_rom = (0..0x2000).map(|i| (i >> 1) as u8).collect();
+        Nrom::new(prg_rom, chr_rom, false)
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Address;
+#[cfg(feature = "serde")]
+use crate::StateError;
+
+#[cfg(feature = "serde")]
+use super::SavedWithoutRom;
+use super::{read_bank, windows, CHRMemory, Mapper, PRG_RAM_SIZE};
+
+/// Mapper 2. Writes to PRG ROM select the bank in the first window, the last bank is fixed.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Uxrom {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    prg_rom: Box<[u8]>,
+    bank_size: usize,
+    bank: u8,
+    last_bank: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr: CHRMemory,
+}
+
+impl Uxrom {
+    pub fn new(prg_rom: Box<[u8]>, chr_rom: Box<[u8]>, chr_ram_enabled: bool) -> Self {
+        Self::with_bank_size(prg_rom, chr_rom, chr_ram_enabled, 0x4000)
+    }
+
+    /// Create a mapper with a different window size, with the remaining space fixed to the last bank.
+    pub fn with_bank_size(
+        prg_rom: Box<[u8]>,
+        chr_rom: Box<[u8]>,
+        chr_ram_enabled: bool,
+        bank_size: usize,
+    ) -> Self {
+        let bank_size = bank_size.min(prg_rom.len());
+        let last_bank = (prg_rom.len() / bank_size - 1) as u8;
+
+        log::info!(
+            "Creating UxROM mapper with PRG ROM of size {} and window of size {}",
+            prg_rom.len(),
+            bank_size
+        );
+
+        Uxrom {
+            prg_rom,
+            bank_size,
+            bank: 0,
+            last_bank,
+            prg_ram: [0; PRG_RAM_SIZE],
+            chr: CHRMemory::new(chr_rom, chr_ram_enabled),
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, address: Address) -> u8 {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000],
+            0x8000..=0xffff => {
+                let offset = address.index() - 0x8000;
+                let bank = if offset < self.bank_size {
+                    self.bank
+                } else {
+                    self.last_bank
+                };
+                read_bank(&self.prg_rom, bank, self.bank_size, offset)
+            }
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn cpu_write(&mut self, address: Address, byte: u8) {
+        match address.index() {
+            0x6000..=0x7fff => self.prg_ram[address.index() - 0x6000] = byte,
+            0x8000..=0xffff => self.bank = byte,
+            _ => {
+                panic!("Out of addressable range: {:?}", address);
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, address: Address) -> u8 {
+        self.chr.read(address.index())
+    }
+
+    fn ppu_write(&mut self, address: Address, byte: u8) {
+        self.chr.write(address.index(), byte);
+    }
+
+    fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn chr(&self) -> &[u8] {
+        &self.chr.data
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn prg_windows(&self) -> Vec<(Address, Address, u8)> {
+        windows(self.bank_size, &[self.bank, self.last_bank])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SavedWithoutRom for Uxrom {
+    fn restore_rom(&mut self, current: &Self) -> Result<(), StateError> {
+        self.prg_rom = current.prg_rom.clone();
+        self.chr.restore_rom(&current.chr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uxrom_switches_first_window_and_fixes_last_bank() {
+        let prg_rom = (0..0x10000).map(|i| (i / 0x4000) as u8).collect();
+        let mut uxrom = Uxrom::new(prg_rom, Box::new([0; 0x2000]), false);
+
+        assert_eq!(uxrom.cpu_read(Address::new(0x8000)), 0);
+        assert_eq!(uxrom.cpu_read(Address::new(0xc000)), 3);
+
+        uxrom.cpu_write(Address::new(0x8000), 2);
+
+        assert_eq!(uxrom.cpu_read(Address::new(0x8000)), 2);
+        assert_eq!(uxrom.cpu_read(Address::new(0xbfff)), 2);
+        assert_eq!(uxrom.cpu_read(Address::new(0xc000)), 3);
+        assert_eq!(uxrom.cpu_read(Address::new(0xffff)), 3);
+    }
+}
@@ -9,14 +9,47 @@ pub trait Memory: Sized {
     /// e.g. when reading from the PPU status register, bit 7 of the register is reset.
     fn read(&mut self, address: Address) -> u8;
     fn write(&mut self, address: Address, byte: u8);
+
+    /// Read `len` bytes starting at `start`, wrapping around at the end of the address space.
+    /// Like [Memory::read], this can trigger side effects for memory-mapped registers.
+    fn read_range(&mut self, start: Address, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|offset| self.read(start + offset as u16))
+            .collect()
+    }
+
+    /// Read without any side effects, or `None` if the address can't be read without them, e.g.
+    /// because it's a memory-mapped register.
+    fn peek(&self, _address: Address) -> Option<u8> {
+        None
+    }
 }
 
 pub struct ArrayMemory([u8; 0x10000]);
 
 impl ArrayMemory {
-    pub fn slice(&self) -> &[u8] {
+    /// Copy `bytes` into memory starting at address 0. Anything past the end of the 64KB address
+    /// space is dropped, and anything not covered is zero.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut memory = ArrayMemory::default();
+        let len = bytes.len().min(memory.0.len());
+        memory.0[..len].copy_from_slice(&bytes[..len]);
+        memory
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
         &self.0
     }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for ArrayMemory {
+    fn from(bytes: Vec<u8>) -> Self {
+        ArrayMemory::from_bytes(&bytes)
+    }
 }
 
 impl Default for ArrayMemory {
@@ -36,6 +69,10 @@ impl Memory for ArrayMemory {
         self.0[address.index()]
     }
 
+    fn peek(&self, address: Address) -> Option<u8> {
+        Some(self.0[address.index()])
+    }
+
     fn write(&mut self, address: Address, byte: u8) {
         self.0[address.index()] = byte;
     }
@@ -49,4 +86,60 @@ impl<'a, T: Memory> Memory for &'a mut T {
     fn write(&mut self, address: Address, byte: u8) {
         T::write(self, address, byte)
     }
+
+    fn peek(&self, address: Address) -> Option<u8> {
+        T::peek(self, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_range_reads_consecutive_bytes() {
+        let mut memory = ArrayMemory::default();
+        for (offset, byte) in [0x12, 0x34, 0x56, 0x78].into_iter().enumerate() {
+            memory.write(Address::new(0x0200 + offset as u16), byte);
+        }
+
+        assert_eq!(
+            memory.read_range(Address::new(0x01FF), 6),
+            [0x00, 0x12, 0x34, 0x56, 0x78, 0x00]
+        );
+    }
+
+    #[test]
+    fn array_memory_can_be_created_from_bytes() {
+        let mut memory = ArrayMemory::from_bytes(&[0x12, 0x34, 0x56]);
+
+        assert_eq!(
+            memory.read_range(Address::new(0x0000), 4),
+            [0x12, 0x34, 0x56, 0]
+        );
+
+        memory.as_mut_slice()[0xFFFF] = 0x78;
+        assert_eq!(memory.as_slice()[0xFFFF], 0x78);
+    }
+
+    #[test]
+    fn array_memory_from_vec_truncates_to_address_space() {
+        let mut bytes = vec![0u8; 0x10001];
+        bytes[0xFFFF] = 0xAB;
+        bytes[0x10000] = 0xCD;
+
+        let mut memory = ArrayMemory::from(bytes);
+
+        assert_eq!(memory.read(Address::new(0xFFFF)), 0xAB);
+        assert_eq!(memory.read(Address::new(0x0000)), 0x00);
+    }
+
+    #[test]
+    fn read_range_wraps_at_end_of_address_space() {
+        let mut memory = ArrayMemory::default();
+        memory.write(Address::new(0xFFFF), 0xAB);
+        memory.write(Address::new(0x0000), 0xCD);
+
+        assert_eq!(memory.read_range(Address::new(0xFFFF), 2), [0xAB, 0xCD]);
+    }
 }
@@ -0,0 +1,120 @@
+//! A description of the CPU address space, including which PRG banks are currently switched in.
+use crate::cartridge::PRG;
+use crate::Address;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MemoryMap {
+    /// Areas of the address space, in order of address.
+    pub areas: Vec<MemoryArea>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MemoryArea {
+    pub start: Address,
+    /// Inclusive end of the area.
+    pub end: Address,
+    pub kind: MemoryAreaKind,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MemoryAreaKind {
+    /// 2KB of internal RAM, mirrored 4 times.
+    InternalRAM,
+    /// The 8 PPU registers, mirrored every 8 bytes.
+    PPURegisters,
+    /// APU registers, OAM DMA and controllers.
+    IORegisters,
+    /// Disabled APU and I/O test registers.
+    APUTestRegisters,
+    /// Cartridge space that isn't mapped to anything.
+    Unmapped,
+    PRGRAM,
+    /// A window into PRG ROM, showing the bank currently switched in.
+    PRGROM {
+        bank: u8,
+    },
+}
+
+impl MemoryMap {
+    pub(crate) fn new(prg: &PRG) -> Self {
+        let mut areas = vec![
+            area(0x0000, 0x1fff, MemoryAreaKind::InternalRAM),
+            area(0x2000, 0x3fff, MemoryAreaKind::PPURegisters),
+            area(0x4000, 0x4017, MemoryAreaKind::IORegisters),
+            area(0x4018, 0x401f, MemoryAreaKind::APUTestRegisters),
+            area(0x4020, 0x5fff, MemoryAreaKind::Unmapped),
+            area(0x6000, 0x7fff, MemoryAreaKind::PRGRAM),
+        ];
+
+        for (start, end, bank) in prg.windows() {
+            areas.push(MemoryArea {
+                start,
+                end,
+                kind: MemoryAreaKind::PRGROM { bank },
+            });
+        }
+
+        MemoryMap { areas }
+    }
+
+    /// Find the area containing the given address.
+    pub fn area(&self, address: Address) -> Option<&MemoryArea> {
+        self.areas
+            .iter()
+            .find(|area| area.start <= address && address <= area.end)
+    }
+}
+
+fn area(start: u16, end: u16, kind: MemoryAreaKind) -> MemoryArea {
+    MemoryArea {
+        start: Address::new(start),
+        end: Address::new(end),
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::MapperKind;
+    use crate::{Cartridge, Memory, NES};
+
+    use super::*;
+
+    #[test]
+    fn memory_map_describes_fixed_areas() {
+        let mut nes = NES::new(uxrom_cartridge(), (), ());
+        let memory_map = nes.memory_map();
+
+        let kind = |address| memory_map.area(Address::new(address)).unwrap().kind;
+        assert_eq!(kind(0x0800), MemoryAreaKind::InternalRAM);
+        assert_eq!(kind(0x2008), MemoryAreaKind::PPURegisters);
+        assert_eq!(kind(0x4016), MemoryAreaKind::IORegisters);
+        assert_eq!(kind(0x4018), MemoryAreaKind::APUTestRegisters);
+        assert_eq!(kind(0x6000), MemoryAreaKind::PRGRAM);
+    }
+
+    #[test]
+    fn memory_map_reports_switched_prg_bank() {
+        let mut nes = NES::new(uxrom_cartridge(), (), ());
+
+        let memory_map = nes.memory_map();
+        let window = memory_map.area(Address::new(0x8000)).unwrap();
+        assert_eq!(window.start, Address::new(0x8000));
+        assert_eq!(window.end, Address::new(0xbfff));
+        assert_eq!(window.kind, MemoryAreaKind::PRGROM { bank: 0 });
+
+        nes.cpu.memory().write(Address::new(0x8000), 2);
+
+        let memory_map = nes.memory_map();
+        let kind = |address| memory_map.area(Address::new(address)).unwrap().kind;
+        assert_eq!(kind(0x8000), MemoryAreaKind::PRGROM { bank: 2 });
+        // The last bank is fixed
+        assert_eq!(kind(0xc000), MemoryAreaKind::PRGROM { bank: 3 });
+    }
+
+    fn uxrom_cartridge() -> Cartridge {
+        let prg_rom = vec![0u8; 0x10000].into_boxed_slice();
+        let chr_rom = Box::new([0u8; 0x2000]);
+        Cartridge::new(prg_rom, chr_rom, false, MapperKind::UxROM)
+    }
+}
@@ -0,0 +1,153 @@
+//! Recording controller input each frame, and playing it back, for reproducible playthroughs.
+//!
+//! A movie is a sequence of 5-byte records, each a little-endian `u32` frame number followed by
+//! the [Buttons] held from that frame onwards. A record is only written when the buttons change.
+use std::io;
+use std::io::{Read, Write};
+
+use crate::Buttons;
+
+const RECORD_SIZE: usize = 5;
+
+/// Writes the buttons held on each frame to a movie.
+pub struct InputRecorder<W> {
+    writer: W,
+    frame: u32,
+    last_buttons: Buttons,
+}
+
+impl<W: Write> InputRecorder<W> {
+    pub fn new(writer: W) -> Self {
+        InputRecorder {
+            writer,
+            frame: 0,
+            last_buttons: Buttons::empty(),
+        }
+    }
+
+    /// Record the buttons held for the next frame. Called once per frame, before running it.
+    pub fn record(&mut self, buttons: Buttons) -> io::Result<()> {
+        if buttons != self.last_buttons {
+            let mut record = [0; RECORD_SIZE];
+            record[..4].copy_from_slice(&self.frame.to_le_bytes());
+            record[4] = buttons.bits();
+            self.writer.write_all(&record)?;
+            self.last_buttons = buttons;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Plays back the buttons from a movie recorded by [InputRecorder].
+pub struct InputPlayer {
+    // Frame numbers and the buttons held from then on, in order
+    changes: Vec<(u32, Buttons)>,
+    next_change: usize,
+    frame: u32,
+    buttons: Buttons,
+}
+
+impl InputPlayer {
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        if data.len() % RECORD_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "movie ends part way through a record",
+            ));
+        }
+
+        let changes = data
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| {
+                let frame = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+                (frame, Buttons::from_bits_retain(record[4]))
+            })
+            .collect();
+
+        Ok(InputPlayer {
+            changes,
+            next_change: 0,
+            frame: 0,
+            buttons: Buttons::empty(),
+        })
+    }
+
+    /// The buttons held for the next frame. Called once per frame, before running it.
+    pub fn next_frame(&mut self) -> Buttons {
+        while let Some(&(frame, buttons)) = self.changes.get(self.next_change) {
+            if frame > self.frame {
+                break;
+            }
+            self.buttons = buttons;
+            self.next_change += 1;
+        }
+        self.frame += 1;
+        self.buttons
+    }
+
+    /// Whether every change in the movie has been played.
+    pub fn is_finished(&self) -> bool {
+        self.next_change >= self.changes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_input_plays_back_the_same() {
+        let frames = [
+            Buttons::empty(),
+            Buttons::A,
+            Buttons::A,
+            Buttons::A | Buttons::RIGHT,
+            Buttons::empty(),
+            Buttons::empty(),
+            Buttons::START,
+        ];
+
+        let mut recorder = InputRecorder::new(vec![]);
+        for buttons in frames {
+            recorder.record(buttons).unwrap();
+        }
+        let movie = recorder.into_inner();
+
+        // Only changes are recorded
+        assert_eq!(movie.len(), 4 * RECORD_SIZE);
+
+        let mut player = InputPlayer::read(movie.as_slice()).unwrap();
+        let played: Vec<Buttons> = frames.iter().map(|_| player.next_frame()).collect();
+
+        assert_eq!(played, frames);
+        assert!(player.is_finished());
+        // The last buttons stay held after the movie ends
+        assert_eq!(player.next_frame(), Buttons::START);
+    }
+
+    #[test]
+    fn movie_is_frame_number_then_buttons() {
+        let mut recorder = InputRecorder::new(vec![]);
+        for _ in 0..0x102 {
+            recorder.record(Buttons::empty()).unwrap();
+        }
+        recorder.record(Buttons::B).unwrap();
+
+        assert_eq!(recorder.into_inner(), [0x02, 0x01, 0x00, 0x00, 0b0100_0000]);
+    }
+
+    #[test]
+    fn truncated_movie_is_an_error() {
+        let result = InputPlayer::read([0u8, 0, 0].as_slice());
+
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
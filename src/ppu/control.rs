@@ -3,6 +3,7 @@ use bitflags::bitflags;
 use crate::Address;
 
 #[derive(Default, Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Control(ControlFlags);
 
 impl Control {
@@ -58,7 +59,7 @@ impl SpriteSize {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum PatternTable {
     Left,
     Right,
@@ -85,6 +86,7 @@ impl From<PatternTable> for Address {
 
 bitflags! {
     #[derive(Default, Copy, Clone, Debug, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     struct ControlFlags: u8 {
         const NMI_ON_VBLANK            = 0b1000_0000;
         const PPU_MASTER_SLAVE         = 0b0100_0000;
@@ -2,6 +2,7 @@ use bitflags::bitflags;
 
 bitflags! {
     #[derive(Default, Copy, Clone, Debug, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Mask: u8 {
         const EMPHASIZE_BLUE       = 0b1000_0000;
         const EMPHASIZE_GREEN      = 0b0100_0000;
@@ -13,3 +14,13 @@ bitflags! {
         const GREYSCALE            = 0b0000_0001;
     }
 }
+
+impl Mask {
+    /// PAL PPUs have the red and green emphasis bits the other way around.
+    pub fn swap_red_green_emphasis(self) -> Self {
+        let mut mask = self - (Mask::EMPHASIZE_RED | Mask::EMPHASIZE_GREEN);
+        mask.set(Mask::EMPHASIZE_RED, self.contains(Mask::EMPHASIZE_GREEN));
+        mask.set(Mask::EMPHASIZE_GREEN, self.contains(Mask::EMPHASIZE_RED));
+        mask
+    }
+}
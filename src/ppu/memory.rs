@@ -7,6 +7,12 @@ use crate::Memory;
 const CHR_END: usize = PALETTE_OFFSET - 1;
 const PALETTE_OFFSET: usize = 0x3f00;
 
+const POWER_ON_PALETTE: [u8; 0x20] = [
+    0x09, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02, 0x0D, 0x08, 0x10, 0x08, 0x24, 0x00, 0x00, 0x04, 0x2C,
+    0x09, 0x01, 0x34, 0x03, 0x00, 0x04, 0x00, 0x14, 0x08, 0x3A, 0x00, 0x02, 0x00, 0x20, 0x2C, 0x08,
+];
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NESPPUMemory<CHR = cartridge::CHR> {
     palette_ram: [u8; 0x20],
     chr: CHR,
@@ -14,21 +20,56 @@ pub struct NESPPUMemory<CHR = cartridge::CHR> {
 
 impl<CHR> NESPPUMemory<CHR> {
     pub fn new(chr: CHR) -> Self {
-        let palette_ram = [
-            0x09, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02, 0x0D, 0x08, 0x10, 0x08, 0x24, 0x00, 0x00,
-            0x04, 0x2C, 0x09, 0x01, 0x34, 0x03, 0x00, 0x04, 0x00, 0x14, 0x08, 0x3A, 0x00, 0x02,
-            0x00, 0x20, 0x2C, 0x08,
-        ];
+        NESPPUMemory {
+            palette_ram: POWER_ON_PALETTE,
+            chr,
+        }
+    }
 
-        NESPPUMemory { palette_ram, chr }
+    /// The palettes at $3F00-$3F1F, with the mirrored sprite colour 0 entries filled in.
+    pub fn palette(&self) -> [u8; 0x20] {
+        let mut palette = [0; 0x20];
+        for (offset, color) in palette.iter_mut().enumerate() {
+            let address = Address::new((PALETTE_OFFSET + offset) as u16);
+            *color = self.palette_ram[self.palette_index(address)];
+        }
+        palette
+    }
+
+    /// Write all the palettes at $3F00-$3F1F. Mirrored entries are written in order, so a sprite
+    /// colour 0 overwrites the background colour it mirrors.
+    pub fn set_palette(&mut self, palette: [u8; 0x20]) {
+        for (offset, color) in palette.into_iter().enumerate() {
+            let address = Address::new((PALETTE_OFFSET + offset) as u16);
+            self.palette_ram[self.palette_index(address)] = color;
+        }
     }
 
     fn palette_index(&self, address: Address) -> usize {
         let index = (address.index() - PALETTE_OFFSET) % 0x0020;
-        let is_unused_colour = index % 0x04 == 0;
-        // unused colours mirror between sprite and background palettes
-        let mask = (is_unused_colour as usize).wrapping_sub(1) | 0b1111;
-        index & mask
+        // Colour 0 of each sprite palette is shared with the background palette, so 0x3f10
+        // mirrors 0x3f00 (the universal background colour), 0x3f14 mirrors 0x3f04, etc.
+        let is_sprite_colour_0 = index & 0x13 == 0x10;
+        if is_sprite_colour_0 {
+            index & 0x0f
+        } else {
+            index
+        }
+    }
+}
+
+impl NESPPUMemory {
+    /// Restore palette and nametable RAM from saved memory.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, saved: NESPPUMemory<cartridge::CHRState>) {
+        self.palette_ram = saved.palette_ram;
+        self.chr.restore(saved.chr);
+    }
+
+    /// Reset palette and nametable RAM to how they are when the NES is powered on.
+    pub fn power_on(&mut self) {
+        self.palette_ram = POWER_ON_PALETTE;
+        self.chr.clear_nametable_ram();
     }
 }
 
@@ -51,6 +92,14 @@ impl<CHR: Memory> Memory for NESPPUMemory<CHR> {
         }
     }
 
+    fn peek(&self, address: Address) -> Option<u8> {
+        match address.index() {
+            0x0000..=CHR_END => self.chr.peek(address),
+            PALETTE_OFFSET..=0x3fff => Some(self.palette_ram[self.palette_index(address)]),
+            _ => None,
+        }
+    }
+
     fn write(&mut self, address: Address, byte: u8) {
         match address.index() {
             0x0000..=CHR_END => self.chr.write(address, byte),
@@ -66,10 +115,25 @@ impl<CHR: Memory> Memory for NESPPUMemory<CHR> {
 
 #[cfg(test)]
 mod tests {
-    use crate::ArrayMemory;
+    use crate::mapper::MapperKind;
+    use crate::{ArrayMemory, Cartridge};
 
     use super::*;
 
+    #[test]
+    fn pattern_table_reads_go_through_cartridge_mapper() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = (0..0x8000).map(|i| (i / 0x2000) as u8).collect();
+        let (mut prg, chr) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::CNROM).split();
+        let mut memory = NESPPUMemory::new(chr);
+
+        assert_eq!(memory.read(Address::new(0x0010)), 0);
+
+        prg.write(Address::new(0x8000), 2);
+
+        assert_eq!(memory.read(Address::new(0x0010)), 2);
+    }
+
     #[test]
     fn can_read_cartridge_space_in_nes_ppu_memory() {
         let mut memory = nes_ppu_memory();
@@ -83,6 +147,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn single_screen_mirroring_routes_all_nametables_to_one_table() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, chr) = Cartridge::new(prg_rom, chr_rom, true, MapperKind::AxROM).split();
+        let mut memory = NESPPUMemory::new(chr);
+
+        memory.write(Address::new(0x2000), 0xa);
+        memory.write(Address::new(0x2400), 0xa);
+
+        // Select nametable B, so writes no longer reach nametable A
+        prg.write(Address::new(0x8000), 0b1_0000);
+        memory.write(Address::new(0x2c05), 0xb);
+
+        for nametable in [0x2000, 0x2400, 0x2800, 0x2c00] {
+            assert_eq!(memory.read(Address::new(nametable + 5)), 0xb);
+        }
+
+        prg.write(Address::new(0x8000), 0b0_0000);
+
+        for nametable in [0x2000, 0x2400, 0x2800, 0x2c00] {
+            assert_eq!(memory.read(Address::new(nametable)), 0xa);
+            assert_eq!(memory.read(Address::new(nametable + 5)), 0x0);
+        }
+    }
+
     #[test]
     fn can_read_palette_ram_in_nes_ppu_memory() {
         let mut memory = nes_ppu_memory();
@@ -137,6 +227,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn palette_ram_does_not_mirror_other_sprite_colours() {
+        let mut memory = nes_ppu_memory();
+
+        for offset in (0x00..0x10).filter(|offset| offset % 4 != 0) {
+            let background_address = Address::new(0x3f00 + offset);
+            let sprite_address = Address::new(0x3f10 + offset);
+
+            memory.write(background_address, 42);
+            memory.write(sprite_address, 24);
+            assert_eq!(memory.read(background_address), 42);
+            assert_eq!(memory.read(sprite_address), 24);
+        }
+    }
+
     fn nes_ppu_memory() -> NESPPUMemory<ArrayMemory> {
         let chr = ArrayMemory::default();
         NESPPUMemory::new(chr)
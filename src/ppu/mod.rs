@@ -1,23 +1,29 @@
 use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
 
 use bitflags::bitflags;
-use control::PatternTable;
+pub use control::PatternTable;
 use control::SpriteSize;
 use log::warn;
 pub use registers::PPURegisters;
 
+use crate::accuracy::AccuracyProfile;
 use crate::Address;
 use crate::Memory;
+use crate::Region;
 
 use self::control::Control;
 use self::mask::Mask;
 pub use self::memory::NESPPUMemory;
+pub use self::palette::Palette;
+use self::palette::DEFAULT_PALETTE;
 use self::scroll::Scroll;
 use self::status::Status;
 
 mod control;
 mod mask;
 mod memory;
+mod palette;
 mod registers;
 mod scroll;
 mod status;
@@ -27,12 +33,36 @@ const SPRITE_PALETTES: Address = Address::new(0x3f10);
 
 const ACTIVE_SPRITES: usize = 8;
 
+const VISIBLE_DOTS: usize = 256;
+
+// The dot on scanline 241 where the vblank NMI is raised, two dots after the flag is set
+const NMI_DOT: u16 = 3;
+
+// The I/O latch fades to 0 if not refreshed for about 600ms
+const IO_LATCH_DECAY_FRAMES: u8 = 36;
+
+/// A copy of the nametables and pattern tables, for viewing tiles while debugging.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VramSnapshot {
+    /// Nametables at $2000, $2400, $2800 and $2C00, after mirroring.
+    pub nametables: [[u8; 0x400]; 4],
+    /// Pattern tables at $0000 and $1000.
+    pub pattern_tables: [[u8; 0x1000]; 2],
+}
+
+/// Called at the end of each visible scanline with the scanline index and that line's colors.
+pub type ScanlineHook = Box<dyn FnMut(u16, &[Color])>;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PPU<M = NESPPUMemory> {
     memory: M,
     read_buffer: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
     object_attribute_memory: [u8; 256],
     scanline: u16,
     cycle_count: u16,
+    // On odd frames the pre-render scanline is one cycle shorter while rendering
+    odd_frame: bool,
     tile_pattern: ShiftRegister,
     palette_select: ShiftRegister,
     active_sprites: [ActiveSprite; ACTIVE_SPRITES],
@@ -49,6 +79,22 @@ pub struct PPU<M = NESPPUMemory> {
     oam_address: u8,
     // Reading vblank just before it's set will prevent it being set and NMI being triggered
     suppress_vblank: bool,
+    // NMI raised by enabling it during vblank, output on the next tick
+    nmi_pending: bool,
+    // The last value written to or read from a register, which is read back from unused bits
+    io_latch: u8,
+    // Frames until the I/O latch decays
+    io_latch_decay: u8,
+    // Colors drawn so far on the current scanline, for the scanline hook
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::array"))]
+    line: [Color; VISIBLE_DOTS],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    scanline_hook: Option<ScanlineHook>,
+    region: Region,
+    accuracy: AccuracyProfile,
+    // Shared with the display, so colors are drawn and sensed by the Zapper the same way
+    #[cfg_attr(feature = "serde", serde(skip))]
+    palette: Rc<Palette>,
 }
 
 impl<M: Memory> PPU<M> {
@@ -59,6 +105,7 @@ impl<M: Memory> PPU<M> {
             object_attribute_memory: [0; 256],
             scanline: 0,
             cycle_count: 0,
+            odd_frame: false,
             tile_pattern: ShiftRegister::default(),
             palette_select: ShiftRegister::default(),
             active_sprites: [ActiveSprite::default(); ACTIVE_SPRITES],
@@ -72,6 +119,14 @@ impl<M: Memory> PPU<M> {
             fine_x: 0,
             oam_address: 0,
             suppress_vblank: false,
+            nmi_pending: false,
+            io_latch: 0,
+            io_latch_decay: 0,
+            line: [Color::from_index(0); VISIBLE_DOTS],
+            scanline_hook: None,
+            region: Region::default(),
+            accuracy: AccuracyProfile::default(),
+            palette: Rc::default(),
         }
     }
 
@@ -123,10 +178,6 @@ impl<M: Memory> PPU<M> {
     }
 
     fn load_sprites(&mut self) {
-        if self.scanline == 0 {
-            return;
-        }
-
         let sprite_size = self.control.sprite_size();
         let table = self.control.sprite_pattern_table();
 
@@ -135,9 +186,12 @@ impl<M: Memory> PPU<M> {
             Sprite::new(chunk[3], chunk[0], chunk[1], attributes)
         });
 
-        let scanline = self.scanline - 1;
+        // Sprites are loaded for the scanline after the one they were evaluated on. No sprites are
+        // evaluated before the first scanline, but patterns are still fetched, which mappers watching
+        // the address bus rely on.
+        let scanline = self.scanline.wrapping_sub(1);
 
-        let sprites_on_scanline = all_sprites.enumerate().filter(|(_, sprite)| {
+        let mut sprites_on_scanline = all_sprites.enumerate().filter(|(_, sprite)| {
             let y = sprite.y as u16;
             scanline >= y && scanline < y + sprite_size.height() as u16
         });
@@ -145,7 +199,11 @@ impl<M: Memory> PPU<M> {
         self.active_sprites = [ActiveSprite::default(); ACTIVE_SPRITES];
         self.active_sprites_has_zero = false;
 
-        for (dest, (i, src)) in self.active_sprites.iter_mut().zip(sprites_on_scanline) {
+        for (dest, (i, src)) in self
+            .active_sprites
+            .iter_mut()
+            .zip(sprites_on_scanline.by_ref())
+        {
             self.active_sprites_has_zero |= i == 0;
             *dest = ActiveSprite {
                 sprite: src,
@@ -153,6 +211,12 @@ impl<M: Memory> PPU<M> {
             };
         }
 
+        // Sprites are only evaluated for visible scanlines while rendering
+        let overflow = sprites_on_scanline.next().is_some();
+        if overflow && scanline < 240 && self.rendering() {
+            self.status |= Status::SPRITE_OVERFLOW;
+        }
+
         for i in 0..ACTIVE_SPRITES {
             let sprite = self.active_sprites[i].sprite;
             let attr = sprite.attributes;
@@ -190,11 +254,27 @@ impl<M: Memory> PPU<M> {
             background
         };
 
-        if self.active_sprites_has_zero && sprite.index == 0 && background_opaque {
+        // Sprite 0 can't hit on the last dot of the scanline. Left column clipping of either the
+        // sprite or the background also prevents a hit, because neither is opaque there.
+        if self.active_sprites_has_zero
+            && sprite.index == 0
+            && background_opaque
+            && self.cycle_count != VISIBLE_DOTS as u16 - 1
+        {
             self.status |= Status::SPRITE_ZERO_HIT;
         }
 
-        Color(self.memory.read(color_address))
+        // Emphasis and greyscale come from PPUMASK as it is on this dot, so they can change mid-frame
+        let mask = match self.region {
+            Region::NTSC => self.mask,
+            Region::PAL => self.mask.swap_red_green_emphasis(),
+        };
+        let color = Color::new(self.memory.read(color_address), mask);
+        if self.mask.contains(Mask::GREYSCALE) {
+            color.greyscale()
+        } else {
+            color
+        }
     }
 
     fn background_color(&self) -> (Address, bool) {
@@ -203,7 +283,8 @@ impl<M: Memory> PPU<M> {
 
         let color_index = (lower_bits | (higher_bits << 2)) as u16;
 
-        let show_background = self.mask.contains(Mask::SHOW_BACKGROUND);
+        let show_background = self.mask.contains(Mask::SHOW_BACKGROUND)
+            && (self.cycle_count >= 8 || self.mask.contains(Mask::SHOW_BACKGROUND_LEFT));
         let opaque = show_background && lower_bits != 0;
 
         // Use universal background colour when transparent
@@ -213,7 +294,9 @@ impl<M: Memory> PPU<M> {
     }
 
     fn sprite_color(&self) -> SelectedSprite {
-        let show_sprites = self.mask.contains(Mask::SHOW_SPRITES) && self.scanline > 0;
+        let show_sprites = self.mask.contains(Mask::SHOW_SPRITES)
+            && (self.cycle_count >= 8 || self.mask.contains(Mask::SHOW_SPRITES_LEFT))
+            && self.scanline > 0;
 
         // Bitflags for which sprites should be shown, to avoid branches
         let mut show: u8 = 0b0000_0000;
@@ -305,32 +388,227 @@ impl<M: Memory> PPU<M> {
         self.palette_select.set_next_bytes(palette0, palette1);
     }
 
+    /// The last scanline of the frame, where the PPU prepares to render the first visible scanline.
+    fn pre_render_scanline(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    fn refresh_io_latch(&mut self, byte: u8) {
+        self.io_latch = byte;
+        self.io_latch_decay = IO_LATCH_DECAY_FRAMES;
+    }
+
+    fn decay_io_latch(&mut self) {
+        // Running fast, the latch holds its value forever
+        if self.accuracy == AccuracyProfile::Fast {
+            return;
+        }
+        if self.io_latch_decay > 0 {
+            self.io_latch_decay -= 1;
+        } else {
+            self.io_latch = 0;
+        }
+    }
+
     fn rendering(&self) -> bool {
         self.mask
             .intersects(Mask::SHOW_BACKGROUND | Mask::SHOW_SPRITES)
     }
 
-    pub fn tick(&mut self) -> PPUOutput {
-        let mut interrupt = false;
-
+    /// Restore registers from a saved PPU, returning the saved memory for the caller to restore.
+    /// The scanline hook, region and palette are kept.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore<N>(&mut self, saved: PPU<N>) -> N {
+        let PPU {
+            memory,
+            read_buffer,
+            object_attribute_memory,
+            scanline,
+            cycle_count,
+            odd_frame,
+            tile_pattern,
+            palette_select,
+            active_sprites,
+            active_sprites_has_zero,
+            control,
+            status,
+            mask,
+            address,
+            temporary_address,
+            write_lower,
+            fine_x,
+            oam_address,
+            suppress_vblank,
+            nmi_pending,
+            io_latch,
+            io_latch_decay,
+            line,
+            scanline_hook: _,
+            region: _,
+            accuracy,
+            palette: _,
+        } = saved;
+        self.read_buffer = read_buffer;
+        self.object_attribute_memory = object_attribute_memory;
+        self.scanline = scanline;
+        self.cycle_count = cycle_count;
+        self.odd_frame = odd_frame;
+        self.tile_pattern = tile_pattern;
+        self.palette_select = palette_select;
+        self.active_sprites = active_sprites;
+        self.active_sprites_has_zero = active_sprites_has_zero;
+        self.control = control;
+        self.status = status;
+        self.mask = mask;
+        self.address = address;
+        self.temporary_address = temporary_address;
+        self.write_lower = write_lower;
+        self.fine_x = fine_x;
+        self.oam_address = oam_address;
+        self.suppress_vblank = suppress_vblank;
+        self.nmi_pending = nmi_pending;
+        self.io_latch = io_latch;
+        self.io_latch_decay = io_latch_decay;
+        self.line = line;
+        self.accuracy = accuracy;
+        memory
+    }
+
+    pub fn memory(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn set_accuracy_profile(&mut self, accuracy: AccuracyProfile) {
+        self.accuracy = accuracy;
+    }
+
+    /// Set the RGB colors that output colors are displayed as.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = Rc::new(palette);
+    }
+
+    pub fn palette(&self) -> &Rc<Palette> {
+        &self.palette
+    }
+
+    pub fn set_scanline_hook(&mut self, hook: impl FnMut(u16, &[Color]) + 'static) {
+        self.scanline_hook = Some(Box::new(hook));
+    }
+
+    /// Reset registers, as when the reset button is pressed. Memory is preserved.
+    pub fn reset(&mut self) {
+        self.control = Control::default();
+        self.mask = Mask::default();
+        self.write_lower = false;
+        self.temporary_address = 0;
+        self.fine_x = 0;
+        self.read_buffer = 0;
+        self.odd_frame = false;
+    }
+
+    /// Reset all state to how it is when the NES is powered on, including OAM.
+    /// The PPU's memory is not affected.
+    pub fn power_on(&mut self) {
+        self.reset();
+        self.object_attribute_memory = [0; 256];
+        self.scanline = 0;
+        self.cycle_count = 0;
+        self.tile_pattern = ShiftRegister::default();
+        self.palette_select = ShiftRegister::default();
+        self.active_sprites = [ActiveSprite::default(); ACTIVE_SPRITES];
+        self.active_sprites_has_zero = false;
+        self.status = Status::default();
+        self.address = 0;
+        self.oam_address = 0;
         self.suppress_vblank = false;
+        self.nmi_pending = false;
+        self.io_latch = 0;
+        self.io_latch_decay = 0;
+    }
+
+    /// The 1KB of tiles and attributes in nametable `index` (0-3), read without affecting the
+    /// PPU's address or read buffer.
+    pub fn nametable(&self, index: u8) -> [u8; 0x400] {
+        assert!(index < 4, "nametable {} out of range", index);
+        self.peek_vram(Address::new(0x2000 + 0x400 * u16::from(index)))
+    }
+
+    /// The 256 tiles in a pattern table, read without affecting the PPU's address or read buffer.
+    /// The cartridge mapper doesn't see these reads, so e.g. MMC3 scanline counting is unaffected.
+    pub fn pattern_table(&self, table: PatternTable) -> [u8; 0x1000] {
+        self.peek_vram(table.into())
+    }
+
+    pub fn vram_snapshot(&self) -> VramSnapshot {
+        VramSnapshot {
+            nametables: [0, 1, 2, 3].map(|index| self.nametable(index)),
+            pattern_tables: [PatternTable::Left, PatternTable::Right]
+                .map(|table| self.pattern_table(table)),
+        }
+    }
+
+    fn peek_vram<const N: usize>(&self, start: Address) -> [u8; N] {
+        std::array::from_fn(|offset| {
+            let address = start + offset as u16;
+            self.memory
+                .peek(address)
+                .unwrap_or_else(|| panic!("VRAM at {:?} can't be peeked", address))
+        })
+    }
+
+    /// The (dot, scanline) the PPU will draw on its next tick. Dots 0-255 of scanlines 0-239
+    /// are visible pixels.
+    pub fn beam_position(&self) -> (u16, u16) {
+        (self.cycle_count, self.scanline)
+    }
+
+    /// Read PPUSTATUS without any of the side-effects of reading the register.
+    pub fn peek_status(&self) -> u8 {
+        self.status.bits()
+    }
+
+    /// Move to a position in the frame without rendering anything, so the next tick runs at
+    /// (`scanline`, `cycle`).
+    #[cfg(test)]
+    pub(crate) fn seek_to(&mut self, scanline: u16, cycle: u16) {
+        let pre_render_scanline = self.pre_render_scanline();
+        assert!(
+            scanline <= pre_render_scanline,
+            "scanline {} out of range",
+            scanline
+        );
+        assert!(cycle <= 340, "cycle {} out of range", cycle);
+        self.scanline = scanline;
+        self.cycle_count = cycle;
+    }
+
+    pub fn tick(&mut self) -> PPUOutput {
+        let mut interrupt = std::mem::take(&mut self.nmi_pending);
 
         let in_bounds = self.scanline < 240 && self.cycle_count < 256;
         let rendering = self.rendering();
+        let pre_render_scanline = self.pre_render_scanline();
 
         match (self.scanline, self.cycle_count) {
             (_, 0) => self.load_sprites(),
-            (241, 1) if !self.suppress_vblank => {
-                // TODO: also suppress NMI the frame after, apparently
-                self.status |= Status::VBLANK;
-
-                if self.control.nmi_on_vblank() {
-                    interrupt = true;
+            (241, 1) => {
+                if !std::mem::take(&mut self.suppress_vblank) {
+                    self.status |= Status::VBLANK;
                 }
+                self.decay_io_latch();
             }
-            (261, 1) => {
+            // NMI follows vblank by a couple of dots, so reading PPUSTATUS in between clears the
+            // flag in time to suppress it
+            (241, NMI_DOT) => {
+                interrupt |= self.status.contains(Status::VBLANK) && self.control.nmi_on_vblank();
+            }
+            (scanline, 1) if scanline == pre_render_scanline => {
                 // TODO: The VBLANK is much too long
-                self.status -= Status::VBLANK | Status::SPRITE_ZERO_HIT;
+                self.status -= Status::VBLANK | Status::SPRITE_ZERO_HIT | Status::SPRITE_OVERFLOW;
                 if rendering {
                     self.set_address(Address::new(self.temporary_address));
                 }
@@ -344,8 +622,8 @@ impl<M: Memory> PPU<M> {
         // A tile is fetched every 8 cycles.
         // The 1st and 2nd tiles are fetched at the of the previous scanline, filling the 16-bit shift registers.
         // The first cycle is idle, so the 3rd tile is fetched at cycle 8.
-        let preparing_next_scanline =
-            (self.scanline < 240 || self.scanline == 261) && self.cycle_count >= 328;
+        let preparing_next_scanline = (self.scanline < 240 || self.scanline == pre_render_scanline)
+            && self.cycle_count >= 328;
         if rendering
             && ((in_bounds && self.cycle_count > 0) || preparing_next_scanline)
             && self.cycle_count % 8 == 0
@@ -356,6 +634,16 @@ impl<M: Memory> PPU<M> {
 
         let color = in_bounds.then(|| self.next_color());
 
+        if let Some(color) = color {
+            let dot = self.cycle_count as usize;
+            self.line[dot] = color;
+            if dot == VISIBLE_DOTS - 1 {
+                if let Some(hook) = &mut self.scanline_hook {
+                    hook(self.scanline, &self.line);
+                }
+            }
+        }
+
         // Don't shift registers in the last 4 bits, or everything goes out of alignment.
         // Oddly, the cycle count in a scanline isn't divisible by 8.
         if self.cycle_count < 336 {
@@ -365,14 +653,22 @@ impl<M: Memory> PPU<M> {
 
         let vblank = self.scanline >= 240;
 
-        if self.cycle_count < 340 {
+        // PAL frames are always the same length
+        let skip_last_cycle = self.region == Region::NTSC
+            && self.odd_frame
+            && rendering
+            && self.scanline == pre_render_scanline
+            && self.cycle_count == 339;
+
+        if self.cycle_count < 340 && !skip_last_cycle {
             self.cycle_count += 1;
         } else {
             self.cycle_count = 0;
-            if self.scanline < 261 {
+            if self.scanline < pre_render_scanline {
                 self.scanline += 1;
             } else {
                 self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
             }
         };
 
@@ -384,6 +680,17 @@ impl<M: Memory> PPU<M> {
     }
 }
 
+impl<CHR> PPU<NESPPUMemory<CHR>> {
+    /// The palettes at $3F00-$3F1F, read without going through PPUDATA.
+    pub fn read_palette(&self) -> [u8; 0x20] {
+        self.memory.palette()
+    }
+
+    pub fn write_palette(&mut self, palette: [u8; 0x20]) {
+        self.memory.set_palette(palette);
+    }
+}
+
 impl<M: Debug> Debug for PPU<M> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PPU")
@@ -414,6 +721,7 @@ pub struct PPUOutput {
 }
 
 #[derive(Default, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ShiftRegister(u16, u16);
 
 impl ShiftRegister {
@@ -446,6 +754,7 @@ struct SelectedSprite {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct ActiveSprite {
     sprite: Sprite,
     pattern0: u8,
@@ -453,6 +762,7 @@ struct ActiveSprite {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Sprite {
     x: u8,
     y: u8,
@@ -479,6 +789,7 @@ impl Default for Sprite {
 
 bitflags! {
     #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     struct SpriteAttributes: u8 {
         const VERTICAL_FLIP   = 0b1000_0000;
         const HORIZONTAL_FLIP = 0b0100_0000;
@@ -505,39 +816,74 @@ fn set_all_bits_to_bit_at_index(byte: u8, index: u8) -> u8 {
 
 impl<M: Memory> PPURegisters for PPU<M> {
     fn write_control(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
+        let nmi_was_enabled = self.control.nmi_on_vblank();
         self.control = Control::from_bits(byte);
 
+        // Enabling NMI while the vblank flag is set triggers an NMI straight away. Before the
+        // vblank NMI dot, the usual NMI is raised instead.
+        let before_vblank_nmi = self.scanline == 241 && self.cycle_count <= NMI_DOT;
+        if !nmi_was_enabled
+            && self.control.nmi_on_vblank()
+            && self.status.contains(Status::VBLANK)
+            && !before_vblank_nmi
+        {
+            self.nmi_pending = true;
+        }
+
         // Set bits of temporary address to nametable
         self.temporary_address &= 0b1111_0011_1111_1111;
         self.temporary_address |= u16::from(self.control.nametable_select()) << 10;
     }
 
     fn write_mask(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         self.mask = Mask::from_bits_truncate(byte);
     }
 
     fn read_status(&mut self) -> u8 {
         self.write_lower = false;
-        self.suppress_vblank = true;
-        let bits = self.status.bits();
+        // Reading just before vblank is set means it won't be set this frame, so there's no NMI
+        if (self.scanline, self.cycle_count) == (241, 1) {
+            self.suppress_vblank = true;
+        }
+        // Only the top 3 bits are driven, the rest come from the I/O latch
+        let bits = self.status.bits() | (self.io_latch & 0b0001_1111);
         self.status.remove(Status::VBLANK);
+        self.refresh_io_latch(bits);
         bits
     }
 
     fn write_oam_address(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         self.oam_address = byte;
     }
 
     fn read_oam_data(&mut self) -> u8 {
-        self.object_attribute_memory[self.oam_address as usize]
+        // While rendering, secondary OAM is cleared at the start of each visible scanline by
+        // reading $FF through OAMDATA. Later reads during sprite evaluation aren't emulated.
+        let clearing_secondary_oam =
+            self.rendering() && self.scanline < 240 && (1..=64).contains(&self.cycle_count);
+        let byte = if clearing_secondary_oam {
+            0xff
+        } else if self.oam_address % 4 == 2 {
+            // Bits 2-4 of sprite attributes don't exist, so always read as 0
+            self.object_attribute_memory[self.oam_address as usize] & 0b1110_0011
+        } else {
+            self.object_attribute_memory[self.oam_address as usize]
+        };
+        self.refresh_io_latch(byte);
+        byte
     }
 
     fn write_oam_data(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         self.object_attribute_memory[self.oam_address as usize] = byte;
         self.oam_address = self.oam_address.wrapping_add(1);
     }
 
     fn write_scroll(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         let fine = byte & 0b111;
         let coarse = (byte & 0b1111_1000) >> 3;
         let mut scroll = Scroll::from_bits_truncate(self.temporary_address);
@@ -555,6 +901,7 @@ impl<M: Memory> PPURegisters for PPU<M> {
     }
 
     fn write_address(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         if self.rendering() {
             // warn!("Attempt to write address to PPU during rendering");
         }
@@ -578,16 +925,22 @@ impl<M: Memory> PPURegisters for PPU<M> {
         let byte = self.memory.read(address);
         self.increment_address();
 
-        if address < BACKGROUND_PALETTES {
+        let value = if address < BACKGROUND_PALETTES {
             let buffer = self.read_buffer;
             self.read_buffer = byte;
             buffer
         } else {
+            // Palette reads return immediately, but the buffer is still filled with the
+            // nametable "underneath" the palette
+            self.read_buffer = self.memory.read(address - 0x1000);
             byte
-        }
+        };
+        self.refresh_io_latch(value);
+        value
     }
 
     fn write_data(&mut self, byte: u8) {
+        self.refresh_io_latch(byte);
         if cfg!(debug_assertions) && self.rendering() {
             // warn!("Attempt to write to PPU during rendering");
         }
@@ -599,94 +952,65 @@ impl<M: Memory> PPURegisters for PPU<M> {
         bytes.rotate_right(self.oam_address as usize);
         self.object_attribute_memory = bytes;
     }
+
+    fn read_io_latch(&mut self) -> u8 {
+        self.io_latch
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub struct Color(u8);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+    index: u8,
+    // Only the emphasis bits of PPUMASK at the time the pixel was output
+    emphasis: Mask,
+}
 
 impl Color {
+    fn new(index: u8, mask: Mask) -> Self {
+        let emphasis = mask & (Mask::EMPHASIZE_RED | Mask::EMPHASIZE_GREEN | Mask::EMPHASIZE_BLUE);
+        Color { index, emphasis }
+    }
+
+    /// A color from the given palette entry (0-63), without emphasis.
+    pub fn from_index(index: u8) -> Self {
+        Color::new(index, Mask::empty())
+    }
+
     pub fn to_byte(&self) -> u8 {
-        self.0
+        self.index
+    }
+
+    /// The palette entry (0-63) for this color, ignoring any emphasis.
+    pub fn index(&self) -> u8 {
+        self.index
     }
 
+    /// The grey from the same row of the palette, as shown when PPUMASK greyscale is enabled.
+    pub fn greyscale(self) -> Self {
+        Color {
+            index: self.index & 0x30,
+            ..self
+        }
+    }
+
+    /// The RGB color in the default palette. Use [Palette::rgb] for any other palette.
     pub fn to_rgb(&self) -> (u8, u8, u8) {
-        COLOR_LOOKUP[self.0 as usize]
+        DEFAULT_PALETTE.rgb(*self)
     }
 }
 
-const COLOR_LOOKUP: [(u8, u8, u8); 64] = [
-    (0x54, 0x54, 0x54),
-    (0x00, 0x1e, 0x74),
-    (0x08, 0x10, 0x90),
-    (0x30, 0x00, 0x88),
-    (0x44, 0x00, 0x64),
-    (0x5c, 0x00, 0x30),
-    (0x54, 0x04, 0x00),
-    (0x3c, 0x18, 0x00),
-    (0x20, 0x2a, 0x00),
-    (0x08, 0x3a, 0x00),
-    (0x00, 0x40, 0x00),
-    (0x00, 0x3c, 0x00),
-    (0x00, 0x32, 0x3c),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-    (0x98, 0x96, 0x98),
-    (0x08, 0x4c, 0xc4),
-    (0x30, 0x32, 0xec),
-    (0x5c, 0x1e, 0xe4),
-    (0x88, 0x14, 0xb0),
-    (0xa0, 0x14, 0x64),
-    (0x98, 0x22, 0x20),
-    (0x78, 0x3c, 0x00),
-    (0x54, 0x5a, 0x00),
-    (0x28, 0x72, 0x00),
-    (0x08, 0x7c, 0x00),
-    (0x00, 0x76, 0x28),
-    (0x00, 0x66, 0x78),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-    (0xec, 0xee, 0xec),
-    (0x4c, 0x9a, 0xec),
-    (0x78, 0x7c, 0xec),
-    (0xb0, 0x62, 0xec),
-    (0xe4, 0x54, 0xec),
-    (0xec, 0x58, 0xb4),
-    (0xec, 0x6a, 0x64),
-    (0xd4, 0x88, 0x20),
-    (0xa0, 0xaa, 0x00),
-    (0x74, 0xc4, 0x00),
-    (0x4c, 0xd0, 0x20),
-    (0x38, 0xcc, 0x6c),
-    (0x38, 0xb4, 0xcc),
-    (0x3c, 0x3c, 0x3c),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-    (0xec, 0xee, 0xec),
-    (0xa8, 0xcc, 0xec),
-    (0xbc, 0xbc, 0xec),
-    (0xd4, 0xb2, 0xec),
-    (0xec, 0xae, 0xec),
-    (0xec, 0xae, 0xd4),
-    (0xec, 0xb4, 0xb0),
-    (0xe4, 0xc4, 0x90),
-    (0xcc, 0xd2, 0x78),
-    (0xb4, 0xde, 0x78),
-    (0xa8, 0xe2, 0x90),
-    (0x98, 0xe2, 0xb4),
-    (0xa0, 0xd6, 0xe4),
-    (0xa0, 0xa2, 0xa0),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x00),
-];
-
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::mapper::MapperKind;
     use crate::mem;
     use crate::ppu::Sprite;
     use crate::Address;
     use crate::ArrayMemory;
+    use crate::Cartridge;
 
     use super::*;
 
@@ -697,6 +1021,72 @@ mod tests {
         let _color: Option<Color> = ppu.tick().color;
     }
 
+    #[test]
+    fn nametable_reads_bytes_without_disturbing_registers() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_address(0x24);
+        ppu.write_address(0x10);
+        ppu.write_data(0xAB);
+        ppu.write_data(0xCD);
+        ppu.read_buffer = 0x12;
+
+        let nametable = ppu.nametable(1);
+
+        assert_eq!(nametable[0x10..0x12], [0xAB, 0xCD]);
+        assert_eq!(ppu.nametable(0), [0; 0x400]);
+        assert_eq!(ppu.address, 0x2412);
+        assert_eq!(ppu.read_buffer, 0x12);
+    }
+
+    #[test]
+    fn pattern_table_reads_tiles_from_selected_table() {
+        let ppu = PPU::with_memory(mem!(0x1000 => { 0x3Cu8, 0x42u8 }));
+
+        assert_eq!(ppu.pattern_table(PatternTable::Left)[..2], [0, 0]);
+        assert_eq!(ppu.pattern_table(PatternTable::Right)[..2], [0x3C, 0x42]);
+    }
+
+    #[test]
+    fn vram_snapshot_does_not_clock_mmc3_irq_counter() {
+        let prg_rom = Box::new([0u8; 0x8000]);
+        let chr_rom = Box::new([0u8; 0x2000]);
+        let (mut prg, chr) = Cartridge::new(prg_rom, chr_rom, false, MapperKind::MMC3).split();
+        let mut ppu = PPU::with_memory(NESPPUMemory::new(chr));
+
+        prg.write(Address::new(0xc000), 1);
+        prg.write(Address::new(0xe001), 0);
+
+        // Reload the counter to 1, leaving A12 low
+        ppu.memory.read(Address::new(0x1000));
+        ppu.memory.read(Address::new(0x0000));
+
+        ppu.vram_snapshot();
+        assert!(!prg.irq());
+
+        // The next real rise is the one that reaches 0
+        ppu.memory.read(Address::new(0x1000));
+        assert!(prg.irq());
+    }
+
+    #[test]
+    fn palette_round_trips_with_sprite_colour_0_mirrored() {
+        let mut ppu = PPU::with_memory(NESPPUMemory::new(ArrayMemory::default()));
+        let palette: [u8; 0x20] = std::array::from_fn(|index| index as u8);
+
+        ppu.write_palette(palette);
+        let read = ppu.read_palette();
+
+        let mut expected = palette;
+        for mirrored in [0x00, 0x04, 0x08, 0x0C] {
+            expected[mirrored] = palette[mirrored + 0x10];
+        }
+        assert_eq!(read, expected);
+
+        ppu.write_address(0x3F);
+        ppu.write_address(0x04);
+        assert_eq!(ppu.read_data(), 0x14);
+    }
+
     #[test]
     fn writing_ppu_control_sets_control() {
         let mut ppu = PPU::with_memory(mem!());
@@ -883,6 +1273,38 @@ mod tests {
         assert_eq!(ppu.read_data(), 0xBB);
     }
 
+    #[test]
+    fn reading_ppu_data_from_nametable_mirror_reads_from_internal_buffer() {
+        let mut ppu = PPU::with_memory(mem! {
+            0x3000 => {
+                0xAA, 0xBB, 0xCC
+            }
+        });
+
+        ppu.write_address(0x30);
+        ppu.write_address(0x00);
+
+        ppu.read_data();
+        assert_eq!(ppu.read_data(), 0xAA);
+        assert_eq!(ppu.read_data(), 0xBB);
+    }
+
+    #[test]
+    fn reading_ppu_data_from_palette_fills_internal_buffer_from_nametable() {
+        let mut ppu = PPU::with_memory(mem! {
+            0x2f00 => { 0x11 }
+            0x3f00 => { 0xAA }
+        });
+
+        ppu.write_address(0x3f);
+        ppu.write_address(0x00);
+        assert_eq!(ppu.read_data(), 0xAA);
+
+        ppu.write_address(0x20);
+        ppu.write_address(0x00);
+        assert_eq!(ppu.read_data(), 0x11);
+    }
+
     #[test]
     fn reading_or_writing_ppu_data_increments_address_by_increment_in_control_register() {
         let mut ppu = PPU::with_memory(mem! {
@@ -963,6 +1385,37 @@ mod tests {
         assert_eq!(ppu.read_oam_data(), 0x43);
     }
 
+    #[test]
+    fn reading_oam_data_masks_unimplemented_attribute_bits() {
+        let mut ppu = PPU::with_memory(mem!());
+
+        for address in 0..4 {
+            ppu.oam_address = address;
+            ppu.write_oam_data(0xff);
+        }
+
+        let bytes: Vec<u8> = (0..4)
+            .map(|address| {
+                ppu.oam_address = address;
+                ppu.read_oam_data()
+            })
+            .collect();
+        assert_eq!(bytes, [0xff, 0xff, 0xe3, 0xff]);
+    }
+
+    #[test]
+    fn reading_oam_data_while_clearing_secondary_oam_reads_0xff() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.object_attribute_memory[0] = 0x42;
+        ppu.write_mask(Mask::SHOW_SPRITES.bits());
+
+        ppu.seek_to(10, 30);
+        assert_eq!(ppu.read_oam_data(), 0xff);
+
+        ppu.seek_to(241, 30);
+        assert_eq!(ppu.read_oam_data(), 0x42);
+    }
+
     #[test]
     fn writing_oam_data_writes_to_oam_address() {
         let mut ppu = PPU::with_memory(mem!());
@@ -1196,6 +1649,51 @@ mod tests {
         assert_eq!(ppu.active_sprites, expected);
     }
 
+    #[test]
+    fn ninth_sprite_on_scanline_sets_sprite_overflow() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_mask(Mask::SHOW_SPRITES.bits());
+
+        for sprite in 0..9 {
+            ppu.object_attribute_memory[sprite * 4] = 20;
+        }
+
+        ppu.scanline = 21;
+        ppu.load_sprites();
+        assert!(ppu.status.contains(Status::SPRITE_OVERFLOW));
+
+        ppu.seek_to(261, 1);
+        ppu.tick();
+        assert!(!ppu.status.contains(Status::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn eight_sprites_on_scanline_do_not_set_sprite_overflow() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_mask(Mask::SHOW_SPRITES.bits());
+
+        for sprite in 0..8 {
+            ppu.object_attribute_memory[sprite * 4] = 20;
+        }
+
+        ppu.scanline = 21;
+        ppu.load_sprites();
+        assert!(!ppu.status.contains(Status::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn sprite_overflow_is_not_set_when_rendering_is_disabled() {
+        let mut ppu = PPU::with_memory(mem!());
+
+        for sprite in 0..9 {
+            ppu.object_attribute_memory[sprite * 4] = 20;
+        }
+
+        ppu.scanline = 21;
+        ppu.load_sprites();
+        assert!(!ppu.status.contains(Status::SPRITE_OVERFLOW));
+    }
+
     #[test]
     fn loading_sprites_clears_active_sprites() {
         let mut ppu = PPU::with_memory(mem!());
@@ -1274,4 +1772,448 @@ mod tests {
             assert_eq!(all.ver_flip(value, SpriteSize::_8x16), 15 - value);
         }
     }
+
+    #[test]
+    fn color_index_ignores_emphasis() {
+        let plain = Color::new(0x21, Mask::empty());
+        let emphasized = Color::new(0x21, Mask::EMPHASIZE_RED | Mask::SHOW_BACKGROUND);
+
+        assert_eq!(plain.index(), 0x21);
+        assert_eq!(plain.index(), emphasized.index());
+        assert_ne!(plain.to_rgb(), emphasized.to_rgb());
+    }
+
+    #[test]
+    fn emphasis_darkens_other_colors() {
+        let (r, g, b) = Color::new(0x20, Mask::empty()).to_rgb();
+        let (er, eg, eb) = Color::new(0x20, Mask::EMPHASIZE_RED).to_rgb();
+
+        assert_eq!(er, r);
+        assert!(eg < g);
+        assert!(eb < b);
+    }
+
+    #[test]
+    fn ticking_at_start_of_vblank_sets_vblank_flag() {
+        let mut ppu = PPU::with_memory(ArrayMemory::default());
+        ppu.seek_to(241, 0);
+        ppu.tick();
+        assert!(!ppu.status.contains(Status::VBLANK));
+
+        ppu.seek_to(241, 1);
+        let output = ppu.tick();
+
+        assert!(ppu.status.contains(Status::VBLANK));
+        assert!(output.vblank);
+    }
+
+    #[test]
+    fn greyscale_color_comes_from_grey_column_of_palette() {
+        let greys = [0x00, 0x10, 0x20, 0x30].map(|index| Color::from_index(index).to_rgb());
+
+        for index in 0..64 {
+            let rgb = Color::from_index(index).greyscale().to_rgb();
+            assert!(greys.contains(&rgb), "Color {:#04x} is not grey", index);
+        }
+
+        let emphasized = Color::new(0x16, Mask::EMPHASIZE_RED).greyscale();
+        assert_eq!(emphasized.index(), 0x10);
+        assert_eq!(emphasized, Color::new(0x10, Mask::EMPHASIZE_RED));
+    }
+
+    #[test]
+    fn writing_ppu_mask_mid_scanline_changes_following_pixels() {
+        let mut ppu = PPU::with_memory(mem!(0x3F00 => { 0x16 }));
+        ppu.write_mask(Mask::SHOW_BACKGROUND.bits());
+        ppu.seek_to(10, 0);
+
+        let before: Vec<Color> = (0..100).filter_map(|_| ppu.tick().color).collect();
+        let mask = Mask::SHOW_BACKGROUND | Mask::EMPHASIZE_BLUE | Mask::GREYSCALE;
+        ppu.write_mask(mask.bits());
+        let after: Vec<Color> = (0..100).filter_map(|_| ppu.tick().color).collect();
+
+        assert!(!before.is_empty());
+        assert!(!after.is_empty());
+        for color in before {
+            assert_eq!(color, Color::new(0x16, Mask::SHOW_BACKGROUND));
+        }
+        for color in after {
+            assert_eq!(color, Color::new(0x10, Mask::EMPHASIZE_BLUE));
+        }
+    }
+
+    #[test]
+    fn odd_frames_are_one_cycle_shorter_when_rendering() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_mask(Mask::SHOW_BACKGROUND.bits());
+
+        assert_eq!(cycles_in_frame(&mut ppu), 89342);
+        assert_eq!(cycles_in_frame(&mut ppu), 89341);
+        assert_eq!(cycles_in_frame(&mut ppu), 89342);
+    }
+
+    #[test]
+    fn odd_frames_are_full_length_when_not_rendering() {
+        let mut ppu = PPU::with_memory(mem!());
+
+        assert_eq!(cycles_in_frame(&mut ppu), 89342);
+        assert_eq!(cycles_in_frame(&mut ppu), 89342);
+    }
+
+    #[test]
+    fn pal_frames_have_312_scanlines_and_are_always_full_length() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.set_region(Region::PAL);
+        ppu.write_mask(Mask::SHOW_BACKGROUND.bits());
+
+        assert_eq!(cycles_in_frame(&mut ppu), 312 * 341);
+        assert_eq!(cycles_in_frame(&mut ppu), 312 * 341);
+    }
+
+    #[test]
+    fn pal_swaps_red_and_green_emphasis() {
+        let mut ppu = PPU::with_memory(mem!(0x3F00 => { 0x16 }));
+        ppu.set_region(Region::PAL);
+        ppu.write_mask((Mask::SHOW_BACKGROUND | Mask::EMPHASIZE_RED).bits());
+
+        ppu.seek_to(10, 0);
+        let color = ppu.tick().color.unwrap();
+
+        assert_eq!(color, Color::new(0x16, Mask::EMPHASIZE_GREEN));
+    }
+
+    #[test]
+    fn unused_bits_of_ppu_status_read_io_latch() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.status = Status::VBLANK;
+
+        ppu.write_control(0b0101_0101);
+
+        assert_eq!(ppu.read_status(), 0b1001_0101);
+        assert_eq!(ppu.read_io_latch(), 0b1001_0101);
+    }
+
+    #[test]
+    fn io_latch_decays_when_not_refreshed() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_mask(0b0000_0110);
+
+        for _ in 0..IO_LATCH_DECAY_FRAMES {
+            ppu.seek_to(241, 1);
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_io_latch(), 0b0000_0110);
+
+        ppu.seek_to(241, 1);
+        ppu.tick();
+        assert_eq!(ppu.read_io_latch(), 0);
+    }
+
+    #[test]
+    fn io_latch_does_not_decay_when_running_fast() {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.set_accuracy_profile(AccuracyProfile::Fast);
+        ppu.write_mask(0b0000_0110);
+
+        for _ in 0..=IO_LATCH_DECAY_FRAMES {
+            ppu.seek_to(241, 1);
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_io_latch(), 0b0000_0110);
+    }
+
+    #[test]
+    fn vblank_nmi_is_raised_shortly_after_vblank_is_set() {
+        let mut ppu = nmi_ppu();
+
+        ppu.tick();
+        assert!(ppu.status.contains(Status::VBLANK));
+
+        assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 1);
+    }
+
+    #[test]
+    fn reading_status_just_before_vblank_suppresses_flag_and_nmi() {
+        let mut ppu = nmi_ppu();
+
+        assert_eq!(ppu.read_status() & 0x80, 0);
+
+        assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 0);
+        assert!(!ppu.status.contains(Status::VBLANK));
+    }
+
+    #[test]
+    fn reading_status_two_dots_before_vblank_does_not_suppress_it() {
+        let mut ppu = nmi_ppu();
+        ppu.seek_to(241, 0);
+
+        assert_eq!(ppu.read_status() & 0x80, 0);
+
+        assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 1);
+        assert!(ppu.status.contains(Status::VBLANK));
+    }
+
+    #[test]
+    fn reading_status_just_after_vblank_is_set_suppresses_nmi() {
+        for dots_after in 1..=2 {
+            let mut ppu = nmi_ppu();
+            for _ in 0..dots_after {
+                ppu.tick();
+            }
+
+            assert_eq!(ppu.read_status() & 0x80, 0x80);
+
+            assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 0);
+        }
+    }
+
+    #[test]
+    fn reading_status_after_nmi_does_not_suppress_it() {
+        let mut ppu = nmi_ppu();
+        let nmis: u8 = (0..3).map(|_| u8::from(ppu.tick().interrupt)).sum();
+
+        assert_eq!(ppu.read_status() & 0x80, 0x80);
+        assert_eq!(nmis, 1);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_nmi() {
+        let mut ppu = nmi_ppu();
+        ppu.write_control(0);
+        assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 0);
+
+        ppu.write_control(0b1000_0000);
+        assert!(ppu.tick().interrupt);
+        assert!(!ppu.tick().interrupt);
+
+        // Only enabling it raises an NMI, not writing while it's already enabled
+        ppu.write_control(0b1000_0000);
+        assert!(!ppu.tick().interrupt);
+
+        // Not once vblank is over
+        ppu.read_status();
+        ppu.write_control(0);
+        ppu.write_control(0b1000_0000);
+        assert!(!ppu.tick().interrupt);
+    }
+
+    #[test]
+    fn disabling_nmi_after_vblank_is_set_suppresses_nmi() {
+        let mut ppu = nmi_ppu();
+        ppu.tick();
+
+        ppu.write_control(0);
+
+        assert_eq!(nmis_until_end_of_vblank_set(&mut ppu), 0);
+        assert!(ppu.status.contains(Status::VBLANK));
+    }
+
+    /// A PPU with NMI enabled, about to set vblank on its next tick.
+    fn nmi_ppu() -> PPU<ArrayMemory> {
+        let mut ppu = PPU::with_memory(mem!());
+        ppu.write_control(0b1000_0000);
+        ppu.seek_to(241, 1);
+        ppu
+    }
+
+    fn nmis_until_end_of_vblank_set(ppu: &mut PPU<ArrayMemory>) -> u8 {
+        let mut nmis = 0;
+        while ppu.beam_position() != (10, 241) {
+            nmis += u8::from(ppu.tick().interrupt);
+        }
+        nmis
+    }
+
+    fn cycles_in_frame(ppu: &mut PPU<ArrayMemory>) -> u32 {
+        let mut cycles = 0;
+        loop {
+            ppu.tick();
+            cycles += 1;
+            if (ppu.scanline, ppu.cycle_count) == (0, 0) {
+                return cycles;
+            }
+        }
+    }
+
+    #[test]
+    fn left_column_is_clipped_unless_enabled_in_mask() {
+        let shown = Mask::SHOW_BACKGROUND | Mask::SHOW_SPRITES;
+        let cases = [
+            (shown, BACKDROP, false),
+            (shown | Mask::SHOW_BACKGROUND_LEFT, BACKGROUND, false),
+            (shown | Mask::SHOW_SPRITES_LEFT, SPRITE, false),
+            (
+                shown | Mask::SHOW_BACKGROUND_LEFT | Mask::SHOW_SPRITES_LEFT,
+                SPRITE,
+                true,
+            ),
+        ];
+
+        for (mask, left_color, sprite_zero_hit) in cases {
+            let mut ppu = clipping_ppu();
+            ppu.write_mask(mask.bits());
+
+            let line = render_scanline(&mut ppu, 10);
+
+            for (x, &color) in line.iter().enumerate() {
+                let expected = if x < 8 { left_color } else { BACKGROUND };
+                assert_eq!(
+                    color,
+                    Color::from_index(expected),
+                    "{:?} at x = {}",
+                    mask,
+                    x
+                );
+            }
+            assert_eq!(
+                ppu.status.contains(Status::SPRITE_ZERO_HIT),
+                sprite_zero_hit
+            );
+        }
+    }
+
+    #[test]
+    fn tall_sprites_draw_lower_tile_on_lower_eight_scanlines() {
+        const TOP: u8 = 0x16;
+        const BOTTOM: u8 = 0x2a;
+
+        for flipped in [false, true] {
+            let mut memory = ArrayMemory::default();
+            // Odd tile indices use the second pattern table for both tiles of the sprite
+            for address in 0x1020..0x1028 {
+                // Top tile uses colour 1
+                memory.write(Address::new(address), 0xff);
+            }
+            for address in 0x1038..0x1040 {
+                // Bottom tile uses colour 2
+                memory.write(Address::new(address), 0xff);
+            }
+            memory.write(Address::new(0x3f00), BACKDROP);
+            memory.write(Address::new(0x3f11), TOP);
+            memory.write(Address::new(0x3f12), BOTTOM);
+
+            let mut ppu = PPU::with_memory(memory);
+            ppu.write_control(0b0010_0000);
+            ppu.write_mask(Mask::SHOW_SPRITES.bits());
+            let attributes = if flipped { 0b1000_0000 } else { 0 };
+            ppu.object_attribute_memory[..4].copy_from_slice(&[19, 0x03, attributes, 16]);
+
+            let column: Vec<u8> = (20..36)
+                .map(|scanline| render_scanline(&mut ppu, scanline)[16].index())
+                .collect();
+
+            let (first, second) = if flipped {
+                (BOTTOM, TOP)
+            } else {
+                (TOP, BOTTOM)
+            };
+            assert_eq!(column[..8], [first; 8], "flipped = {}", flipped);
+            assert_eq!(column[8..], [second; 8], "flipped = {}", flipped);
+            assert_eq!(render_scanline(&mut ppu, 36)[16].index(), BACKDROP);
+        }
+    }
+
+    const BACKDROP: u8 = 0x0f;
+    const BACKGROUND: u8 = 0x16;
+    const SPRITE: u8 = 0x2a;
+
+    #[test]
+    fn sprite_zero_hit_is_set_on_first_overlapping_dot() {
+        let shown = Mask::SHOW_BACKGROUND | Mask::SHOW_SPRITES;
+        let left = Mask::SHOW_BACKGROUND_LEFT | Mask::SHOW_SPRITES_LEFT;
+        let cases = [
+            // Sprite 0 is entirely in the clipped left column
+            (0, shown, None),
+            (0, shown | left, Some(0)),
+            // Partly in the clipped left column
+            (4, shown, Some(8)),
+            (8, shown, Some(8)),
+            (100, shown, Some(100)),
+            (254, shown, Some(254)),
+            // Only overlaps on the last dot, which can't hit
+            (255, shown, None),
+        ];
+
+        for (x, mask, expected) in cases {
+            let mut ppu = clipping_ppu();
+            ppu.object_attribute_memory[3] = x;
+            ppu.write_mask(mask.bits());
+
+            ppu.seek_to(9, 320);
+            let mut hit_dot = None;
+            while hit_dot.is_none() && ppu.scanline < 11 {
+                let (dot, scanline) = ppu.beam_position();
+                ppu.tick();
+                if ppu.status.contains(Status::SPRITE_ZERO_HIT) {
+                    assert_eq!(scanline, 10);
+                    hit_dot = Some(dot);
+                }
+            }
+
+            assert_eq!(hit_dot, expected, "sprite 0 at x = {} with {:?}", x, mask);
+        }
+    }
+
+    /// A PPU with an opaque background everywhere, and an opaque sprite 0 in the left column of
+    /// scanline 10.
+    fn clipping_ppu() -> PPU<ArrayMemory> {
+        let mut memory = ArrayMemory::default();
+        // Tile 0 is opaque, using colour 3 of its palette
+        for address in 0x0000..0x0010 {
+            memory.write(Address::new(address), 0xff);
+        }
+        memory.write(Address::new(0x3f00), BACKDROP);
+        memory.write(Address::new(0x3f03), BACKGROUND);
+        memory.write(Address::new(0x3f13), SPRITE);
+
+        let mut ppu = PPU::with_memory(memory);
+        ppu.object_attribute_memory[..4].copy_from_slice(&[9, 0, 0, 0]);
+        ppu
+    }
+
+    fn render_scanline(ppu: &mut PPU<ArrayMemory>, scanline: u16) -> Vec<Color> {
+        // Start at the end of the previous scanline, where the first tiles are fetched
+        ppu.seek_to(scanline - 1, 320);
+        (0..341)
+            .filter_map(|_| ppu.tick().color)
+            .take(VISIBLE_DOTS)
+            .collect()
+    }
+
+    #[test]
+    fn writing_sprite_palette_colour_0_sets_backdrop() {
+        let mut ppu = PPU::with_memory(NESPPUMemory::new(ArrayMemory::default()));
+        ppu.write_mask(Mask::SHOW_BACKGROUND.bits());
+
+        ppu.write_address(0x3f);
+        ppu.write_address(0x10);
+        ppu.write_data(0x16);
+
+        ppu.seek_to(10, 0);
+        let colors: Vec<Color> = (0..256).filter_map(|_| ppu.tick().color).collect();
+
+        assert!(!colors.is_empty());
+        for color in colors {
+            assert_eq!(color.index(), 0x16);
+        }
+    }
+
+    #[test]
+    fn scanline_hook_is_called_for_each_visible_scanline() {
+        let scanlines = Rc::new(RefCell::new(vec![]));
+
+        let mut ppu = PPU::with_memory(mem!(0x3F00 => { 0x16 }));
+        let hook_scanlines = scanlines.clone();
+        ppu.set_scanline_hook(move |scanline, colors| {
+            assert_eq!(colors, [Color::from_index(0x16); 256]);
+            hook_scanlines.borrow_mut().push(scanline);
+        });
+
+        // Run for a full frame
+        for _ in 0..(262 * 341) {
+            ppu.tick();
+        }
+
+        assert_eq!(*scanlines.borrow(), (0..240).collect::<Vec<u16>>());
+    }
 }
@@ -0,0 +1,228 @@
+//! The RGB colors the PPU's 64 color indices are displayed as.
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::Read;
+use std::sync::LazyLock;
+
+use super::Color;
+
+type Rgb = (u8, u8, u8);
+
+const COLORS: usize = 64;
+// One set of colors for each combination of the three emphasis bits
+const EMPHASIS_SETS: usize = 8;
+
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+/// The default palette, used by [Color::to_rgb] and by displays until given another palette.
+pub(super) static DEFAULT_PALETTE: LazyLock<Palette> =
+    LazyLock::new(|| Palette::from_colors(COLOR_LOOKUP));
+
+/// The RGB color for each of the 64 colors the PPU outputs, and for each combination of the
+/// PPUMASK emphasis bits.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Palette {
+    // Indexed by the emphasis bits of PPUMASK (red, green, blue from lowest), then color index
+    colors: [[Rgb; COLORS]; EMPHASIS_SETS],
+}
+
+impl Palette {
+    /// A palette from 64 colors. Emphasizing a color is approximated by darkening the others.
+    pub fn from_colors(colors: [Rgb; COLORS]) -> Self {
+        let sets = std::array::from_fn(|emphasis| {
+            let red = emphasis & 0b001 != 0;
+            let green = emphasis & 0b010 != 0;
+            let blue = emphasis & 0b100 != 0;
+            colors.map(|(r, g, b)| {
+                (
+                    attenuate(r, green || blue),
+                    attenuate(g, red || blue),
+                    attenuate(b, red || green),
+                )
+            })
+        });
+        Palette { colors: sets }
+    }
+
+    /// Read an FCEUX-style `.pal` file. This is either 64 RGB triples (192 bytes), or 512 (1536
+    /// bytes) with a set of 64 for each combination of the emphasis bits.
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        let triples: Vec<Rgb> = data
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+
+        if data.len() == COLORS * 3 {
+            let colors = std::array::from_fn(|index| triples[index]);
+            Ok(Palette::from_colors(colors))
+        } else if data.len() == COLORS * EMPHASIS_SETS * 3 {
+            let colors = std::array::from_fn(|set| {
+                std::array::from_fn(|index| triples[set * COLORS + index])
+            });
+            Ok(Palette { colors })
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("palette is {} bytes, expected 192 or 1536", data.len()),
+            ))
+        }
+    }
+
+    /// The RGB color that `color` is displayed as, including its emphasis.
+    pub fn rgb(&self, color: Color) -> Rgb {
+        let emphasis = color.emphasis.bits() >> 5;
+        self.colors[usize::from(emphasis)][usize::from(color.index) % COLORS]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        DEFAULT_PALETTE.clone()
+    }
+}
+
+impl Debug for Palette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Palette").finish_non_exhaustive()
+    }
+}
+
+fn attenuate(value: u8, attenuated: bool) -> u8 {
+    if attenuated {
+        (value as f32 * EMPHASIS_ATTENUATION) as u8
+    } else {
+        value
+    }
+}
+
+const COLOR_LOOKUP: [(u8, u8, u8); 64] = [
+    (0x54, 0x54, 0x54),
+    (0x00, 0x1e, 0x74),
+    (0x08, 0x10, 0x90),
+    (0x30, 0x00, 0x88),
+    (0x44, 0x00, 0x64),
+    (0x5c, 0x00, 0x30),
+    (0x54, 0x04, 0x00),
+    (0x3c, 0x18, 0x00),
+    (0x20, 0x2a, 0x00),
+    (0x08, 0x3a, 0x00),
+    (0x00, 0x40, 0x00),
+    (0x00, 0x3c, 0x00),
+    (0x00, 0x32, 0x3c),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x98, 0x96, 0x98),
+    (0x08, 0x4c, 0xc4),
+    (0x30, 0x32, 0xec),
+    (0x5c, 0x1e, 0xe4),
+    (0x88, 0x14, 0xb0),
+    (0xa0, 0x14, 0x64),
+    (0x98, 0x22, 0x20),
+    (0x78, 0x3c, 0x00),
+    (0x54, 0x5a, 0x00),
+    (0x28, 0x72, 0x00),
+    (0x08, 0x7c, 0x00),
+    (0x00, 0x76, 0x28),
+    (0x00, 0x66, 0x78),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xec, 0xee, 0xec),
+    (0x4c, 0x9a, 0xec),
+    (0x78, 0x7c, 0xec),
+    (0xb0, 0x62, 0xec),
+    (0xe4, 0x54, 0xec),
+    (0xec, 0x58, 0xb4),
+    (0xec, 0x6a, 0x64),
+    (0xd4, 0x88, 0x20),
+    (0xa0, 0xaa, 0x00),
+    (0x74, 0xc4, 0x00),
+    (0x4c, 0xd0, 0x20),
+    (0x38, 0xcc, 0x6c),
+    (0x38, 0xb4, 0xcc),
+    (0x3c, 0x3c, 0x3c),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xec, 0xee, 0xec),
+    (0xa8, 0xcc, 0xec),
+    (0xbc, 0xbc, 0xec),
+    (0xd4, 0xb2, 0xec),
+    (0xec, 0xae, 0xec),
+    (0xec, 0xae, 0xd4),
+    (0xec, 0xb4, 0xb0),
+    (0xe4, 0xc4, 0x90),
+    (0xcc, 0xd2, 0x78),
+    (0xb4, 0xde, 0x78),
+    (0xa8, 0xe2, 0x90),
+    (0x98, 0xe2, 0xb4),
+    (0xa0, 0xd6, 0xe4),
+    (0xa0, 0xa2, 0xa0),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::mask::Mask;
+
+    use super::*;
+
+    /// A color with emphasis bits 0-7 as they are in PPUMASK
+    fn color(index: u8, emphasis: u8) -> Color {
+        Color::new(index, Mask::from_bits_truncate(emphasis << 5))
+    }
+
+    #[test]
+    fn reads_64_color_palette() {
+        let mut pal: Vec<u8> = (0..192).map(|byte| byte as u8).collect();
+        pal[0x16 * 3..0x16 * 3 + 3].copy_from_slice(&[0xB5, 0x31, 0x20]);
+
+        let palette = Palette::read(pal.as_slice()).unwrap();
+
+        assert_eq!(palette.rgb(color(0x16, 0)), (0xB5, 0x31, 0x20));
+        assert_eq!(palette.rgb(color(0x00, 0)), (0, 1, 2));
+        assert_eq!(palette.rgb(color(0x3F, 0)), (189, 190, 191));
+        // Emphasis is approximated
+        assert_eq!(palette.rgb(color(0x16, 0b001)), (0xB5, 0x28, 0x1A));
+    }
+
+    #[test]
+    fn reads_palette_with_emphasis_sets() {
+        let pal: Vec<u8> = (0..1536).map(|byte| (byte / 192) as u8).collect();
+
+        let palette = Palette::read(pal.as_slice()).unwrap();
+
+        for emphasis in 0..8 {
+            assert_eq!(
+                palette.rgb(color(0x21, emphasis)),
+                (emphasis, emphasis, emphasis)
+            );
+        }
+    }
+
+    #[test]
+    fn palette_of_wrong_size_is_an_error() {
+        let result = Palette::read([0u8; 100].as_slice());
+
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn default_palette_attenuates_other_colors_when_emphasized() {
+        let palette = Palette::default();
+        let (r, g, b) = palette.rgb(color(0x20, 0));
+
+        assert_eq!(
+            palette.rgb(color(0x20, 0b001)),
+            (r, attenuate(g, true), attenuate(b, true))
+        );
+        assert_eq!(
+            palette.rgb(color(0x20, 0b100)),
+            (attenuate(r, true), attenuate(g, true), b)
+        );
+    }
+}
@@ -20,4 +20,7 @@ pub trait PPURegisters {
     fn write_data(&mut self, byte: u8);
 
     fn write_oam_dma(&mut self, bytes: [u8; 256]);
+
+    /// The value left on the PPU's data bus, returned when reading a write-only register.
+    fn read_io_latch(&mut self) -> u8;
 }
@@ -4,6 +4,7 @@ use crate::Address;
 
 bitflags! {
     #[derive(Copy, Clone)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Scroll: u16 {
         const COARSE_X             = 0b0000_0000_0001_1111;
         const COARSE_Y             = 0b0000_0011_1110_0000;
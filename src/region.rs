@@ -0,0 +1,61 @@
+/// The TV system the NES was built for, which determines its clock speeds.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Region {
+    #[default]
+    NTSC,
+    PAL,
+}
+
+impl Region {
+    pub const fn cpu_frequency_hz(&self) -> f64 {
+        match self {
+            Region::NTSC => 1_789_773.0,
+            Region::PAL => 1_662_607.0,
+        }
+    }
+
+    pub const fn frame_rate(&self) -> f64 {
+        self.cpu_frequency_hz() / self.cpu_cycles_per_frame()
+    }
+
+    /// Number of scanlines in a frame, including vblank and the pre-render scanline.
+    pub const fn scanlines(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL => 312,
+        }
+    }
+
+    /// PPU dots per CPU cycle, in fifths of a dot so PAL's 3.2 dots is a whole number.
+    pub const fn ppu_fifth_dots_per_cpu_cycle(&self) -> u16 {
+        match self {
+            Region::NTSC => 15,
+            Region::PAL => 16,
+        }
+    }
+
+    // The PPU runs 3 dots per CPU cycle on NTSC, and 3.2 dots per CPU cycle on PAL.
+    // On NTSC, every other frame is one dot shorter.
+    const fn cpu_cycles_per_frame(&self) -> f64 {
+        match self {
+            Region::NTSC => 29_780.5,
+            Region::PAL => 33_247.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntsc_cpu_frequency_is_1_789_773_hz() {
+        assert_eq!(Region::NTSC.cpu_frequency_hz(), 1_789_773.0);
+    }
+
+    #[test]
+    fn ntsc_frame_rate_is_roughly_60_fps() {
+        assert!((Region::NTSC.frame_rate() - 60.0988).abs() < 0.001);
+    }
+}
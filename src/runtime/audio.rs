@@ -0,0 +1,98 @@
+//! Audio plumbing shared between runtimes.
+use std::collections::VecDeque;
+
+/// A queue of samples, filled by the emulator and drained by the audio device.
+pub struct SampleQueue {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    last: f32,
+}
+
+impl SampleQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            last: 0.0,
+        }
+    }
+
+    /// Add samples to the queue. If the queue is full, the oldest samples are dropped, so the
+    /// emulator running fast adds as little latency as possible.
+    pub fn extend(&mut self, samples: &[f32]) {
+        self.samples.extend(samples);
+        let overflow = self.samples.len().saturating_sub(self.capacity);
+        self.samples.drain(..overflow);
+    }
+
+    /// Drop all queued samples and output silence until more are added.
+    pub fn silence(&mut self) {
+        self.samples.clear();
+        self.last = 0.0;
+    }
+
+    /// Fill `out` with queued samples. If the queue runs out, the last sample is repeated, which
+    /// avoids audible pops.
+    pub fn drain_into(&mut self, out: &mut [f32]) {
+        for sample in out {
+            if let Some(next) = self.samples.pop_front() {
+                self.last = next;
+            }
+            *sample = self.last;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_queue_drains_in_order() {
+        let mut queue = SampleQueue::new(8);
+        queue.extend(&[0.1, 0.2, 0.3]);
+
+        let mut out = [0.0; 2];
+        queue.drain_into(&mut out);
+
+        assert_eq!(out, [0.1, 0.2]);
+
+        queue.drain_into(&mut out);
+        assert_eq!(out, [0.3, 0.3]);
+    }
+
+    #[test]
+    fn sample_queue_repeats_last_sample_when_empty() {
+        let mut queue = SampleQueue::new(8);
+        queue.extend(&[0.1, 0.2]);
+
+        let mut out = [0.0; 4];
+        queue.drain_into(&mut out);
+
+        assert_eq!(out, [0.1, 0.2, 0.2, 0.2]);
+    }
+
+    #[test]
+    fn silenced_sample_queue_outputs_zero() {
+        let mut queue = SampleQueue::new(8);
+        queue.extend(&[0.1, 0.2]);
+
+        queue.silence();
+        let mut out = [1.0; 2];
+        queue.drain_into(&mut out);
+
+        assert_eq!(out, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn sample_queue_drops_oldest_samples_when_full() {
+        let mut queue = SampleQueue::new(3);
+        queue.extend(&[0.1, 0.2]);
+        queue.extend(&[0.3, 0.4]);
+
+        let mut out = [0.0; 3];
+        queue.drain_into(&mut out);
+
+        assert_eq!(out, [0.2, 0.3, 0.4]);
+    }
+}
@@ -1,5 +1,18 @@
 use std::{error::Error, time::Duration};
 
+use crate::Region;
+
+#[cfg(any(feature = "sdl", test))]
+mod audio;
+
+#[cfg(any(feature = "sdl", test))]
+mod pacing;
+
+mod recorder;
+
+#[cfg(any(feature = "sdl", test))]
+mod save;
+
 #[cfg(feature = "sdl")]
 mod sdl;
 
@@ -12,15 +25,16 @@ pub use web::Web as ActiveRuntime;
 #[cfg(feature = "sdl")]
 pub use sdl::Sdl as ActiveRuntime;
 
+pub use recorder::AudioRecorder;
+
 pub trait Runtime {
     fn init_log(level: log::Level) -> Result<(), Box<dyn Error>>;
     fn run() -> Result<(), Box<dyn Error>>;
 }
 
-const FPS: u64 = 60;
-const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / FPS);
-// True frequency is 1789773Hz, but tuned to match my emulator's rate
-const NES_AUDIO_FREQ: f64 = 1_866_000.0;
+const FRAME_DURATION: Duration =
+    Duration::from_nanos((1_000_000_000.0 / Region::NTSC.frame_rate()) as u64);
+#[cfg(any(feature = "sdl", feature = "web"))]
 const TARGET_AUDIO_FREQ: i32 = 44100;
 
 // No-op runtime when one isn't configured
@@ -51,7 +65,7 @@ impl Runtime for () {
 
         let mut nes = NES::new(cartridge, (), ());
         // TODO: maybe execute indefinitely?
-        nes.tick();
+        nes.try_tick()?;
         Ok(())
     }
 }
@@ -0,0 +1,236 @@
+//! Holding the emulator to the NES frame rate, or running faster while fast-forwarding.
+use std::time::{Duration, Instant};
+
+use crate::{CpuError, NESDisplay, NESSpeaker, NES};
+
+/// Number of NES frames run for each frame shown while fast-forwarding.
+pub const FAST_FORWARD_SPEED: u32 = 4;
+
+/// Slowest and fastest speed multipliers accepted by [FramePacer::set_speed].
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 4.0;
+
+pub struct FramePacer {
+    frame_duration: Duration,
+    // Multiplier on the emulation speed, where 1.0 is the NES frame rate
+    speed: f32,
+    start_of_frame: Instant,
+    // Frames run since the last one shown while fast-forwarding
+    frames_since_present: u32,
+}
+
+impl FramePacer {
+    pub fn new(frame_duration: Duration, now: Instant) -> Self {
+        Self {
+            frame_duration,
+            speed: 1.0,
+            start_of_frame: now,
+            frames_since_present: 0,
+        }
+    }
+
+    /// Whether the frame just completed should be shown. While fast-forwarding, only every
+    /// [FAST_FORWARD_SPEED]th frame is shown.
+    pub fn present(&mut self, fast_forward: bool) -> bool {
+        if !fast_forward {
+            self.frames_since_present = 0;
+            return true;
+        }
+
+        self.frames_since_present += 1;
+        if self.frames_since_present >= FAST_FORWARD_SPEED {
+            self.frames_since_present = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Run at `speed` times the NES frame rate, between [MIN_SPEED] and [MAX_SPEED].
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    // Time each frame should take at the current speed
+    fn scaled_frame_duration(&self) -> Duration {
+        let nanos = self.frame_duration.as_nanos() as f64 / f64::from(self.speed);
+        Duration::from_nanos(nanos.round() as u64)
+    }
+
+    /// How long to sleep so frames are evenly spaced. Never sleeps while fast-forwarding.
+    pub fn sleep_time(&mut self, now: Instant, fast_forward: bool) -> Option<Duration> {
+        if fast_forward {
+            self.start_of_frame = now;
+            return None;
+        }
+
+        let frame_duration = self.scaled_frame_duration();
+        let elapsed = now.duration_since(self.start_of_frame);
+        if let Some(time_to_sleep) = frame_duration.checked_sub(elapsed) {
+            self.start_of_frame = now + time_to_sleep;
+            Some(time_to_sleep)
+        } else {
+            // We're running behind, sleep less next time
+            self.start_of_frame = now - (elapsed - frame_duration);
+            None
+        }
+    }
+}
+
+/// Double or halve `speed`, staying between [MIN_SPEED] and [MAX_SPEED].
+pub fn step_speed(speed: f32, faster: bool) -> f32 {
+    let speed = if faster { speed * 2.0 } else { speed / 2.0 };
+    speed.clamp(MIN_SPEED, MAX_SPEED)
+}
+
+/// The rate the NES should produce samples at so that, running at `speed`, the audio device
+/// still receives `hz` samples per second. Nothing compensates for the change in pitch, so
+/// slow-motion sounds lower and turbo sounds higher.
+pub fn sample_rate_for_speed(hz: u32, speed: f32) -> u32 {
+    (hz as f32 / speed).round() as u32
+}
+
+/// Run the NES for a frame, unless paused. Returns whether it was run, or the error that stopped
+/// the CPU part way through the frame.
+pub fn run_frame(
+    nes: &mut NES<impl NESDisplay, impl NESSpeaker>,
+    paused: bool,
+) -> Result<bool, CpuError> {
+    if !paused {
+        while !nes.try_tick()?.frame_complete {}
+    }
+    Ok(!paused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::nes_with_program;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn sleeps_for_remainder_of_frame() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+
+        let sleep = pacer.sleep_time(start + Duration::from_millis(10), false);
+
+        assert_eq!(sleep, Some(Duration::from_millis(6)));
+    }
+
+    #[test]
+    fn sleeps_less_after_running_behind() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+
+        assert_eq!(
+            pacer.sleep_time(start + Duration::from_millis(20), false),
+            None
+        );
+        let sleep = pacer.sleep_time(start + Duration::from_millis(30), false);
+
+        assert_eq!(sleep, Some(Duration::from_millis(2)));
+    }
+
+    #[test]
+    fn sleep_is_skipped_while_fast_forwarding() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+
+        let sleep = pacer.sleep_time(start + Duration::from_millis(1), true);
+        assert_eq!(sleep, None);
+
+        // Normal pacing resumes from the last fast-forwarded frame
+        let sleep = pacer.sleep_time(start + Duration::from_millis(11), false);
+        assert_eq!(sleep, Some(Duration::from_millis(6)));
+    }
+
+    #[test]
+    fn sleep_time_scales_with_speed() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+        pacer.set_speed(2.0);
+
+        let sleep = pacer.sleep_time(start + Duration::from_millis(2), false);
+        assert_eq!(sleep, Some(Duration::from_millis(6)));
+
+        pacer.set_speed(0.5);
+        let sleep = pacer.sleep_time(start + Duration::from_millis(10), false);
+        assert_eq!(sleep, Some(Duration::from_millis(30)));
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        // At 4x and 0.25x, a 16ms frame takes 4ms and 64ms
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+        pacer.set_speed(100.0);
+        assert_eq!(
+            pacer.sleep_time(start, false),
+            Some(Duration::from_millis(4))
+        );
+
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(FRAME, start);
+        pacer.set_speed(0.0);
+        assert_eq!(
+            pacer.sleep_time(start, false),
+            Some(Duration::from_millis(64))
+        );
+
+        assert_eq!(step_speed(1.0, true), 2.0);
+        assert_eq!(step_speed(1.0, false), 0.5);
+        assert_eq!(step_speed(MAX_SPEED, true), MAX_SPEED);
+        assert_eq!(step_speed(MIN_SPEED, false), MIN_SPEED);
+    }
+
+    #[test]
+    fn sample_rate_scales_inversely_with_speed() {
+        assert_eq!(sample_rate_for_speed(44100, 1.0), 44100);
+        assert_eq!(sample_rate_for_speed(44100, 2.0), 22050);
+        assert_eq!(sample_rate_for_speed(44100, 0.25), 176400);
+    }
+
+    #[test]
+    fn only_some_frames_are_presented_while_fast_forwarding() {
+        let mut pacer = FramePacer::new(FRAME, Instant::now());
+
+        assert!(pacer.present(false));
+        assert!(pacer.present(false));
+
+        let presented: Vec<bool> = (0..8).map(|_| pacer.present(true)).collect();
+        assert_eq!(
+            presented,
+            [false, false, false, true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn frames_are_not_run_while_paused() {
+        // JMP $8000
+        let mut nes = nes_with_program(&[0x4C, 0x00, 0x80]);
+
+        assert_eq!(run_frame(&mut nes, false), Ok(true));
+        let instructions = nes.instruction_count();
+
+        assert_eq!(run_frame(&mut nes, true), Ok(false));
+        assert_eq!(run_frame(&mut nes, true), Ok(false));
+        assert_eq!(nes.frame_count(), 1);
+        assert_eq!(nes.instruction_count(), instructions);
+
+        assert_eq!(run_frame(&mut nes, false), Ok(true));
+        assert_eq!(nes.frame_count(), 2);
+    }
+
+    #[test]
+    fn running_frame_stops_at_unknown_opcode() {
+        // NOP; XAA #$00
+        let mut nes = nes_with_program(&[0xEA, 0x8B, 0x00]);
+
+        assert!(run_frame(&mut nes, false).is_err());
+        assert_eq!(nes.frame_count(), 0);
+        assert_eq!(run_frame(&mut nes, true), Ok(false));
+    }
+}
@@ -0,0 +1,180 @@
+//! Recording the audio output to a WAV file.
+use std::io::{self, Write};
+
+use crate::NESSpeaker;
+
+/// A speaker that records every sample it's given, to be written out as a mono 16-bit WAV file
+/// with [AudioRecorder::finish]. Samples are passed on to the wrapped speaker, so playback
+/// continues while recording.
+///
+/// The WAV file is played back at the sample rate the recorder is created with, which should be
+/// the rate given to [crate::NES::set_sample_rate].
+pub struct AudioRecorder<S = ()> {
+    speaker: S,
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl AudioRecorder {
+    /// Record samples produced at `sample_rate` samples per second.
+    pub fn new(sample_rate: u32) -> Self {
+        Self::wrapping((), sample_rate)
+    }
+}
+
+impl<S: NESSpeaker> AudioRecorder<S> {
+    /// Record samples produced at `sample_rate` samples per second, while also passing them on
+    /// to `speaker`.
+    pub fn wrapping(speaker: S, sample_rate: u32) -> Self {
+        Self {
+            speaker,
+            samples: vec![],
+            sample_rate,
+        }
+    }
+
+    /// The samples recorded so far.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn speaker(&mut self) -> &mut S {
+        &mut self.speaker
+    }
+
+    /// Write the recording as a WAV file, returning the wrapped speaker.
+    pub fn finish(self, mut writer: impl Write) -> io::Result<S> {
+        const CHANNELS: u16 = 1;
+        const BITS_PER_SAMPLE: u16 = 16;
+        const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        let data_size = u32::try_from(self.samples.len() * usize::from(BLOCK_ALIGN))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "recording too long"))?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        let byte_rate = self.sample_rate * u32::from(BLOCK_ALIGN);
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        writer.flush()?;
+        Ok(self.speaker)
+    }
+}
+
+impl<S: NESSpeaker> NESSpeaker for AudioRecorder<S> {
+    fn emit(&mut self, wave: f32) {
+        self.samples.push(wave);
+        self.speaker.emit(wave);
+    }
+
+    fn emit_stereo(&mut self, left: f32, right: f32) {
+        // The recording is mono, but the wrapped speaker still gets both sides
+        self.samples.push((left + right) / 2.0);
+        self.speaker.emit_stereo(left, right);
+    }
+
+    fn end_frame(&mut self) {
+        self.speaker.end_frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_writes_wav_header_and_samples() {
+        let mut recorder = AudioRecorder::new(44100);
+        let wave = [0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5];
+        for _ in 0..10 {
+            for sample in wave {
+                recorder.emit(sample);
+            }
+        }
+
+        let mut wav = vec![];
+        recorder.finish(&mut wav).unwrap();
+
+        let u16_at = |i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(4) as usize, wav.len() - 8);
+        assert_eq!(&wav[8..12], b"WAVE");
+        assert_eq!(&wav[12..16], b"fmt ");
+        assert_eq!(u16_at(20), 1);
+        assert_eq!(u16_at(22), 1);
+        assert_eq!(u32_at(24), 44100);
+        assert_eq!(u32_at(28), 88200);
+        assert_eq!(u16_at(32), 2);
+        assert_eq!(u16_at(34), 16);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(40), 160);
+        assert_eq!(wav.len(), 44 + 160);
+
+        let samples: Vec<i16> = wav[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(
+            &samples[..8],
+            [0, 16383, 32767, 16383, 0, -16383, -32767, -16383]
+        );
+    }
+
+    #[test]
+    fn wav_header_has_given_sample_rate() {
+        let mut wav = vec![];
+        AudioRecorder::new(48000).finish(&mut wav).unwrap();
+
+        let u32_at = |i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(24), 48000);
+        assert_eq!(u32_at(28), 96000);
+    }
+
+    #[test]
+    fn recorder_passes_samples_to_wrapped_speaker() {
+        #[derive(Default)]
+        struct Speaker {
+            samples: Vec<f32>,
+            frames: usize,
+        }
+
+        impl NESSpeaker for Speaker {
+            fn emit(&mut self, wave: f32) {
+                self.samples.push(wave);
+            }
+
+            fn end_frame(&mut self) {
+                self.frames += 1;
+            }
+        }
+
+        let mut recorder = AudioRecorder::wrapping(Speaker::default(), 44100);
+        recorder.emit(0.25);
+        recorder.emit_stereo(0.0, 1.0);
+        recorder.end_frame();
+        assert_eq!(recorder.samples(), [0.25, 0.5]);
+
+        let speaker = recorder.finish(io::sink()).unwrap();
+        assert_eq!(speaker.samples, [0.25, 0.5]);
+        assert_eq!(speaker.frames, 1);
+    }
+}
@@ -0,0 +1,97 @@
+//! Persisting battery-backed PRG RAM to a `.sav` file next to the ROM.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+pub struct SaveFile {
+    path: PathBuf,
+}
+
+impl SaveFile {
+    /// The save file for the given ROM, e.g. `game.sav` for `game.nes`.
+    pub fn for_rom(rom_path: impl AsRef<Path>) -> Self {
+        Self {
+            path: rom_path.as_ref().with_extension("sav"),
+        }
+    }
+
+    /// Load saved RAM, if the save file exists.
+    pub fn load(&self, ram: &mut [u8]) -> io::Result<()> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        if data.len() != ram.len() {
+            warn!(
+                "Ignoring save file {} of size {}, expected {}",
+                self.path.display(),
+                data.len(),
+                ram.len()
+            );
+            return Ok(());
+        }
+
+        info!("Loading save file {}", self.path.display());
+        ram.copy_from_slice(&data);
+        Ok(())
+    }
+
+    pub fn save(&self, ram: &[u8]) -> io::Result<()> {
+        fs::write(&self.path, ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_file_is_next_to_rom() {
+        let save_file = SaveFile::for_rom("roms/game.nes");
+
+        assert_eq!(save_file.path, Path::new("roms/game.sav"));
+    }
+
+    #[test]
+    fn can_save_and_load_ram() {
+        let save_file = SaveFile::for_rom(temp_rom_path("save_and_load"));
+
+        save_file.save(&[1, 2, 3, 4]).unwrap();
+
+        let mut ram = [0; 4];
+        save_file.load(&mut ram).unwrap();
+        assert_eq!(ram, [1, 2, 3, 4]);
+
+        fs::remove_file(&save_file.path).unwrap();
+    }
+
+    #[test]
+    fn loading_missing_save_file_leaves_ram_unchanged() {
+        let save_file = SaveFile::for_rom(temp_rom_path("missing"));
+
+        let mut ram = [5; 4];
+        save_file.load(&mut ram).unwrap();
+
+        assert_eq!(ram, [5; 4]);
+    }
+
+    #[test]
+    fn loading_save_file_of_wrong_size_leaves_ram_unchanged() {
+        let save_file = SaveFile::for_rom(temp_rom_path("wrong_size"));
+        save_file.save(&[1, 2]).unwrap();
+
+        let mut ram = [5; 4];
+        save_file.load(&mut ram).unwrap();
+
+        assert_eq!(ram, [5; 4]);
+        fs::remove_file(&save_file.path).unwrap();
+    }
+
+    fn temp_rom_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nes-rust-test-{}-{}.nes", name, std::process::id()))
+    }
+}
@@ -1,32 +1,45 @@
+use std::cell::Cell;
 use std::error::Error;
 use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
 
-use log::info;
+use log::{error, info};
 use sdl2::audio::AudioCallback;
 use sdl2::audio::AudioDevice;
 use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::render::WindowCanvas;
 use sdl2::render::{Texture, TextureCreator};
 use sdl2::video::WindowContext;
 
 use crate::INes;
+use crate::InputPlayer;
+use crate::InputRecorder;
 use crate::NESDisplay;
 use crate::NESSpeaker;
 use crate::NES;
-use crate::{Buttons, Color, HEIGHT, WIDTH};
+use crate::{Buttons, Color, Palette, Zapper, HEIGHT, WIDTH};
 
+use super::audio::SampleQueue;
+use super::pacing;
+use super::pacing::FramePacer;
+use super::save::SaveFile;
 use super::Runtime;
 use super::FRAME_DURATION;
-use super::NES_AUDIO_FREQ;
 use super::TARGET_AUDIO_FREQ;
 
 const SCALE: u16 = 3;
+// Save battery-backed RAM every 10 seconds, in case the emulator doesn't exit cleanly
+const SAVE_INTERVAL_FRAMES: u32 = 600;
 
 pub struct Sdl;
 
@@ -69,9 +82,20 @@ impl Runtime for Sdl {
         canvas.clear();
         canvas.present();
 
+        // Held down to run as fast as possible, without sound
+        let fast_forward = Rc::new(Cell::new(false));
+        // Changed with +/- to run in slow-motion or turbo
+        let speed = Rc::new(Cell::new(1.0));
+
         let texture_creator = canvas.texture_creator();
-        let display = SDLDisplay::new(&texture_creator, canvas);
-        let speaker = SDLSpeaker::new(&sdl_context)?;
+        let display = SDLDisplay::new(
+            &texture_creator,
+            canvas,
+            fast_forward.clone(),
+            speed.clone(),
+        );
+        let speaker = SDLSpeaker::new(&sdl_context, fast_forward.clone())?;
+        let audio_queue = speaker.queue.clone();
 
         let args: Vec<String> = std::env::args().collect();
 
@@ -86,19 +110,171 @@ impl Runtime for Sdl {
 
         let cartridge = ines.into_cartridge();
 
+        // Only ROMs read from a file have somewhere to put a save file
+        let save_file = match args.get(1) {
+            Some(filename) if cartridge.has_battery() => Some(SaveFile::for_rom(filename)),
+            _ => None,
+        };
+
         let mut nes = NES::new(cartridge, display, speaker);
+        nes.set_sample_rate(TARGET_AUDIO_FREQ as u32);
+
+        if let Some(save_file) = &save_file {
+            save_file.load(&mut nes.prg_ram())?;
+        }
+
+        // Input can be recorded to or played back from a movie, e.g. `nes game.nes --record run.mov`
+        let mut recorder = None;
+        let mut player = None;
+        match (args.get(2).map(String::as_str), args.get(3)) {
+            (Some("--record"), Some(path)) => {
+                recorder = Some(InputRecorder::new(BufWriter::new(File::create(path)?)));
+            }
+            (Some("--play"), Some(path)) => {
+                player = Some(InputPlayer::read(File::open(path)?)?);
+            }
+            _ => {}
+        }
+
+        let mut frames_since_save = 0;
+        let mut paused = false;
 
         loop {
-            // Arbitrary number of ticks so we don't poll events too much
-            for _ in 1..1000 {
-                nes.tick();
+            if !paused {
+                if let Some(player) = &mut player {
+                    nes.set_controller_state(0, player.next_frame());
+                }
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(nes.controller().buttons())?;
+                }
+            }
+
+            // Poll events once per frame. While paused, the last frame stays on screen.
+            match pacing::run_frame(&mut nes, paused) {
+                Ok(true) => {}
+                Ok(false) => std::thread::sleep(FRAME_DURATION),
+                Err(err) => {
+                    // Pause rather than crash, so the game can still be reset or the window closed
+                    error!("Pausing: {}", err);
+                    paused = true;
+                    audio_queue.lock().unwrap().silence();
+                }
+            }
+
+            if let Some(save_file) = &save_file {
+                frames_since_save += 1;
+                if frames_since_save >= SAVE_INTERVAL_FRAMES {
+                    save_file.save(&nes.prg_ram())?;
+                    frames_since_save = 0;
+                }
             }
 
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit { .. } => {
+                        if let Some(save_file) = &save_file {
+                            save_file.save(&nes.prg_ram())?;
+                        }
+                        if let Some(recorder) = recorder.take() {
+                            recorder.into_inner().flush()?;
+                        }
                         return Ok(());
                     }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::R),
+                        repeat: false,
+                        ..
+                    } => {
+                        nes.soft_reset();
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::P),
+                        repeat: false,
+                        ..
+                    } => {
+                        paused = !paused;
+                        if paused {
+                            audio_queue.lock().unwrap().silence();
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::G),
+                        repeat: false,
+                        ..
+                    } => {
+                        // Swap the second controller for a Zapper aimed with the mouse, or back
+                        let connect = nes.zapper().is_none();
+                        nes.set_zapper_connected(connect);
+                        info!(
+                            "Zapper {}",
+                            if connect { "connected" } else { "disconnected" }
+                        );
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        repeat: false,
+                        ..
+                    } => {
+                        save_screenshot(nes.display())?;
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Tab),
+                        ..
+                    } => {
+                        fast_forward.set(true);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus),
+                        repeat: false,
+                        ..
+                    } => {
+                        set_speed(&mut nes, &speed, pacing::step_speed(speed.get(), true));
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Minus | Keycode::KpMinus),
+                        repeat: false,
+                        ..
+                    } => {
+                        set_speed(&mut nes, &speed, pacing::step_speed(speed.get(), false));
+                    }
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Tab),
+                        ..
+                    } => {
+                        fast_forward.set(false);
+                    }
+                    Event::MouseButtonDown {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
+                        ..
+                    } => {
+                        if let Some(zapper) = nes.zapper() {
+                            aim_zapper(zapper, x, y);
+                            zapper.set_trigger(true);
+                        }
+                    }
+                    Event::MouseButtonUp {
+                        mouse_btn: MouseButton::Left,
+                        ..
+                    } => {
+                        if let Some(zapper) = nes.zapper() {
+                            zapper.set_trigger(false);
+                        }
+                    }
+                    Event::MouseMotion { x, y, .. } => {
+                        if let Some(zapper) = nes.zapper() {
+                            aim_zapper(zapper, x, y);
+                        }
+                    }
+                    Event::Window {
+                        win_event: WindowEvent::Leave,
+                        ..
+                    } => {
+                        if let Some(zapper) = nes.zapper() {
+                            zapper.aim_off_screen();
+                        }
+                    }
                     Event::KeyDown {
                         keycode: Some(keycode),
                         ..
@@ -118,6 +294,32 @@ impl Runtime for Sdl {
     }
 }
 
+// Run at `value` times the normal speed. The sample rate is scaled so the audio device is fed at
+// the same rate, which shifts the pitch along with the speed.
+fn set_speed(nes: &mut NES<SDLDisplay, SDLSpeaker>, speed: &Cell<f32>, value: f32) {
+    speed.set(value);
+    nes.set_sample_rate(pacing::sample_rate_for_speed(
+        TARGET_AUDIO_FREQ as u32,
+        value,
+    ));
+    info!("Speed: {}x", value);
+}
+
+// Point the Zapper at the pixel under the mouse, given in window coordinates
+fn aim_zapper(zapper: &mut Zapper, x: i32, y: i32) {
+    let scale = i32::from(SCALE);
+    zapper.aim((x / scale) as u16, (y / scale) as u16);
+}
+
+// Save a PNG of the screen to the working directory, named by the current time
+fn save_screenshot(display: &SDLDisplay) -> Result<(), Box<dyn Error>> {
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let filename = format!("screenshot-{}.png", timestamp.as_millis());
+    std::fs::write(&filename, display.to_png())?;
+    info!("Saved screenshot to {}", filename);
+    Ok(())
+}
+
 fn keycode_binding(keycode: Keycode) -> Buttons {
     match keycode {
         Keycode::Z | Keycode::A => Buttons::A,
@@ -138,13 +340,21 @@ struct SDLDisplay<'r> {
     buffer: [u8; WIDTH as usize * HEIGHT as usize * 4],
     x: usize,
     y: usize,
-    start_of_frame: Instant,
+    pacer: FramePacer,
+    fast_forward: Rc<Cell<bool>>,
+    speed: Rc<Cell<f32>>,
     last_fps_log: Instant,
     frames_since_last_fps_log: u64,
+    palette: Rc<Palette>,
 }
 
 impl<'r> SDLDisplay<'r> {
-    fn new(texture_creator: &'r TextureCreator<WindowContext>, canvas: WindowCanvas) -> Self {
+    fn new(
+        texture_creator: &'r TextureCreator<WindowContext>,
+        canvas: WindowCanvas,
+        fast_forward: Rc<Cell<bool>>,
+        speed: Rc<Cell<f32>>,
+    ) -> Self {
         let texture = texture_creator
             .create_texture_streaming(None, WIDTH as u32, HEIGHT as u32)
             .unwrap();
@@ -156,18 +366,31 @@ impl<'r> SDLDisplay<'r> {
             buffer: [0; WIDTH as usize * HEIGHT as usize * 4],
             x: 0,
             y: 0,
-            start_of_frame: now,
+            pacer: FramePacer::new(FRAME_DURATION, now),
+            fast_forward,
+            speed,
             last_fps_log: now,
             frames_since_last_fps_log: 0,
+            palette: Rc::default(),
         }
     }
+
+    fn to_png(&self) -> Vec<u8> {
+        // The buffer is in the texture's BGR order
+        let rgba: Vec<u8> = self
+            .buffer
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], 0xFF])
+            .collect();
+        crate::encode_png(&rgba)
+    }
 }
 
 impl<'r> NESDisplay for SDLDisplay<'r> {
     fn draw_pixel(&mut self, color: Color) {
         let offset = (self.y * WIDTH as usize + self.x) * 4;
         if offset + 2 < self.buffer.len() {
-            let (r, g, b) = color.to_rgb();
+            let (r, g, b) = self.palette.rgb(color);
             self.buffer[offset] = b;
             self.buffer[offset + 1] = g;
             self.buffer[offset + 2] = r;
@@ -181,47 +404,57 @@ impl<'r> NESDisplay for SDLDisplay<'r> {
         }
         if self.y == usize::from(HEIGHT) {
             self.y = 0;
-            self.texture
-                .update(None, &self.buffer, WIDTH as usize * 4)
-                .unwrap();
-            self.canvas.copy(&self.texture, None, None).unwrap();
-            self.canvas.present();
-
-            let now = Instant::now();
-            let elapsed = now.duration_since(self.start_of_frame);
-            if let Some(time_to_sleep) = FRAME_DURATION.checked_sub(elapsed) {
-                std::thread::sleep(time_to_sleep);
-                self.start_of_frame = now + time_to_sleep;
-            } else {
-                // We're running behind, sleep less next time
-                self.start_of_frame = now - (elapsed - FRAME_DURATION);
-            }
+        }
+    }
 
-            self.frames_since_last_fps_log += 1;
+    fn enter_vblank(&mut self) {}
 
-            let now = Instant::now();
-            let elapsed_since_last_fps_log = now.duration_since(self.last_fps_log);
-            if elapsed_since_last_fps_log > Duration::from_secs(5) {
-                let fps = self.frames_since_last_fps_log as f64
-                    / elapsed_since_last_fps_log.as_secs_f64();
-                info!("FPS: {}", fps);
-                self.last_fps_log = now;
-                self.frames_since_last_fps_log = 0;
-            }
+    fn end_frame(&mut self) {
+        let fast_forward = self.fast_forward.get();
+        if !self.pacer.present(fast_forward) {
+            return;
+        }
+
+        self.texture
+            .update(None, &self.buffer, WIDTH as usize * 4)
+            .unwrap();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+
+        self.pacer.set_speed(self.speed.get());
+        if let Some(time_to_sleep) = self.pacer.sleep_time(Instant::now(), fast_forward) {
+            std::thread::sleep(time_to_sleep);
+        }
+
+        self.frames_since_last_fps_log += 1;
+
+        let now = Instant::now();
+        let elapsed_since_last_fps_log = now.duration_since(self.last_fps_log);
+        if elapsed_since_last_fps_log > Duration::from_secs(5) {
+            let fps =
+                self.frames_since_last_fps_log as f64 / elapsed_since_last_fps_log.as_secs_f64();
+            info!("FPS: {}", fps);
+            self.last_fps_log = now;
+            self.frames_since_last_fps_log = 0;
         }
     }
 
-    fn enter_vblank(&mut self) {}
+    fn set_palette(&mut self, palette: Rc<Palette>) {
+        self.palette = palette;
+    }
 }
 
 struct SDLSpeaker {
-    _device: AudioDevice<MyAudioCallback>,
-    buffer: AudioBuffer,
-    next_sample: f64,
+    _device: AudioDevice<SDLAudioCallback>,
+    // Samples are added to the shared queue in batches, to avoid locking it for every sample
+    batch: Vec<f32>,
+    batch_size: usize,
+    queue: Arc<Mutex<SampleQueue>>,
+    fast_forward: Rc<Cell<bool>>,
 }
 
 impl SDLSpeaker {
-    fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+    fn new(sdl_context: &sdl2::Sdl, fast_forward: Rc<Cell<bool>>) -> Result<Self, String> {
         let audio_subsystem = sdl_context.audio()?;
 
         let desired_spec = AudioSpecDesired {
@@ -230,76 +463,58 @@ impl SDLSpeaker {
             samples: None,
         };
 
-        let double_buffer = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(Mutex::new(SampleQueue::new(0)));
 
         let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-            let double_buffer = double_buffer.clone();
-            double_buffer
-                .lock()
-                .unwrap()
-                .resize(spec.samples as usize, 0.0);
-            MyAudioCallback(double_buffer)
+            // Allow a few device buffers of latency before dropping samples
+            *queue.lock().unwrap() = SampleQueue::new(spec.samples as usize * 4);
+            SDLAudioCallback(queue.clone())
         })?;
         device.resume();
 
         let sample_size = device.spec().samples;
         log::info!("Audio sample size: {}", sample_size);
 
-        let buffer = AudioBuffer::new(sample_size as usize, double_buffer);
-
         Ok(Self {
             _device: device,
-            buffer,
-            next_sample: 0.0,
+            batch: Vec::with_capacity(sample_size as usize),
+            batch_size: sample_size as usize,
+            queue,
+            fast_forward,
         })
     }
+
+    fn flush(&mut self) {
+        self.queue.lock().unwrap().extend(&self.batch);
+        self.batch.clear();
+    }
 }
 
 impl NESSpeaker for SDLSpeaker {
     fn emit(&mut self, value: f32) {
-        // Naive downsampling
-        if self.next_sample <= 0.0 {
-            self.buffer.push(value);
-            self.next_sample += NES_AUDIO_FREQ / TARGET_AUDIO_FREQ as f64;
+        // Samples are produced too fast to play while fast-forwarding, so they're dropped rather
+        // than overflowing the queue
+        if self.fast_forward.get() {
+            return;
         }
-        self.next_sample -= 1.0;
-    }
-}
 
-struct AudioBuffer {
-    size: usize,
-    buffer: Vec<f32>,
-    double_buffer: Arc<Mutex<Vec<f32>>>,
-}
-
-impl AudioBuffer {
-    fn new(size: usize, double_buffer: Arc<Mutex<Vec<f32>>>) -> Self {
-        let buffer = Vec::with_capacity(size);
-        Self {
-            size,
-            buffer,
-            double_buffer,
+        self.batch.push(value);
+        if self.batch.len() >= self.batch_size {
+            self.flush();
         }
     }
 
-    fn push(&mut self, value: f32) {
-        self.buffer.push(value);
-        if self.buffer.len() == self.size {
-            let mut double_buffer = self.double_buffer.lock().unwrap();
-            double_buffer.copy_from_slice(&self.buffer);
-            self.buffer.clear();
-        }
+    fn end_frame(&mut self) {
+        self.flush();
     }
 }
 
-struct MyAudioCallback(Arc<Mutex<Vec<f32>>>);
+struct SDLAudioCallback(Arc<Mutex<SampleQueue>>);
 
-impl AudioCallback for MyAudioCallback {
+impl AudioCallback for SDLAudioCallback {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        let buffer = self.0.lock().unwrap();
-        debug_assert_eq!(buffer.len(), out.len());
-        out.copy_from_slice(&buffer);
+        self.0.lock().unwrap().drain_into(out);
     }
 }
@@ -1,5 +1,5 @@
 #![allow(dead_code)] // Might be disabled by features
-use crate::{runtime::Runtime, BufferDisplay, Buttons, INes, NESSpeaker, HEIGHT, NES, WIDTH};
+use crate::{runtime::Runtime, BufferDisplay, Buttons, Cartridge, NESSpeaker, HEIGHT, NES, WIDTH};
 use anyhow::{anyhow, Context};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use std::{
@@ -17,7 +17,7 @@ use web_sys::{
 };
 use zip::ZipArchive;
 
-use super::{FRAME_DURATION, NES_AUDIO_FREQ, TARGET_AUDIO_FREQ};
+use super::{FRAME_DURATION, TARGET_AUDIO_FREQ};
 
 const DEFAULT_ROM: &[u8] = include_bytes!("../../roms/AlwasAwakening_demo.nes");
 
@@ -177,8 +177,8 @@ impl Runtime for Web {
                 return Ok(());
             }
 
-            let mut ctx = ctx.borrow_mut();
-            let ctx = match &mut *ctx {
+            let mut ctx_slot = ctx.borrow_mut();
+            let ctx = match &mut *ctx_slot {
                 Some(ctx) => ctx,
                 None => return Ok(()),
             };
@@ -188,13 +188,17 @@ impl Runtime for Web {
             save_state(ctx.rom_hash, nes)?;
 
             for _ in 0..needed_frames {
-                // Run NES until frame starts
-                while nes.display().vblank() {
-                    nes.tick();
-                }
-                // Run NES until frame ends
-                while !nes.display().vblank() {
-                    nes.tick();
+                loop {
+                    match nes.try_tick() {
+                        Ok(result) if result.frame_complete => break,
+                        Ok(_) => {}
+                        Err(err) => {
+                            // Halt, leaving the last frame on screen until another ROM is loaded
+                            log::error!("Halting: {}", err);
+                            ctx_slot.take();
+                            return Ok(());
+                        }
+                    }
                 }
             }
             num_frames = expected_frames;
@@ -341,16 +345,16 @@ fn upload_rom(ctx: Rc<RefCell<Option<NesContext>>>, files: impl Iterator<Item =
 }
 
 fn set_rom(rom: &[u8]) -> Result<NesContext, Box<dyn Error>> {
-    let ines = INes::read(rom)?;
-    let cartridge = ines.into_cartridge();
+    let cartridge = Cartridge::from_bytes(rom)?;
     let display = BufferDisplay::default();
-    let speaker = WebSpeaker::default();
+    let speaker = WebSpeaker;
 
     let mut rom_hasher = DefaultHasher::new();
     rom.hash(&mut rom_hasher);
     let rom_hash = rom_hasher.finish();
 
     let mut nes = NES::new(cartridge, display, speaker);
+    nes.set_sample_rate(TARGET_AUDIO_FREQ as u32);
     load_state(rom_hash, &mut nes)?;
 
     Ok(NesContext { nes, rom_hash })
@@ -360,7 +364,7 @@ fn save_state<D, S>(rom_hash: u64, nes: &mut NES<D, S>) -> Result<(), Box<dyn Er
     let ram = nes.cpu.memory().prg().ram();
 
     let key = state_key(rom_hash);
-    let value = BASE64_STANDARD.encode(ram);
+    let value = BASE64_STANDARD.encode(&*ram);
     local_storage()?
         .set_item(&key, &value)
         .map_err(|_| anyhow!("Failed to save state to local storage"))?;
@@ -415,19 +419,11 @@ fn state_key(rom_hash: u64) -> String {
     format!("nes-state-{}", hash_base64)
 }
 
-#[derive(Default)]
-struct WebSpeaker {
-    next_sample: f64,
-}
+struct WebSpeaker;
 
 impl NESSpeaker for WebSpeaker {
     fn emit(&mut self, value: f32) {
-        // Naive downsampling
-        if self.next_sample <= 0.0 {
-            push_audio_buffer(value);
-            self.next_sample += NES_AUDIO_FREQ / TARGET_AUDIO_FREQ as f64;
-        }
-        self.next_sample -= 1.0;
+        push_audio_buffer(value);
     }
 }
 
@@ -1,4 +1,5 @@
 use crate::cpu::Instruction;
+use crate::Address;
 
 pub trait SerializeByte {
     fn to_byte(self) -> u8;
@@ -15,3 +16,21 @@ impl SerializeByte for Instruction {
         self.to_opcode()
     }
 }
+
+/// Values written to memory by the [mem!](crate::mem) macro, which may be more than one byte.
+pub trait SerializeBytes {
+    fn to_bytes(self) -> Vec<u8>;
+}
+
+impl<T: SerializeByte> SerializeBytes for T {
+    fn to_bytes(self) -> Vec<u8> {
+        vec![self.to_byte()]
+    }
+}
+
+/// Addresses are written little-endian, lower byte first, like the 6502 expects.
+impl SerializeBytes for Address {
+    fn to_bytes(self) -> Vec<u8> {
+        vec![self.lower(), self.higher()]
+    }
+}
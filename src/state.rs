@@ -0,0 +1,163 @@
+//! Saving emulator state with serde, in bincode's compact binary format.
+//!
+//! Values are written in order with no field names or type information, so state can only be read
+//! back into the same types that wrote it. Integers are little-endian, and sequences are prefixed
+//! with their length.
+use std::fmt::{self, Display, Formatter};
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Error from saving or loading emulator state.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StateError(String);
+
+impl StateError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        StateError(message.into())
+    }
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid state: {}", self.0)
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<bincode::Error> for StateError {
+    fn from(error: bincode::Error) -> Self {
+        StateError(error.to_string())
+    }
+}
+
+// Fixed-size integers keep the format simple, and trailing bytes mean the state is for other types
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+}
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, StateError> {
+    Ok(options().serialize(value)?)
+}
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, StateError> {
+    Ok(options().deserialize(bytes)?)
+}
+
+/// Serde only supports arrays up to 32 elements, use with `#[serde(with = "crate::state::array")]`
+/// for larger arrays such as RAM.
+pub mod array {
+    use std::marker::PhantomData;
+
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(
+        array: &[T; N],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for element in array {
+            tuple.serialize_element(element)?;
+        }
+        tuple.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[T; N], D::Error> {
+        deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+    }
+
+    struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>, const N: usize> Visitor<'de> for ArrayVisitor<T, N> {
+        type Value = [T; N];
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(formatter, "an array of length {}", N)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut elements = Vec::with_capacity(N);
+            while let Some(element) = seq.next_element()? {
+                elements.push(element);
+            }
+            let len = elements.len();
+            elements
+                .try_into()
+                .map_err(|_| de::Error::invalid_length(len, &self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(u16),
+        Rectangle { width: u8, height: u8 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Example {
+        flag: bool,
+        number: i32,
+        name: String,
+        shapes: Vec<Shape>,
+        maybe: Option<f32>,
+        #[serde(with = "array")]
+        ram: [u8; 100],
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let example = Example {
+            flag: true,
+            number: -1234,
+            name: "NES".to_string(),
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(500),
+                Shape::Rectangle {
+                    width: 3,
+                    height: 4,
+                },
+            ],
+            maybe: Some(0.5),
+            ram: std::array::from_fn(|i| i as u8),
+        };
+
+        let bytes = to_bytes(&example).unwrap();
+
+        assert_eq!(from_bytes::<Example>(&bytes), Ok(example));
+    }
+
+    #[test]
+    fn integers_are_little_endian() {
+        assert_eq!(to_bytes(&0x1234u16).unwrap(), [0x34, 0x12]);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let bytes = to_bytes(&0x12345678u32).unwrap();
+
+        assert!(from_bytes::<u32>(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn trailing_input_is_an_error() {
+        let bytes = to_bytes(&0x12345678u32).unwrap();
+
+        assert!(from_bytes::<u16>(&bytes).is_err());
+    }
+}
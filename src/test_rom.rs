@@ -0,0 +1,116 @@
+//! Detects results from test ROMs, such as blargg's test suites.
+//!
+//! Test ROMs report their status in PRG RAM:
+//! - $6000: status, $80 while running, $81 if the ROM needs a reset, otherwise the result code.
+//! - $6001-$6003: the signature $DE $B0 $61, which shows the status is valid.
+//! - $6004+: a null-terminated ASCII message describing the result.
+use crate::{Address, NESDisplay, NESSpeaker, NES};
+
+const STATUS: Address = Address::new(0x6000);
+const SIGNATURE: Address = Address::new(0x6001);
+const MESSAGE: Address = Address::new(0x6004);
+const MESSAGE_END: Address = Address::new(0x7fff);
+
+const EXPECTED_SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+const STATUS_PASSED: u8 = 0x00;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TestStatus {
+    Running,
+    Passed,
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct TestRomMonitor;
+
+impl TestRomMonitor {
+    pub fn poll<D: NESDisplay, S: NESSpeaker>(&mut self, nes: &mut NES<D, S>) -> TestStatus {
+        let signature = [
+            nes.read_cpu(SIGNATURE),
+            nes.read_cpu(SIGNATURE + 1),
+            nes.read_cpu(SIGNATURE + 2),
+        ];
+
+        if signature != EXPECTED_SIGNATURE {
+            return TestStatus::Running;
+        }
+
+        match nes.read_cpu(STATUS) {
+            // TODO: support resetting the NES when requested
+            STATUS_RUNNING | STATUS_RESET => TestStatus::Running,
+            STATUS_PASSED => TestStatus::Passed,
+            code => {
+                let message = Self::message(nes);
+                TestStatus::Failed(format!("Failed with code {:#04x}: {}", code, message))
+            }
+        }
+    }
+
+    fn message<D: NESDisplay, S: NESSpeaker>(nes: &mut NES<D, S>) -> String {
+        let mut message = vec![];
+        let mut address = MESSAGE;
+        while address <= MESSAGE_END {
+            let byte = nes.read_cpu(address);
+            if byte == 0 {
+                break;
+            }
+            message.push(byte);
+            address += 1;
+        }
+        String::from_utf8_lossy(&message).trim().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::looping_nes;
+
+    use super::*;
+
+    #[test]
+    fn test_rom_is_running_until_signature_is_written() {
+        let mut nes = looping_nes();
+        let mut monitor = TestRomMonitor;
+
+        write_ram(&mut nes, STATUS, &[STATUS_PASSED]);
+        assert_eq!(monitor.poll(&mut nes), TestStatus::Running);
+
+        write_ram(&mut nes, SIGNATURE, &EXPECTED_SIGNATURE);
+        write_ram(&mut nes, STATUS, &[STATUS_RUNNING]);
+        assert_eq!(monitor.poll(&mut nes), TestStatus::Running);
+    }
+
+    #[test]
+    fn test_rom_passes_when_status_is_zero() {
+        let mut nes = looping_nes();
+        let mut monitor = TestRomMonitor;
+
+        write_ram(&mut nes, SIGNATURE, &EXPECTED_SIGNATURE);
+        write_ram(&mut nes, STATUS, &[STATUS_PASSED]);
+        assert_eq!(monitor.poll(&mut nes), TestStatus::Passed);
+    }
+
+    #[test]
+    fn test_rom_fails_with_message_when_status_is_non_zero() {
+        let mut nes = looping_nes();
+        let mut monitor = TestRomMonitor;
+
+        write_ram(&mut nes, SIGNATURE, &EXPECTED_SIGNATURE);
+        write_ram(&mut nes, MESSAGE, b"\nSprite hit too late\n\0");
+        write_ram(&mut nes, STATUS, &[0x03]);
+        assert_eq!(
+            monitor.poll(&mut nes),
+            TestStatus::Failed("Failed with code 0x03: Sprite hit too late".to_owned())
+        );
+    }
+
+    fn write_ram(nes: &mut NES<(), ()>, address: Address, bytes: &[u8]) {
+        let offset = address.index() - STATUS.index();
+        let mut ram = nes.cpu.memory().prg().ram();
+        ram[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
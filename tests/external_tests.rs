@@ -65,11 +65,10 @@ enum Success {
         "vbl_basics", include_bytes!("ppu_vbl_nmi/rom_singles/01-vbl_basics.nes"),
         Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
     },
-    // TODO
-    // vbl_set_time = {
-    //     "vbl_set_time", include_bytes!("ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes"),
-    //     Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
-    // },
+    vbl_set_time = {
+        "vbl_set_time", include_bytes!("ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes"),
+        Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
+    },
     vbl_clear_time = {
         "vbl_clear_time", include_bytes!("ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes"),
         Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
@@ -84,21 +83,19 @@ enum Success {
     //     "nmi_timing", include_bytes!("ppu_vbl_nmi/rom_singles/05-nmi_timing.nes"),
     //     Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
     // },
-    // TODO
-    // suppression = {
-    //     "suppression", include_bytes!("ppu_vbl_nmi/rom_singles/06-suppression.nes"),
-    //     Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
-    // },
+    suppression = {
+        "suppression", include_bytes!("ppu_vbl_nmi/rom_singles/06-suppression.nes"),
+        Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
+    },
     // TODO
     // nmi_on_timing = {
     //     "nmi_on_timing", include_bytes!("ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes"),
     //     Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
     // },
-    // TODO
-    // nmi_off_timing = {
-    //     "nmi_off_timing", include_bytes!("ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes"),
-    //     Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
-    // },
+    nmi_off_timing = {
+        "nmi_off_timing", include_bytes!("ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes"),
+        Setup::Default, Terminate::Address(0xe8d5), Success::Byte(0x6000, 0x00)
+    },
     // TODO
     // even_odd_frames = {
     //     "even_odd_frames", include_bytes!("ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes"),
@@ -222,6 +219,22 @@ fn external_test(
     );
 }
 
+#[test]
+fn running_frames_is_deterministic() {
+    let run = || {
+        let rom = include_bytes!("sprite_hit_tests/01.basics.nes");
+        let cartridge = INes::read(Cursor::new(rom)).unwrap().into_cartridge();
+        let mut nes = NES::new(cartridge, BufferDisplay::default(), ());
+        for frame in 1..=60 {
+            assert_eq!(nes.run_frame(), frame);
+        }
+        (nes.instruction_count(), nes.display().buffer().to_vec())
+    };
+
+    let (instructions, buffer) = run();
+    assert_eq!(run(), (instructions, buffer));
+}
+
 fn get_result(success_check: Success, nes: &mut NES<BufferDisplay, ()>) -> Result<(), String> {
     match success_check {
         Success::Never => Err("Always fails".to_owned()),
@@ -48,6 +48,20 @@ fn run<M: Memory>(cpu: &mut CPU<M>, params: &[u8], expected: &[u8]) {
     assert_eq!(result, expected);
 }
 
+#[test]
+fn cpu_runs_standalone_on_array_memory() {
+    let memory = mem!(
+        0x8000 => { LDA_IMM, 0x10u8, CLC, ADC_IMM, 0x22u8, STA_ZPA, RETURN_ADDRESS, BRK }
+        0xFFFC => { 0x00u8, 0x80u8 }
+    );
+    let mut cpu = CPU::from_memory(memory);
+
+    let instructions = cpu.run_until(100, |cpu| cpu.read(Address::new(0x00B0)) != 0);
+
+    assert_eq!(instructions, 4);
+    assert_eq!(cpu.registers().accumulator, 0x32);
+}
+
 #[test]
 fn hello_world() {
     run!(b"Felix\0" -> b"hello world from Felix!\0";